@@ -4,4 +4,8 @@ fn main() {
     // Rerun on WITX spec changes
     println!("cargo:rerun-if-changed=../spec/*.witx");
 
+    // Export the spec root, used to check bindings against the spec WITX
+    if let Ok(root) = std::env::var("DEP_EMBEDDED_WASM_SPEC_ROOT") {
+        println!("cargo:rustc-env=WASM_EMBEDDED_SPEC_ROOT={}", root);
+    }
 }
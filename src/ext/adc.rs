@@ -0,0 +1,24 @@
+//! ADC (analog input) peripheral interface
+
+use wasm_embedded_spec::Error;
+
+/// ADC driver
+///
+/// Channels are sampled either individually, or captured into a buffer
+/// of raw samples driven by a hardware or software trigger.
+pub trait Adc {
+    /// Initialise an ADC channel, returning a handle
+    fn init(&mut self, device: u32, channel: u32) -> Result<i32, Error>;
+
+    /// Deinitialise an ADC channel
+    fn deinit(&mut self, handle: i32) -> Result<(), Error>;
+
+    /// Read a raw sample
+    fn read_raw(&mut self, handle: i32) -> Result<i32, Error>;
+
+    /// Read a sample converted to millivolts
+    fn read(&mut self, handle: i32) -> Result<f32, Error>;
+
+    /// Capture raw samples, filling the provided buffer using the specified trigger
+    fn capture(&mut self, handle: i32, trigger: u32, samples: &mut [i32]) -> Result<(), Error>;
+}
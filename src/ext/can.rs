@@ -0,0 +1,124 @@
+//! CAN bus peripheral interface
+
+use wasm_embedded_spec::Error;
+
+/// Maximum standard (11-bit) frame identifier
+pub const CAN_SFF_MAX: u32 = 0x7FF;
+
+/// Maximum extended (29-bit) frame identifier
+pub const CAN_EFF_MAX: u32 = 0x1FFF_FFFF;
+
+/// Maximum classic CAN payload length
+pub const CAN_MAX_LEN: usize = 8;
+
+/// Maximum CAN-FD payload length
+pub const CANFD_MAX_LEN: usize = 64;
+
+/// CAN frame
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Frame {
+    /// Frame identifier
+    pub id: u32,
+    /// Extended (29-bit) identifier
+    pub extended: bool,
+    /// Remote transmission request
+    pub remote: bool,
+    /// CAN-FD frame
+    pub fd: bool,
+    len: u8,
+    data: [u8; CANFD_MAX_LEN],
+}
+
+impl Frame {
+    /// Create a data frame, returning `None` where the identifier or
+    /// payload length is invalid for the frame type
+    pub fn new(id: u32, extended: bool, fd: bool, data: &[u8]) -> Option<Self> {
+        let mut f = Self{ id, extended, remote: false, fd, len: 0, data: [0u8; CANFD_MAX_LEN] };
+
+        if !f.valid_id() || !valid_len(fd, data.len()) {
+            return None;
+        }
+
+        f.len = data.len() as u8;
+        f.data[..data.len()].copy_from_slice(data);
+
+        Some(f)
+    }
+
+    /// Create a remote transmission request frame, with the requested data length
+    pub fn remote(id: u32, extended: bool, len: usize) -> Option<Self> {
+        let f = Self{ id, extended, remote: true, fd: false, len: len as u8, data: [0u8; CANFD_MAX_LEN] };
+
+        match f.valid_id() && len <= CAN_MAX_LEN {
+            true => Some(f),
+            false => None,
+        }
+    }
+
+    /// Frame payload (empty for remote frames)
+    pub fn data(&self) -> &[u8] {
+        match self.remote {
+            true => &[],
+            false => &self.data[..self.len as usize],
+        }
+    }
+
+    /// Frame data length, including the requested length for remote frames
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Check whether the frame carries no data
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn valid_id(&self) -> bool {
+        match self.extended {
+            true => self.id <= CAN_EFF_MAX,
+            false => self.id <= CAN_SFF_MAX,
+        }
+    }
+}
+
+/// Check a payload length is valid, CAN-FD frames above 8 bytes are
+/// limited to the discrete DLC lengths
+fn valid_len(fd: bool, len: usize) -> bool {
+    matches!((fd, len), (_, 0..=CAN_MAX_LEN) | (true, 12 | 16 | 20 | 24 | 32 | 48 | 64))
+}
+
+/// CAN receive filter
+///
+/// Frames are accepted where `frame.id & mask == id & mask` and the
+/// identifier type matches the filter.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature="serde", serde(rename_all="snake_case"))]
+pub struct Filter {
+    pub id: u32,
+    pub mask: u32,
+    #[cfg_attr(feature="serde", serde(default))]
+    pub extended: bool,
+}
+
+/// CAN driver
+pub trait Can {
+    /// Initialise a CAN interface, returning a handle. Where `fd` is set
+    /// CAN-FD frames may be sent and received, failing with
+    /// [`Error::Unsupported`] if the interface does not support CAN-FD.
+    fn init(&mut self, port: u32, fd: bool) -> Result<i32, Error>;
+
+    /// Deinitialise a CAN interface
+    fn deinit(&mut self, handle: i32) -> Result<(), Error>;
+
+    /// Replace receive filters, receiving frames matching any of the
+    /// provided filters (or all frames where empty)
+    fn set_filters(&mut self, handle: i32, filters: &[Filter]) -> Result<(), Error>;
+
+    /// Send a frame
+    fn send(&mut self, handle: i32, frame: &Frame) -> Result<(), Error>;
+
+    /// Receive a frame, blocking for up to `timeout_ms` (or indefinitely
+    /// where unset) and returning `None` on timeout
+    fn receive(&mut self, handle: i32, timeout_ms: Option<u32>) -> Result<Option<Frame>, Error>;
+}
@@ -0,0 +1,27 @@
+//! Guest logging interface
+
+use wasm_embedded_spec::Error;
+
+pub use log::Level;
+
+/// Guest log driver
+pub trait Logger {
+    /// Write a log message at the provided level
+    ///
+    /// Messages may be discarded by level filters or rate limiting, which
+    /// is not reported to the guest.
+    fn write(&mut self, level: Level, message: &str) -> Result<(), Error>;
+}
+
+/// Convert a guest log level (1 for error through 5 for trace, matching
+/// [`log::Level`]) for bindings
+pub fn level(raw: u32) -> Result<Level, Error> {
+    match raw {
+        1 => Ok(Level::Error),
+        2 => Ok(Level::Warn),
+        3 => Ok(Level::Info),
+        4 => Ok(Level::Debug),
+        5 => Ok(Level::Trace),
+        _ => Err(Error::InvalidArg),
+    }
+}
@@ -0,0 +1,81 @@
+//! Extended peripheral interfaces
+//!
+//! These extend the embedded-wasm spec [`Engine`] with peripherals not yet
+//! covered by the spec, following the same handle-based conventions as the
+//! spec drivers. These are bound to guest imports under the wasmtime
+//! runtime (see `rt_wasmtime`), wasm3 does not yet bind these and rejects
+//! guests importing [`MODULES`] prior to loading.
+
+use wasm_embedded_spec::Engine;
+
+mod pwm;
+pub use pwm::{Pwm, Polarity};
+
+mod adc;
+pub use adc::Adc;
+
+mod can;
+pub use can::{Can, Frame, Filter, CAN_SFF_MAX, CAN_EFF_MAX, CAN_MAX_LEN, CANFD_MAX_LEN};
+
+mod onewire;
+pub use onewire::{OneWire, OneWireBits, Rom, SEARCH_ROM, crc8, search_rom};
+
+mod time;
+pub use time::Time;
+#[cfg(feature="std")]
+pub(crate) use time::Timers;
+
+mod watchdog;
+pub use watchdog::Watchdog;
+
+mod storage;
+pub use storage::{Storage, KEY_MAX_LEN, valid_key};
+
+mod logger;
+pub use logger::{Logger, Level, level};
+
+/// Guest import modules for extended peripherals
+pub const MODULES: &[&str] = &["pwm", "adc", "can", "onewire", "time", "watchdog", "storage", "log"];
+
+/// Engine providing extended peripherals
+pub trait EngineExt: Engine {
+    type Pwm: Pwm;
+
+    type Adc: Adc;
+
+    type Can: Can;
+
+    type OneWire: OneWire;
+
+    type Time: Time;
+
+    type Watchdog: Watchdog;
+
+    type Storage: Storage;
+
+    type Logger: Logger;
+
+    /// Fetch the PWM driver, if available
+    fn pwm(&mut self) -> Option<&mut Self::Pwm>;
+
+    /// Fetch the ADC driver, if available
+    fn adc(&mut self) -> Option<&mut Self::Adc>;
+
+    /// Fetch the CAN driver, if available
+    fn can(&mut self) -> Option<&mut Self::Can>;
+
+    /// Fetch the 1-Wire driver, if available
+    fn onewire(&mut self) -> Option<&mut Self::OneWire>;
+
+    /// Fetch the time driver, if available
+    fn time(&mut self) -> Option<&mut Self::Time>;
+
+    /// Fetch the watchdog driver, if available
+    fn watchdog(&mut self) -> Option<&mut Self::Watchdog>;
+
+    /// Fetch the storage driver, if available
+    fn storage(&mut self) -> Option<&mut Self::Storage>;
+
+    /// Fetch the guest logger, if available
+    fn logger(&mut self) -> Option<&mut Self::Logger>;
+}
@@ -0,0 +1,153 @@
+//! 1-Wire bus peripheral interface
+
+use log::error;
+
+use wasm_embedded_spec::Error;
+
+/// 1-Wire device ROM code, in bus order (family code first, CRC last)
+pub type Rom = [u8; 8];
+
+/// ROM search command
+pub const SEARCH_ROM: u8 = 0xF0;
+
+/// 1-Wire driver
+pub trait OneWire {
+    /// Initialise a 1-Wire bus, returning a handle
+    ///
+    /// Where `pin` is negative this uses bus master `bus`, otherwise the
+    /// bus is bit-banged on the provided GPIO pin.
+    fn init(&mut self, bus: u32, pin: i32) -> Result<i32, Error>;
+
+    /// Deinitialise a 1-Wire bus
+    fn deinit(&mut self, handle: i32) -> Result<(), Error>;
+
+    /// Issue a bus reset, returning whether any device signalled presence
+    ///
+    /// Kernel bus masters do not report the presence pulse, so for these
+    /// this instead reports whether the kernel's last automatic search
+    /// found any devices (`w1_master_slave_count > 0`). Devices attached
+    /// since that search may not yet be reported.
+    fn reset(&mut self, handle: i32) -> Result<bool, Error>;
+
+    /// Search for devices on the bus, filling the provided buffer with
+    /// ROM codes and returning the number of devices found (up to the
+    /// buffer length)
+    fn search(&mut self, handle: i32, roms: &mut [Rom]) -> Result<usize, Error>;
+
+    /// Write a byte to the bus
+    fn write_byte(&mut self, handle: i32, data: u8) -> Result<(), Error>;
+
+    /// Read a byte from the bus
+    fn read_byte(&mut self, handle: i32) -> Result<u8, Error>;
+}
+
+/// Bit-level 1-Wire bus access, used to implement byte and ROM search
+/// operations for bit-banged buses
+pub trait OneWireBits {
+    /// Issue a bus reset, returning whether any device signalled presence
+    fn reset(&mut self) -> Result<bool, Error>;
+
+    /// Write a single bit (time slot) to the bus
+    fn write_bit(&mut self, bit: bool) -> Result<(), Error>;
+
+    /// Read a single bit (time slot) from the bus
+    fn read_bit(&mut self) -> Result<bool, Error>;
+
+    /// Write a byte to the bus, least significant bit first
+    fn write_byte(&mut self, data: u8) -> Result<(), Error> {
+        for i in 0..8 {
+            self.write_bit(data & (1 << i) != 0)?;
+        }
+        Ok(())
+    }
+
+    /// Read a byte from the bus, least significant bit first
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        let mut data = 0;
+        for i in 0..8 {
+            if self.read_bit()? {
+                data |= 1 << i;
+            }
+        }
+        Ok(data)
+    }
+}
+
+/// Enumerate devices using the ROM search algorithm (Maxim AN187),
+/// filling the provided buffer with ROM codes and returning the number
+/// of devices found
+pub fn search_rom(bus: &mut impl OneWireBits, roms: &mut [Rom]) -> Result<usize, Error> {
+    let mut rom = [0u8; 8];
+    let mut last_discrepancy = 0;
+    let mut n = 0;
+
+    while n < roms.len() {
+        if !bus.reset()? {
+            break;
+        }
+        bus.write_byte(SEARCH_ROM)?;
+
+        let mut last_zero = 0;
+        for i in 1..=64 {
+            let (byte, mask) = ((i - 1) / 8, 1 << ((i - 1) % 8));
+
+            let dir = match (bus.read_bit()?, bus.read_bit()?) {
+                // No devices responding
+                (true, true) => return Ok(n),
+                (b, c) if b != c => b,
+                // Discrepancy, devices with both bit values
+                _ => {
+                    let d = match i < last_discrepancy {
+                        true => rom[byte] & mask != 0,
+                        false => i == last_discrepancy,
+                    };
+                    if !d {
+                        last_zero = i;
+                    }
+                    d
+                },
+            };
+
+            match dir {
+                true => rom[byte] |= mask,
+                false => rom[byte] &= !mask,
+            }
+            bus.write_bit(dir)?;
+        }
+
+        if crc8(&rom) != 0 {
+            error!("1-Wire search CRC mismatch for ROM: {:02x?}", rom);
+            return Err(Error::Failed);
+        }
+
+        roms[n] = rom;
+        n += 1;
+
+        last_discrepancy = last_zero;
+        if last_discrepancy == 0 {
+            break;
+        }
+    }
+
+    Ok(n)
+}
+
+/// Compute the Maxim/Dallas 1-Wire CRC8, as used in ROM codes and
+/// device scratchpads (a valid ROM code has a CRC of zero over all bytes)
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+
+    for b in data {
+        let mut b = *b;
+        for _ in 0..8 {
+            let mix = (crc ^ b) & 0x01;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8C;
+            }
+            b >>= 1;
+        }
+    }
+
+    crc
+}
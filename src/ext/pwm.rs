@@ -0,0 +1,38 @@
+//! PWM peripheral interface
+
+use wasm_embedded_spec::Error;
+
+/// PWM output polarity
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature="serde", serde(rename_all="snake_case"))]
+pub enum Polarity {
+    /// Output high for the duty cycle
+    Normal,
+    /// Output low for the duty cycle
+    Inversed,
+}
+
+/// PWM driver
+///
+/// Periods and duty cycles are specified in nanoseconds, channels are
+/// disabled until explicitly enabled.
+pub trait Pwm {
+    /// Initialise a PWM channel, returning a handle
+    fn init(&mut self, chip: u32, channel: u32) -> Result<i32, Error>;
+
+    /// Disable and deinitialise a PWM channel
+    fn deinit(&mut self, handle: i32) -> Result<(), Error>;
+
+    /// Set the PWM period
+    fn set_period(&mut self, handle: i32, period_ns: u32) -> Result<(), Error>;
+
+    /// Set the PWM duty cycle (active time per period)
+    fn set_duty_cycle(&mut self, handle: i32, duty_ns: u32) -> Result<(), Error>;
+
+    /// Set the PWM output polarity
+    fn set_polarity(&mut self, handle: i32, polarity: Polarity) -> Result<(), Error>;
+
+    /// Enable or disable PWM output
+    fn enable(&mut self, handle: i32, enabled: bool) -> Result<(), Error>;
+}
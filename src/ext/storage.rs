@@ -0,0 +1,34 @@
+//! Persistent key-value storage interface
+
+use wasm_embedded_spec::Error;
+
+/// Maximum storage key length in bytes
+pub const KEY_MAX_LEN: usize = 64;
+
+/// Key-value storage driver
+///
+/// Stores are private to a guest and persist across restarts. Keys are
+/// limited to [`KEY_MAX_LEN`] ASCII alphanumerics, `_`, `-` and `.`
+/// (see [`valid_key`]), so these may be mapped directly to backing files.
+pub trait Storage {
+    /// Read a value into the provided buffer, returning the value length
+    /// or `None` if the key is unset
+    ///
+    /// Values longer than the buffer are truncated, so an empty buffer may
+    /// be used to query the value length.
+    fn get(&mut self, key: &str, buff: &mut [u8]) -> Result<Option<usize>, Error>;
+
+    /// Write a value, replacing any existing value atomically
+    fn set(&mut self, key: &str, value: &[u8]) -> Result<(), Error>;
+
+    /// Remove a value, returning whether the key was set
+    fn remove(&mut self, key: &str) -> Result<bool, Error>;
+}
+
+/// Check whether a storage key is valid
+pub fn valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= KEY_MAX_LEN
+        && !key.starts_with('.')
+        && key.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-' || c == b'.')
+}
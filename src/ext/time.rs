@@ -0,0 +1,141 @@
+//! Time services (delays, monotonic clock and periodic timers)
+
+use wasm_embedded_spec::Error;
+
+/// Time driver
+///
+/// Timestamps are monotonic microseconds from engine creation. Periodic
+/// timers count expirations, which guests either wait on directly or
+/// receive as callbacks, with runtimes waiting for expired timers via
+/// [`Time::timer_wait_any`] and [`Time::timer_poll`] and invoking the
+/// guest handler for each.
+pub trait Time {
+    /// Block for the provided number of microseconds
+    fn delay_us(&mut self, us: u32) -> Result<(), Error>;
+
+    /// Block for the provided number of milliseconds
+    fn delay_ms(&mut self, ms: u32) -> Result<(), Error>;
+
+    /// Read the monotonic clock in microseconds
+    fn now_us(&mut self) -> Result<u64, Error>;
+
+    /// Start a periodic timer, returning a handle
+    fn timer_start(&mut self, period_us: u32) -> Result<i32, Error>;
+
+    /// Stop a periodic timer
+    fn timer_stop(&mut self, handle: i32) -> Result<(), Error>;
+
+    /// Block until the timer next expires, returning the number of
+    /// expirations since the timer was last waited on or polled
+    fn timer_wait(&mut self, handle: i32) -> Result<u32, Error>;
+
+    /// Fetch a timer which has expired since last waited on or polled,
+    /// without blocking
+    fn timer_poll(&mut self) -> Result<Option<i32>, Error>;
+
+    /// Block until any timer expires or the timeout elapses (indefinitely
+    /// where unset), returning the expired timer or `None` on timeout
+    ///
+    /// This fails with [`Error::NoDevice`] where no timers are running
+    /// and no timeout is provided.
+    fn timer_wait_any(&mut self, timeout_us: Option<u32>) -> Result<Option<i32>, Error>;
+}
+
+/// Periodic timer state shared by time drivers, independent of the clock
+#[cfg(feature="std")]
+#[derive(Clone, PartialEq, Default, Debug)]
+pub(crate) struct Timers {
+    timers: std::collections::BTreeMap<i32, Timer>,
+}
+
+#[cfg(feature="std")]
+#[derive(Clone, PartialEq, Debug)]
+struct Timer {
+    period: u64,
+    next: u64,
+    pending: u32,
+}
+
+#[cfg(feature="std")]
+impl Timer {
+    /// Accumulate expirations up to the provided time
+    fn update(&mut self, now: u64) {
+        if now >= self.next {
+            let n = (now - self.next) / self.period + 1;
+            self.pending = self.pending.saturating_add(n as u32);
+            self.next += n * self.period;
+        }
+    }
+}
+
+#[cfg(feature="std")]
+impl Timers {
+    /// Add a timer with the provided handle, first expiring one period from now
+    pub(crate) fn insert(&mut self, handle: i32, now: u64, period_us: u32) -> Result<(), Error> {
+        if period_us == 0 {
+            return Err(Error::InvalidArg);
+        }
+
+        let period = period_us as u64;
+        self.timers.insert(handle, Timer{ period, next: now + period, pending: 0 });
+
+        Ok(())
+    }
+
+    /// Remove a timer, returning whether this existed
+    pub(crate) fn remove(&mut self, handle: i32) -> bool {
+        self.timers.remove(&handle).is_some()
+    }
+
+    /// Fetch the time at which a wait on the timer completes
+    pub(crate) fn deadline(&mut self, handle: i32, now: u64) -> Option<u64> {
+        let t = self.timers.get_mut(&handle)?;
+        t.update(now);
+
+        match t.pending {
+            0 => Some(t.next),
+            _ => Some(now),
+        }
+    }
+
+    /// Take pending expirations for a timer
+    pub(crate) fn take(&mut self, handle: i32, now: u64) -> Option<u32> {
+        let t = self.timers.get_mut(&handle)?;
+        t.update(now);
+
+        Some(core::mem::take(&mut t.pending))
+    }
+
+    /// Fetch the time at which a wait on any timer completes, waking at
+    /// `limit` where provided and earlier than any timer
+    pub(crate) fn wake(&mut self, now: u64, limit: Option<u64>) -> Option<u64> {
+        let next = self.timers.values_mut()
+            .map(|t| {
+                t.update(now);
+                match t.pending {
+                    0 => t.next,
+                    _ => now,
+                }
+            })
+            .min();
+
+        match (next, limit) {
+            (Some(n), Some(l)) => Some(n.min(l)),
+            (n, l) => n.or(l),
+        }
+    }
+
+    /// Take the first expired timer, if any
+    pub(crate) fn poll(&mut self, now: u64) -> Option<i32> {
+        for (h, t) in self.timers.iter_mut() {
+            t.update(now);
+
+            if t.pending > 0 {
+                t.pending = 0;
+                return Some(*h);
+            }
+        }
+
+        None
+    }
+}
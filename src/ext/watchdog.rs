@@ -0,0 +1,9 @@
+//! Watchdog peripheral interface
+
+use wasm_embedded_spec::Error;
+
+/// Watchdog driver, allowing guests to signal they are healthy
+pub trait Watchdog {
+    /// Kick (pet) the watchdog
+    fn kick(&mut self) -> Result<(), Error>;
+}
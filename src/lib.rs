@@ -11,6 +11,33 @@ pub use wasm_embedded_spec::{
 
 pub mod opts;
 
+pub mod ext;
+pub use ext::EngineExt;
+
+#[cfg(feature="std")]
+pub mod limits;
+
+#[cfg(feature="std")]
+pub mod module;
+
+#[cfg(feature="std")]
+pub mod server;
+
+#[cfg(feature="std")]
+pub use server::{Server, StopToken};
+
+#[cfg(feature="std")]
+pub mod watchdog;
+
+#[cfg(feature="std")]
+pub mod logging;
+
+#[cfg(feature="trace")]
+pub mod trace;
+
+#[cfg(feature="supervisor")]
+pub mod supervisor;
+
 #[cfg(feature="hal-mock")]
 pub mod mock;
 
@@ -21,44 +48,101 @@ pub mod linux;
 pub use wasm_embedded_rt_wasm3::{self as rt_wasm3};
 
 #[cfg(feature="rt-wasmtime")]
-pub use wasm_embedded_rt_wasmtime::{self as rt_wasmtime};
+pub mod rt_wasmtime;
 
-/// WASM server
-pub struct Server<E: Engine> {
-    config: opts::Config,
-    runtime: RuntimeCtx<E>,
+/// Execute a WASM binary against the provided engine using the selected runtime
+///
+/// This provides a common execution path across runtimes, so a given
+/// engine is bound and driven identically under each.
+#[cfg(feature="std")]
+pub fn run<E: EngineExt + Send + 'static>(runtime: &opts::Runtime, engine: E, bin: &[u8]) -> anyhow::Result<()> {
+    exec(runtime, engine, bin, &module::Guest::default())
 }
 
-impl <E: Engine> Server<E> {
-    /// Create new server instance
-    pub fn new(config: opts::Config) -> Self {
-        Self {
-            config,
-            runtime: RuntimeCtx::None,
-        }
-    }
+/// Execute a WASM binary with the provided guest arguments, environment
+/// and preopened directories
+#[cfg(feature="std")]
+pub fn exec<E: EngineExt + Send + 'static>(runtime: &opts::Runtime, engine: E, bin: &[u8], guest: &module::Guest) -> anyhow::Result<()> {
+    run_limited(runtime, engine, bin, guest, &opts::Limits::default(), &StopToken::new())
+}
 
-    /// Load and execute a provided wasm binary
-    pub fn exec(&mut self, bin: &[u8]) -> Result<(), ()> {
-        Ok(())
-    }
+/// Execute a WASM binary with the provided execution limits, stopping the
+/// guest once the provided [`StopToken`] is stopped
+///
+/// Under wasmtime guests are interrupted preemptively, and the runtime has
+/// exited once this returns. Under wasm3 guests are stopped only by the
+/// engine (where bound to the [`StopToken`]) refusing driver access, so
+/// timeouts take effect on the next driver access, and fuel limits are
+/// rejected.
+///
+/// Under wasm3, which does not link WASI, arguments and environment are
+/// provided by replacing the guest WASI imports, with guests requiring
+/// other WASI functions (or preopened directories) rejected.
+#[cfg(feature="std")]
+#[allow(unused_mut, unused_variables)]
+pub fn run_limited<E: EngineExt + Send + 'static>(runtime: &opts::Runtime, mut engine: E, bin: &[u8], guest: &module::Guest, limits: &opts::Limits, stop: &StopToken) -> anyhow::Result<()> {
+    let _guard = limits.acquire()?;
+
+    #[allow(unreachable_patterns)]
+    match runtime {
+        #[cfg(feature="rt-wasmtime")]
+        opts::Runtime::Wasmtime => {
+            let mut rt = rt_wasmtime::WasmtimeRuntime::new(engine, bin, guest, limits)?
+                .with_stop(stop);
+            limits.timed(stop, || rt.run())?;
+        },
+        #[cfg(feature="rt-wasm3")]
+        opts::Runtime::Wasm3 => {
+            if limits.fuel.is_some() {
+                return Err(anyhow::anyhow!("Fuel limits are unsupported by the wasm3 runtime"));
+            }
+            if !guest.dirs.is_empty() {
+                return Err(anyhow::anyhow!("Preopened directories require WASI, unsupported by the wasm3 runtime"));
+            }
+
+            // Memory and table limits are applied by clamping the module,
+            // as wasm3 provides no resource limiter
+            let bin = limits.instrument(bin)?;
+
+            // WASI and extended peripherals are not linked under wasm3,
+            // nor are entrypoint results returned
+            let bin = guest.shim_args(&bin)?;
+            module::check_wasi(&bin)?;
+            module::check_ext(&bin)?;
+            module::check_exit(&bin)?;
 
-    /// Start the server listening for remote operations
-    pub fn serve(&mut self, port: u16) -> Result<(), ()> {
-        Ok(())
+            let mut rt = rt_wasm3::Wasm3Runtime::new(&mut engine, &bin)?;
+            limits.timed(stop, || rt.run().map_err(trapped))?;
+        },
+        _ => {
+            return Err(anyhow::anyhow!("Runtime was not built with {} enabled", runtime))
+        },
     }
-}
 
-/// Storage for runtime context
-enum RuntimeCtx<E: Engine> {
-    /// No runtime loaded
-    None,
+    Ok(())
+}
 
-    /// WASM3 runtime loaded
-    #[cfg(feature="rt-wasm3")]
-    Wasm3(rt_wasm3::Wasm3Runtime),
+/// Validate a WASM binary for the selected runtime and limits, without
+/// executing it
+#[cfg(feature="std")]
+#[allow(unused_variables)]
+pub fn validate(runtime: &opts::Runtime, bin: &[u8], limits: &opts::Limits) -> anyhow::Result<()> {
+    #[allow(unreachable_patterns)]
+    match runtime {
+        #[cfg(feature="rt-wasmtime")]
+        opts::Runtime::Wasmtime => rt_wasmtime::validate(bin),
+        #[cfg(feature="rt-wasm3")]
+        opts::Runtime::Wasm3 => {
+            module::parse(bin)?;
+            limits.instrument(bin).map(|_| ())
+        },
+        _ => Err(anyhow::anyhow!("Runtime was not built with {} enabled", runtime)),
+    }
+}
 
-    /// Wasmtime runtime loaded
-    #[cfg(feature="rt-wasmtime")]
-    Wasmtime(rt_wasmtime::WasmtimeRuntime<E>),
+/// Report runtime errors arising during guest execution as traps
+#[cfg(feature="std")]
+#[allow(dead_code)]
+fn trapped(e: impl core::fmt::Display) -> anyhow::Error {
+    module::Trap{ reason: e.to_string(), backtrace: None }.into()
 }
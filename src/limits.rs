@@ -0,0 +1,225 @@
+//! Guest execution limits
+//!
+//! Fuel and timeouts are enforced by the wasmtime runtime, using native fuel
+//! metering and epoch interruption (via the instance [`StopToken`]), so
+//! guests are stopped within a bounded time and the runtime exits before
+//! execution returns. The wasm3 runtime provides no fuel metering, so fuel
+//! limits are rejected there, while timeouts stop the [`StopToken`] so
+//! engines bound to this refuse further driver access.
+//!
+//! Memory and table limits are enforced by the wasmtime resource limiter.
+//! Under wasm3, which provides no equivalent, the maximums of defined and
+//! imported memories and tables are instead clamped by rewriting the guest
+//! module prior to loading ([`Limits::instrument`]). In both cases guests
+//! requiring more than permitted fail to load with [`LimitExceeded`], and
+//! growth beyond the limits fails in the guest.
+
+use std::{time::Duration, vec::Vec};
+use std::sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}, mpsc::{channel, RecvTimeoutError}};
+
+use log::debug;
+use parity_wasm::elements::{self, Module, External, MemoryType, ResizableLimits, TableType};
+
+use crate::server::StopToken;
+
+pub use crate::opts::Limits;
+
+/// Execution limit exceeded errors
+#[derive(Clone, PartialEq, Debug, thiserror::Error)]
+pub enum LimitExceeded {
+    /// Guest exhausted its fuel budget
+    #[error("fuel limit exceeded ({0} units)")]
+    Fuel(u64),
+    /// Guest exceeded its wall-clock timeout
+    #[error("execution timeout exceeded ({0:?})")]
+    Timeout(Duration),
+    /// Guest requires more memory than permitted
+    #[error("memory limit exceeded (requested {requested} pages, limit {limit})")]
+    Memory{ requested: u32, limit: u32 },
+    /// Guest requires a larger table than permitted
+    #[error("table limit exceeded (requested {requested} elements, limit {limit})")]
+    Table{ requested: u32, limit: u32 },
+    /// Too many guest instances executing
+    #[error("instance limit exceeded (limit {0})")]
+    Instances(usize),
+}
+
+/// Count of executing guest instances, shared between clones
+///
+/// Counts are execution state rather than configuration, so these are
+/// ignored when comparing limits.
+#[derive(Clone, Default)]
+pub struct Instances(Arc<AtomicUsize>);
+
+impl Instances {
+    /// Fetch the number of executing instances
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl PartialEq for Instances {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl core::fmt::Debug for Instances {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("Instances").field(&self.count()).finish()
+    }
+}
+
+/// Guard for an executing guest instance, released on drop
+pub struct InstanceGuard(Instances);
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        (self.0).0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Limits {
+    /// Instrument a guest module to enforce the configured memory and
+    /// table limits, returning the module unmodified if these are unset
+    pub fn instrument(&self, bin: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if self.max_memory_pages.is_none() && self.max_table_elements.is_none() {
+            return Ok(bin.to_vec());
+        }
+
+        let m = crate::module::parse(bin)?;
+
+        // Parse any existing names so these are preserved
+        let mut m = m.parse_names().unwrap_or_else(|(_, m)| m);
+
+        if let Some(limit) = self.max_memory_pages {
+            limit_memory(&mut m, limit)?;
+        }
+
+        if let Some(limit) = self.max_table_elements {
+            limit_tables(&mut m, limit)?;
+        }
+
+        elements::serialize(m)
+            .map_err(|e| anyhow::anyhow!("Failed to serialise module: {:?}", e))
+    }
+
+    /// Acquire an instance slot, failing if the instance limit is reached
+    ///
+    /// Instances are counted per limit set, shared between its clones.
+    pub fn acquire(&self) -> Result<InstanceGuard, LimitExceeded> {
+        let n = self.instances.0.fetch_add(1, Ordering::SeqCst);
+        let guard = InstanceGuard(self.instances.clone());
+
+        match self.max_instances {
+            Some(limit) if n >= limit => Err(LimitExceeded::Instances(limit)),
+            _ => Ok(guard),
+        }
+    }
+
+    /// Execute `f`, stopping the instance via the provided [`StopToken`]
+    /// if this exceeds the configured timeout
+    ///
+    /// The timer is joined prior to returning, and failures following
+    /// expiry are reported as [`LimitExceeded::Timeout`].
+    pub fn timed<T>(&self, stop: &StopToken, f: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+        let t = match self.timeout {
+            Some(t) => t,
+            None => return f(),
+        };
+
+        let (tx, rx) = channel::<()>();
+        let expired = Arc::new(AtomicBool::new(false));
+
+        let timer = {
+            let (stop, expired) = (stop.clone(), expired.clone());
+            std::thread::spawn(move || {
+                // Completion disconnects the channel, cancelling the timer
+                if let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(t) {
+                    debug!("Execution timeout expired ({:?})", t);
+                    expired.store(true, Ordering::SeqCst);
+                    stop.stop();
+                }
+            })
+        };
+
+        let res = f();
+
+        drop(tx);
+        let _ = timer.join();
+
+        match res {
+            Err(_) if expired.load(Ordering::SeqCst) => Err(LimitExceeded::Timeout(t).into()),
+            r => r,
+        }
+    }
+}
+
+/// Clamp maximum memory sizes, rejecting memories exceeding the limit
+fn limit_memory(m: &mut Module, limit: u32) -> Result<(), LimitExceeded> {
+    let clamp = |e: &mut MemoryType| {
+        let (initial, max) = clamp(e.limits(), limit)
+            .map_err(|requested| LimitExceeded::Memory{ requested, limit })?;
+
+        debug!("Limiting memory to {} pages (requested: {:?})", max, e.limits().maximum());
+        *e = MemoryType::new(initial, Some(max));
+
+        Ok(())
+    };
+
+    for e in imports(m) {
+        if let External::Memory(t) = e {
+            clamp(t)?;
+        }
+    }
+
+    for e in m.memory_section_mut().map(|s| s.entries_mut().iter_mut()).into_iter().flatten() {
+        clamp(e)?;
+    }
+
+    Ok(())
+}
+
+/// Clamp maximum table sizes, rejecting tables exceeding the limit
+fn limit_tables(m: &mut Module, limit: u32) -> Result<(), LimitExceeded> {
+    let clamp = |e: &mut TableType| {
+        let (initial, max) = clamp(e.limits(), limit)
+            .map_err(|requested| LimitExceeded::Table{ requested, limit })?;
+
+        debug!("Limiting table to {} elements (requested: {:?})", max, e.limits().maximum());
+        *e = TableType::new(initial, Some(max));
+
+        Ok(())
+    };
+
+    for e in imports(m) {
+        if let External::Table(t) = e {
+            clamp(t)?;
+        }
+    }
+
+    for e in m.table_section_mut().map(|s| s.entries_mut().iter_mut()).into_iter().flatten() {
+        clamp(e)?;
+    }
+
+    Ok(())
+}
+
+/// Iterate over module imports
+fn imports(m: &mut Module) -> impl Iterator<Item = &mut External> {
+    m.import_section_mut()
+        .map(|s| s.entries_mut().iter_mut())
+        .into_iter()
+        .flatten()
+        .map(|e| e.external_mut())
+}
+
+/// Clamp resizable limits, returning the initial and maximum sizes or the
+/// initial size where this exceeds the limit
+fn clamp(l: &ResizableLimits, limit: u32) -> Result<(u32, u32), u32> {
+    if l.initial() > limit {
+        return Err(l.initial());
+    }
+
+    Ok((l.initial(), l.maximum().map(|v| v.min(limit)).unwrap_or(limit)))
+}
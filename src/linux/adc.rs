@@ -0,0 +1,389 @@
+//! Linux ADC driver implementation, using the IIO subsystem
+
+use std::{format, string::String, vec::Vec, collections::HashMap, path::PathBuf};
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use log::{debug, warn, error};
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::Adc;
+
+/// IIO device directory
+const IIO_ROOT: &str = "/sys/bus/iio/devices";
+
+/// Maximum interval between captured samples before a capture is
+/// abandoned, so a stalled trigger cannot block the guest indefinitely
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Opened IIO channel
+struct AdcChannel {
+    device: u32,
+    channel: u32,
+}
+
+impl AdcChannel {
+    /// Resolve the device sysfs directory
+    fn dir(&self) -> PathBuf {
+        PathBuf::from(IIO_ROOT).join(format!("iio:device{}", self.device))
+    }
+
+    /// Read a device attribute
+    fn read(&self, name: &str) -> Option<String> {
+        std::fs::read_to_string(self.dir().join(name)).ok().map(|s| s.trim().into())
+    }
+
+    /// Write a device attribute
+    fn write(&self, name: &str, value: &str) -> Result<(), Error> {
+        let p = self.dir().join(name);
+
+        if let Err(e) = std::fs::write(&p, value) {
+            error!("Failed to write IIO attribute {}: {:?}", p.display(), e);
+            return Err(Error::Failed);
+        }
+
+        Ok(())
+    }
+
+    /// Read a channel attribute, falling back to the shared attribute
+    /// (`in_voltage_{name}`) where no channel specific attribute exists
+    fn read_info(&self, name: &str) -> Option<f32> {
+        self.read(&format!("in_voltage{}_{}", self.channel, name))
+            .or_else(|| self.read(&format!("in_voltage_{}", name)))
+            .and_then(|v| v.parse().ok())
+    }
+}
+
+/// IIO scan element format, parsed from `in_voltageN_type`
+/// (`[be|le]:[s|u]bits/storagebits>>shift`)
+#[derive(Clone, PartialEq, Debug)]
+pub struct ScanType {
+    pub big_endian: bool,
+    pub signed: bool,
+    pub bits: u32,
+    pub storage: u32,
+    pub shift: u32,
+}
+
+impl ScanType {
+    /// Parse a scan element type, rejecting formats that cannot be
+    /// decoded into an `i32` sample
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        let t = Self::parse_fields(s).ok_or_else(|| {
+            error!("Invalid IIO scan element type: {}", s);
+            Error::InvalidArg
+        })?;
+
+        // Repeated elements (`X`) are not supported, and samples must
+        // fit within whole bytes of storage
+        if t.bits == 0 || t.bits > 32
+                || t.storage % 8 != 0 || !(8..=64).contains(&t.storage)
+                || t.bits + t.shift > t.storage {
+            error!("Unsupported IIO scan element type: {}", s);
+            return Err(Error::Unsupported);
+        }
+
+        Ok(t)
+    }
+
+    fn parse_fields(s: &str) -> Option<Self> {
+        let (endian, s) = s.trim().split_once(':')?;
+        let big_endian = match endian {
+            "be" => true,
+            "le" => false,
+            _ => return None,
+        };
+        let signed = match s.get(..1)? {
+            "s" => true,
+            "u" => false,
+            _ => return None,
+        };
+        let (bits, s) = s.get(1..)?.split_once('/')?;
+        let (storage, shift) = match s.split_once(">>") {
+            Some((st, sh)) => (st, sh),
+            None => (s, "0"),
+        };
+
+        Some(Self{
+            big_endian,
+            signed,
+            bits: bits.parse().ok()?,
+            storage: storage.parse().ok()?,
+            shift: shift.parse().ok()?,
+        })
+    }
+
+    /// Sample storage size in bytes
+    pub fn size(&self) -> usize {
+        (self.storage / 8) as usize
+    }
+
+    /// Decode a raw sample from its storage bytes
+    ///
+    /// Expects a type validated by [`ScanType::parse`], with `b` holding
+    /// [`ScanType::size`] bytes.
+    pub fn decode(&self, b: &[u8]) -> i32 {
+        let mut v: u64 = 0;
+        for i in 0..b.len() {
+            let byte = match self.big_endian {
+                true => b[i],
+                false => b[b.len() - 1 - i],
+            };
+            v = (v << 8) | byte as u64;
+        }
+
+        let v = (v >> self.shift) & ((1u64 << self.bits) - 1);
+
+        match self.signed && (v >> (self.bits - 1)) & 1 == 1 {
+            true => (v as i64 - (1i64 << self.bits)) as i32,
+            false => v as i32,
+        }
+    }
+}
+
+/// Buffered capture configuration, restoring the device trigger and
+/// enabled scan elements on drop so captures do not disturb other users
+struct Capture<'a> {
+    channel: &'a AdcChannel,
+    saved: Vec<(String, String)>,
+    enabled: bool,
+}
+
+impl <'a> Capture<'a> {
+    fn new(channel: &'a AdcChannel) -> Self {
+        Self{ channel, saved: Vec::new(), enabled: false }
+    }
+
+    /// Write an attribute, saving the current value for restoration
+    fn set(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        if let Some(v) = self.channel.read(name) {
+            if !self.saved.iter().any(|(n, _)| n == name) {
+                self.saved.push((name.into(), v));
+            }
+        }
+        self.channel.write(name, value)
+    }
+
+    /// Enable the device buffer
+    fn enable(&mut self, length: usize) -> Result<(), Error> {
+        self.set("buffer/length", &format!("{}", length))?;
+        self.channel.write("buffer/enable", "1")?;
+        self.enabled = true;
+        Ok(())
+    }
+}
+
+impl <'a> Drop for Capture<'a> {
+    fn drop(&mut self) {
+        // Buffers must be disabled before the trigger or elements change
+        if self.enabled {
+            if let Err(e) = self.channel.write("buffer/enable", "0") {
+                warn!("Failed to disable IIO buffer: {:?}", e);
+            }
+        }
+
+        for (n, v) in self.saved.iter().rev() {
+            if let Err(e) = self.channel.write(n, v) {
+                warn!("Failed to restore IIO attribute {}: {:?}", n, e);
+            }
+        }
+    }
+}
+
+/// Read exactly `buff.len()` bytes, failing where no data is available
+/// within [`CAPTURE_TIMEOUT`]
+fn read_timeout(f: &mut File, buff: &mut [u8]) -> std::io::Result<()> {
+    let mut n = 0;
+
+    while n < buff.len() {
+        let mut p = libc::pollfd{ fd: f.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        match unsafe { libc::poll(&mut p, 1, CAPTURE_TIMEOUT.as_millis() as libc::c_int) } {
+            0 => return Err(std::io::ErrorKind::TimedOut.into()),
+            r if r < 0 => {
+                let e = std::io::Error::last_os_error();
+                if e.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e);
+            },
+            _ => (),
+        }
+
+        match f.read(&mut buff[n..]) {
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(r) => n += r,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+pub struct AdcDriver {
+    count: i32,
+    pub(super) allowed: Option<Vec<u32>>,
+    adc: HashMap<i32, AdcChannel>
+}
+
+impl AdcDriver {
+    pub fn new() -> Self {
+        Self{
+            count: 0,
+            allowed: None,
+            adc: HashMap::new()
+        }
+    }
+
+    /// Close channels left open by the guest
+    pub(super) fn release(&mut self) {
+        let handles: Vec<i32> = self.adc.keys().cloned().collect();
+        for h in handles {
+            debug!("Releasing ADC handle: {}", h);
+            let _ = Adc::deinit(self, h);
+        }
+    }
+
+    fn channel(&self, handle: i32) -> Result<&AdcChannel, Error> {
+        match self.adc.get(&handle) {
+            Some(c) => Ok(c),
+            None => {
+                error!("No ADC channel for handle: {}", handle);
+                Err(Error::NoDevice)
+            }
+        }
+    }
+}
+
+impl Drop for AdcDriver {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl Adc for AdcDriver {
+    fn init(&mut self, device: u32, channel: u32) -> Result<i32, Error> {
+        if !super::permitted(&self.allowed, &device) {
+            error!("ADC device {} not permitted", device);
+            return Err(Error::NoDevice);
+        }
+
+        let c = AdcChannel{ device, channel };
+        debug!("Opening ADC device: {} channel: {}", c.dir().display(), channel);
+
+        if c.read(&format!("in_voltage{}_raw", channel)).is_none() {
+            error!("No ADC channel in_voltage{}_raw for device {}", channel, device);
+            return Err(Error::NoDevice);
+        }
+
+        let idx = self.count;
+        self.count += 1;
+
+        // Store for later use
+        self.adc.insert(idx, c);
+
+        // Return index
+        Ok(idx)
+    }
+
+    fn deinit(&mut self, handle: i32) -> Result<(), Error> {
+        debug!("Dropping ADC handle: {}", handle);
+
+        let _c = self.adc.remove(&handle);
+
+        Ok(())
+    }
+
+    fn read_raw(&mut self, handle: i32) -> Result<i32, Error> {
+        let c = self.channel(handle)?;
+
+        let raw = c.read(&format!("in_voltage{}_raw", c.channel))
+            .and_then(|v| v.parse().ok());
+
+        debug!("ADC read handle: {} raw: {:?}", handle, raw);
+
+        match raw {
+            Some(v) => Ok(v),
+            None => {
+                error!("Failed to read ADC channel");
+                Err(Error::Failed)
+            }
+        }
+    }
+
+    fn read(&mut self, handle: i32) -> Result<f32, Error> {
+        let raw = self.read_raw(handle)?;
+
+        // IIO voltages are (raw + offset) * scale in millivolts
+        let c = self.channel(handle)?;
+        let scale = c.read_info("scale").unwrap_or(1.0);
+        let offset = c.read_info("offset").unwrap_or(0.0);
+
+        Ok((raw as f32 + offset) * scale)
+    }
+
+    fn capture(&mut self, handle: i32, trigger: u32, samples: &mut [i32]) -> Result<(), Error> {
+        let c = self.channel(handle)?;
+
+        debug!("ADC capture handle: {} trigger: {} samples: {}", handle, trigger, samples.len());
+
+        let ty = match c.read(&format!("scan_elements/in_voltage{}_type", c.channel)) {
+            Some(t) => ScanType::parse(&t)?,
+            None => {
+                error!("No IIO scan element for channel {}", c.channel);
+                return Err(Error::Unsupported);
+            }
+        };
+
+        // Device configuration is restored once the capture is dropped
+        let mut capture = Capture::new(c);
+
+        // Select trigger by name
+        let t = PathBuf::from(IIO_ROOT).join(format!("trigger{}", trigger)).join("name");
+        let name = match std::fs::read_to_string(&t) {
+            Ok(n) => n.trim().to_string(),
+            Err(e) => {
+                error!("Failed to read IIO trigger {}: {:?}", t.display(), e);
+                return Err(Error::NoDevice);
+            }
+        };
+        capture.set("trigger/current_trigger", &name)?;
+
+        // Enable only the captured channel, so each scan holds a single sample
+        let enabled = format!("in_voltage{}_en", c.channel);
+        if let Ok(d) = std::fs::read_dir(c.dir().join("scan_elements")) {
+            for e in d.flatten() {
+                let n = e.file_name().to_string_lossy().to_string();
+                if n.ends_with("_en") && n != enabled {
+                    capture.set(&format!("scan_elements/{}", n), "0")?;
+                }
+            }
+        }
+        capture.set(&format!("scan_elements/{}", enabled), "1")?;
+
+        // Capture samples
+        capture.enable(samples.len())?;
+
+        let size = ty.size();
+        let mut buff = vec![0u8; samples.len() * size];
+
+        let res = File::open(format!("/dev/iio:device{}", c.device))
+            .and_then(|mut f| read_timeout(&mut f, &mut buff));
+
+        drop(capture);
+
+        if let Err(e) = res {
+            error!("Failed to read IIO buffer: {:?}", e);
+            return Err(Error::Failed);
+        }
+
+        for (s, b) in samples.iter_mut().zip(buff.chunks(size)) {
+            *s = ty.decode(b);
+        }
+
+        Ok(())
+    }
+}
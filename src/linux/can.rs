@@ -0,0 +1,334 @@
+//! Linux CAN driver implementation, using SocketCAN raw sockets
+//!
+//! Interfaces are resolved by port as `{prefix}{port}`, with the prefix
+//! defaulting to `can` (use `vcan` to test against virtual interfaces).
+
+use std::{format, string::String, collections::HashMap, ffi::CString, path::PathBuf};
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
+
+use log::{debug, error};
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::{Can, Frame, Filter, CAN_EFF_MAX, CAN_SFF_MAX, CANFD_MAX_LEN};
+
+// SocketCAN definitions from `linux/can.h` and `linux/can/raw.h`
+const PF_CAN: libc::c_int = 29;
+const CAN_RAW: libc::c_int = 1;
+const SOL_CAN_RAW: libc::c_int = 100 + CAN_RAW;
+const CAN_RAW_FILTER: libc::c_int = 1;
+const CAN_RAW_FD_FRAMES: libc::c_int = 5;
+
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+
+/// Classic frame size (`struct can_frame`)
+const CAN_MTU: usize = 16;
+/// CAN-FD frame size (`struct canfd_frame`)
+const CANFD_MTU: usize = 72;
+
+/// `struct canfd_frame`, sharing its header layout with `struct can_frame`
+#[allow(dead_code)]
+#[repr(C, align(8))]
+struct RawFrame {
+    can_id: u32,
+    len: u8,
+    flags: u8,
+    res0: u8,
+    res1: u8,
+    data: [u8; CANFD_MAX_LEN],
+}
+
+/// `struct sockaddr_can`
+#[allow(dead_code)]
+#[repr(C, align(8))]
+struct SockAddrCan {
+    can_family: libc::sa_family_t,
+    can_ifindex: libc::c_int,
+    can_addr: [u64; 2],
+}
+
+/// `struct can_filter`
+#[repr(C)]
+struct RawFilter {
+    can_id: u32,
+    can_mask: u32,
+}
+
+/// Open CAN socket, closed on drop
+struct CanSocket {
+    fd: RawFd,
+    iface: String,
+    fd_frames: bool,
+}
+
+impl CanSocket {
+    /// Set a socket option
+    fn set_opt<T>(&self, name: libc::c_int, v: *const T, len: usize) -> Result<(), Error> {
+        let res = unsafe {
+            libc::setsockopt(self.fd, SOL_CAN_RAW, name, v as *const libc::c_void, len as libc::socklen_t)
+        };
+
+        if res < 0 {
+            error!("Failed to set CAN socket option {}: {:?}", name, std::io::Error::last_os_error());
+            return Err(Error::Failed);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for CanSocket {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+pub struct CanDriver {
+    count: i32,
+    prefix: String,
+    pub(super) allowed: Option<Vec<u32>>,
+    can: HashMap<i32, CanSocket>
+}
+
+impl CanDriver {
+    pub fn new() -> Self {
+        Self{
+            count: 0,
+            prefix: String::from("can"),
+            allowed: None,
+            can: HashMap::new()
+        }
+    }
+
+    /// Set the interface name prefix used to resolve ports
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Close sockets left open by the guest
+    pub(super) fn release(&mut self) {
+        let handles: Vec<i32> = self.can.keys().cloned().collect();
+        for h in handles {
+            debug!("Releasing CAN handle: {}", h);
+            let _ = Can::deinit(self, h);
+        }
+    }
+
+    fn socket(&self, handle: i32) -> Result<&CanSocket, Error> {
+        match self.can.get(&handle) {
+            Some(s) => Ok(s),
+            None => {
+                error!("No CAN interface for handle: {}", handle);
+                Err(Error::NoDevice)
+            }
+        }
+    }
+}
+
+impl Drop for CanDriver {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl Can for CanDriver {
+    fn init(&mut self, port: u32, fd: bool) -> Result<i32, Error> {
+        if !super::permitted(&self.allowed, &port) {
+            error!("CAN port {} not permitted", port);
+            return Err(Error::NoDevice);
+        }
+
+        let iface = format!("{}{}", self.prefix, port);
+        debug!("Opening CAN interface: {} (fd: {})", iface, fd);
+
+        // CAN-FD interfaces report the FD frame size as their MTU
+        let mtu = std::fs::read_to_string(PathBuf::from("/sys/class/net").join(&iface).join("mtu"))
+            .ok().and_then(|m| m.trim().parse::<usize>().ok());
+        match mtu {
+            None => {
+                error!("No CAN interface: {}", iface);
+                return Err(Error::NoDevice);
+            },
+            Some(m) if fd && m != CANFD_MTU => {
+                error!("CAN interface {} does not support CAN-FD (mtu: {})", iface, m);
+                return Err(Error::Unsupported);
+            },
+            _ => (),
+        }
+
+        let name = CString::new(iface.clone()).map_err(|_| Error::InvalidArg)?;
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if index == 0 {
+            error!("Failed to resolve CAN interface {}: {:?}", iface, std::io::Error::last_os_error());
+            return Err(Error::NoDevice);
+        }
+
+        let s = unsafe { libc::socket(PF_CAN, libc::SOCK_RAW | libc::SOCK_CLOEXEC, CAN_RAW) };
+        if s < 0 {
+            error!("Failed to open CAN socket: {:?}", std::io::Error::last_os_error());
+            return Err(Error::Failed);
+        }
+        let s = CanSocket{ fd: s, iface, fd_frames: fd };
+
+        if fd {
+            let enable: libc::c_int = 1;
+            s.set_opt(CAN_RAW_FD_FRAMES, &enable, std::mem::size_of::<libc::c_int>())?;
+        }
+
+        let addr = SockAddrCan{ can_family: PF_CAN as libc::sa_family_t, can_ifindex: index as libc::c_int, can_addr: [0; 2] };
+        let res = unsafe {
+            libc::bind(s.fd, &addr as *const SockAddrCan as *const libc::sockaddr, std::mem::size_of::<SockAddrCan>() as libc::socklen_t)
+        };
+        if res < 0 {
+            error!("Failed to bind CAN socket to {}: {:?}", s.iface, std::io::Error::last_os_error());
+            return Err(Error::Failed);
+        }
+
+        let idx = self.count;
+        self.count += 1;
+
+        // Store for later use
+        self.can.insert(idx, s);
+
+        // Return index
+        Ok(idx)
+    }
+
+    fn deinit(&mut self, handle: i32) -> Result<(), Error> {
+        debug!("Dropping CAN handle: {}", handle);
+
+        let _s = self.can.remove(&handle);
+
+        Ok(())
+    }
+
+    fn set_filters(&mut self, handle: i32, filters: &[Filter]) -> Result<(), Error> {
+        let s = self.socket(handle)?;
+
+        debug!("CAN set filters handle: {} filters: {:?}", handle, filters);
+
+        // Masking the extended flag matches only the filter identifier type,
+        // a single zero-mask filter restores the receive-all default
+        let raw: Vec<RawFilter> = match filters.is_empty() {
+            true => vec![RawFilter{ can_id: 0, can_mask: 0 }],
+            false => filters.iter().map(|f| match f.extended {
+                true => RawFilter{ can_id: (f.id & CAN_EFF_MAX) | CAN_EFF_FLAG, can_mask: (f.mask & CAN_EFF_MAX) | CAN_EFF_FLAG },
+                false => RawFilter{ can_id: f.id & CAN_SFF_MAX, can_mask: (f.mask & CAN_SFF_MAX) | CAN_EFF_FLAG },
+            }).collect(),
+        };
+
+        s.set_opt(CAN_RAW_FILTER, raw.as_ptr(), raw.len() * std::mem::size_of::<RawFilter>())
+    }
+
+    fn send(&mut self, handle: i32, frame: &Frame) -> Result<(), Error> {
+        let s = self.socket(handle)?;
+
+        debug!("CAN send handle: {} frame: {:?}", handle, frame);
+
+        if frame.fd && !s.fd_frames {
+            error!("CAN-FD frames not enabled for interface {}", s.iface);
+            return Err(Error::Unsupported);
+        }
+
+        let mut raw = RawFrame{
+            can_id: frame.id,
+            len: frame.len() as u8,
+            flags: 0,
+            res0: 0,
+            res1: 0,
+            data: [0u8; CANFD_MAX_LEN],
+        };
+        raw.data[..frame.data().len()].copy_from_slice(frame.data());
+
+        if frame.extended {
+            raw.can_id |= CAN_EFF_FLAG;
+        }
+        if frame.remote {
+            raw.can_id |= CAN_RTR_FLAG;
+        }
+
+        let size = match frame.fd {
+            true => CANFD_MTU,
+            false => CAN_MTU,
+        };
+
+        let n = unsafe { libc::write(s.fd, &raw as *const RawFrame as *const libc::c_void, size) };
+        if n != size as isize {
+            error!("Failed to send CAN frame: {:?}", std::io::Error::last_os_error());
+            return Err(Error::Failed);
+        }
+
+        Ok(())
+    }
+
+    fn receive(&mut self, handle: i32, timeout_ms: Option<u32>) -> Result<Option<Frame>, Error> {
+        let s = self.socket(handle)?;
+
+        let deadline = timeout_ms.map(|t| Instant::now() + Duration::from_millis(t as u64));
+
+        loop {
+            // Wait for a frame, resuming on signal interruption
+            let timeout = match deadline {
+                Some(d) => d.saturating_duration_since(Instant::now()).as_millis() as libc::c_int,
+                None => -1,
+            };
+
+            let mut p = libc::pollfd{ fd: s.fd, events: libc::POLLIN, revents: 0 };
+            let res = unsafe { libc::poll(&mut p, 1, timeout) };
+            if res < 0 {
+                let e = std::io::Error::last_os_error();
+                if e.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                error!("Failed to poll CAN socket: {:?}", e);
+                return Err(Error::Failed);
+            }
+            if res == 0 {
+                debug!("CAN receive handle: {} timeout", handle);
+                return Ok(None);
+            }
+
+            let mut raw = RawFrame{ can_id: 0, len: 0, flags: 0, res0: 0, res1: 0, data: [0u8; CANFD_MAX_LEN] };
+            let n = unsafe { libc::read(s.fd, &mut raw as *mut RawFrame as *mut libc::c_void, CANFD_MTU) };
+
+            let fd = match n as usize {
+                CAN_MTU => false,
+                CANFD_MTU => true,
+                _ => {
+                    error!("Failed to receive CAN frame: {:?}", std::io::Error::last_os_error());
+                    return Err(Error::Failed);
+                }
+            };
+
+            // Error frames are only delivered where requested, skip regardless
+            if raw.can_id & CAN_ERR_FLAG != 0 {
+                continue;
+            }
+
+            let extended = raw.can_id & CAN_EFF_FLAG != 0;
+            let id = match extended {
+                true => raw.can_id & CAN_EFF_MAX,
+                false => raw.can_id & CAN_SFF_MAX,
+            };
+
+            let frame = match raw.can_id & CAN_RTR_FLAG != 0 {
+                true => Frame::remote(id, extended, raw.len as usize),
+                false => Frame::new(id, extended, fd, &raw.data[..(raw.len as usize).min(CANFD_MAX_LEN)]),
+            };
+
+            debug!("CAN receive handle: {} frame: {:?}", handle, frame);
+
+            match frame {
+                Some(f) => return Ok(Some(f)),
+                None => {
+                    error!("Received invalid CAN frame (id: {:#x} len: {})", raw.can_id, raw.len);
+                    return Err(Error::Failed);
+                }
+            }
+        }
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::collections::HashMap;
 
-use log::{debug, error};
+use log::{debug, warn, error};
 use embedded_hal::digital::{PinState, blocking::*};
 use linux_embedded_hal::{SysfsPin, sysfs_gpio::Direction};
 
@@ -10,6 +10,7 @@ use wasm_embedded_spec::{Error, Gpio};
 
 pub struct GpioDriver {
     count: i32,
+    pub(super) allowed: Option<Vec<i32>>,
     gpio: HashMap<i32, SysfsPin>
 }
 
@@ -17,16 +18,45 @@ impl GpioDriver {
     pub fn new() -> Self {
         Self{
             count: 0,
+            allowed: None,
             gpio: HashMap::new()
         }
     }
+
+    /// Unexport any pins still held when the driver is dropped
+    pub(super) fn release(&mut self) {
+        let handles: Vec<i32> = self.gpio.keys().cloned().collect();
+        for h in handles {
+            debug!("Releasing GPIO handle: {}", h);
+            let _ = Gpio::deinit(self, h);
+        }
+    }
+}
+
+impl Drop for GpioDriver {
+    fn drop(&mut self) {
+        self.release();
+    }
 }
 
 impl Gpio for GpioDriver {
     /// Initialise the provided GPIO pin in input or output mode
-    fn init(&mut self, _port: i32, pin: i32, output: bool) -> Result<i32, Error> {
-        
-        debug!("GPIO init port: {} pin: {} output: {:?}", _port, pin, output);
+    ///
+    /// Sysfs GPIOs use global pin numbers, so the port is ignored. Where
+    /// permitted pins are configured these are global numbers, so only
+    /// port 0 is accepted.
+    fn init(&mut self, port: i32, pin: i32, output: bool) -> Result<i32, Error> {
+        if port != 0 && self.allowed.is_some() {
+            error!("GPIO port {} unsupported with permitted pins, pins use global sysfs numbering with port 0", port);
+            return Err(Error::InvalidArg);
+        }
+
+        if !super::permitted(&self.allowed, &pin) {
+            error!("GPIO pin {} not permitted", pin);
+            return Err(Error::NoDevice);
+        }
+
+        debug!("GPIO init port: {} pin: {} output: {:?}", port, pin, output);
 
         let idx = self.count;
         self.count += 1;
@@ -59,7 +89,12 @@ impl Gpio for GpioDriver {
     fn deinit(&mut self, handle: i32) -> Result<(), Error> {
         debug!("Dropping GPIO handle: {}", handle);
 
-        let mut _pin = self.gpio.remove(&handle);
+        // Unexport pin so this is released for other users
+        if let Some(pin) = self.gpio.remove(&handle) {
+            if let Err(e) = pin.unexport() {
+                warn!("Failed to unexport pin: {:?}", e);
+            }
+        }
 
         Ok(())
     }
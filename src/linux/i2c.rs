@@ -10,6 +10,7 @@ use wasm_embedded_spec::{Error};
 
 pub struct I2cDriver {
     count: i32,
+    pub(super) allowed: Option<Vec<u32>>,
     i2c: HashMap<i32, I2cdev>
 }
 
@@ -17,14 +18,34 @@ impl I2cDriver {
     pub fn new() -> Self {
         Self{
             count: 0,
+            allowed: None,
             i2c: HashMap::new()
         }
     }
+
+    /// Close any I2C devices left open by the guest
+    pub(super) fn release(&mut self) {
+        let handles: Vec<i32> = self.i2c.keys().cloned().collect();
+        for h in handles {
+            debug!("Releasing I2C handle: {}", h);
+            let _ = wasm_embedded_spec::I2c::deinit(self, h);
+        }
+    }
+}
+
+impl Drop for I2cDriver {
+    fn drop(&mut self) {
+        self.release();
+    }
 }
 
 impl wasm_embedded_spec::I2c for I2cDriver {
     fn init(&mut self, dev: u32, _baud: u32, _sda: i32, _sck: i32) -> Result<i32, Error> {
-        
+        if !super::permitted(&self.allowed, &dev) {
+            error!("I2C device {} not permitted", dev);
+            return Err(Error::NoDevice);
+        }
+
         let p = format!("/dev/i2c-{}", dev);
         debug!("Opening I2C device: {}", p);
 
@@ -2,6 +2,8 @@
 
 use wasm_embedded_spec::Engine;
 
+use crate::{EngineExt, opts::Peripherals, server::StopToken, watchdog::WatchdogHandle, logging::{GuestLog, LogConfig}};
+
 mod i2c;
 pub use i2c::I2cDriver;
 
@@ -14,12 +16,42 @@ pub use gpio::GpioDriver;
 mod uart;
 pub use uart::UartDriver;
 
+mod pwm;
+pub use pwm::PwmDriver;
+
+mod adc;
+pub use adc::{AdcDriver, ScanType};
+
+mod can;
+pub use can::CanDriver;
+
+mod onewire;
+pub use onewire::OneWireDriver;
+
+mod time;
+pub use time::TimeDriver;
+
+mod watchdog;
+pub use watchdog::{WatchdogDriver, WatchdogDevice};
+
+mod storage;
+pub use storage::{StorageDriver, FileStorage, EepromStorage};
+
 /// Linux embedded wasm driver context
 pub struct LinuxCtx {
     pub(super) spi: SpiDriver,
     pub(super) i2c: I2cDriver,
     pub(super) uart: UartDriver,
     pub(super) gpio: GpioDriver,
+    pub(super) pwm: PwmDriver,
+    pub(super) adc: AdcDriver,
+    pub(super) can: CanDriver,
+    pub(super) onewire: OneWireDriver,
+    pub(super) time: TimeDriver,
+    pub(super) watchdog: WatchdogDriver,
+    pub(super) storage: StorageDriver,
+    pub(super) logger: GuestLog,
+    stop: Option<StopToken>,
 }
 
 impl LinuxCtx {
@@ -30,8 +62,105 @@ impl LinuxCtx {
             i2c: I2cDriver::new(),
             uart: UartDriver::new(),
             gpio: GpioDriver::new(),
+            pwm: PwmDriver::new(),
+            adc: AdcDriver::new(),
+            can: CanDriver::new(),
+            onewire: OneWireDriver::new(),
+            time: TimeDriver::new(),
+            watchdog: WatchdogDriver::new(),
+            storage: StorageDriver::new(),
+            logger: GuestLog::new("guest", LogConfig::default()),
+            stop: None,
         }
     }
+
+    /// Create a new linux driver context restricted to the provided peripherals
+    pub fn with_peripherals(p: Peripherals) -> Self {
+        let mut ctx = Self::new();
+
+        ctx.gpio.allowed = p.gpio;
+        ctx.i2c.allowed = p.i2c;
+        ctx.spi.allowed = p.spi;
+        ctx.uart.allowed = p.uart;
+        ctx.pwm.allowed = p.pwm;
+        ctx.adc.allowed = p.adc;
+        ctx.can.allowed = p.can;
+        ctx.onewire.allowed = p.onewire;
+        ctx.onewire.pins = ctx.gpio.allowed.clone();
+        ctx.onewire.bitbang = p.onewire_bitbang;
+
+        ctx
+    }
+
+    /// Bind the context to a [`StopToken`], releasing all peripherals and
+    /// refusing further driver access once stopped
+    pub fn with_stop(mut self, stop: StopToken) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Set the interface name prefix used to resolve CAN ports
+    /// (`can` by default, `vcan` for virtual interfaces)
+    pub fn with_can_prefix(mut self, prefix: &str) -> Self {
+        self.can = CanDriver::new().with_prefix(prefix);
+        self
+    }
+
+    /// Permit guests to bit-bang 1-Wire buses on GPIO pins
+    ///
+    /// Sysfs GPIO access cannot reliably meet 1-Wire slot timing, so
+    /// kernel `w1-gpio` bus masters should be preferred.
+    pub fn with_onewire_bitbang(mut self, enabled: bool) -> Self {
+        self.onewire.bitbang = enabled;
+        self
+    }
+
+    /// Forward guest watchdog kicks to the provided watchdog service
+    pub fn with_watchdog(mut self, handle: WatchdogHandle) -> Self {
+        self.watchdog.handle = Some(handle);
+        self
+    }
+
+    /// Provide the guest with persistent storage
+    pub fn with_storage(mut self, storage: StorageDriver) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// Route guest log messages through the provided logger
+    pub fn with_logger(mut self, logger: GuestLog) -> Self {
+        self.logger = logger;
+        self
+    }
+
+    /// Check whether the context has been stopped, releasing peripherals if so
+    fn stopped(&mut self) -> bool {
+        match &self.stop {
+            Some(s) if s.is_stopped() => (),
+            _ => return false,
+        }
+
+        // Release is idempotent, handles are removed on deinit
+        self.gpio.release();
+        self.i2c.release();
+        self.spi.release();
+        self.uart.release();
+        self.pwm.release();
+        self.adc.release();
+        self.can.release();
+        self.onewire.release();
+        self.time.release();
+
+        true
+    }
+}
+
+/// Check whether a peripheral is permitted (all are where unrestricted)
+pub(super) fn permitted<T: PartialEq>(allowed: &Option<Vec<T>>, v: &T) -> bool {
+    match allowed {
+        Some(a) => a.contains(v),
+        None => true,
+    }
 }
 
 impl Engine for LinuxCtx {
@@ -44,18 +173,104 @@ impl Engine for LinuxCtx {
     type Uart = UartDriver;
 
     fn gpio(&mut self) -> Option<&mut Self::Gpio> {
+        if self.stopped() {
+            return None;
+        }
         Some(&mut self.gpio)
     }
 
     fn i2c(&mut self) -> Option<&mut Self::I2c> {
+        if self.stopped() {
+            return None;
+        }
         Some(&mut self.i2c)
     }
 
     fn spi(&mut self) -> Option<&mut Self::Spi> {
+        if self.stopped() {
+            return None;
+        }
         Some(&mut self.spi)
     }
 
     fn uart(&mut self) -> Option<&mut Self::Uart> {
+        if self.stopped() {
+            return None;
+        }
         Some(&mut self.uart)
     }
 }
+
+impl EngineExt for LinuxCtx {
+    type Pwm = PwmDriver;
+
+    type Adc = AdcDriver;
+
+    type Can = CanDriver;
+
+    type OneWire = OneWireDriver;
+
+    type Time = TimeDriver;
+
+    type Watchdog = WatchdogDriver;
+
+    type Storage = StorageDriver;
+
+    type Logger = GuestLog;
+
+    fn pwm(&mut self) -> Option<&mut Self::Pwm> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.pwm)
+    }
+
+    fn adc(&mut self) -> Option<&mut Self::Adc> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.adc)
+    }
+
+    fn can(&mut self) -> Option<&mut Self::Can> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.can)
+    }
+
+    fn onewire(&mut self) -> Option<&mut Self::OneWire> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.onewire)
+    }
+
+    fn time(&mut self) -> Option<&mut Self::Time> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.time)
+    }
+
+    fn watchdog(&mut self) -> Option<&mut Self::Watchdog> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.watchdog)
+    }
+
+    fn storage(&mut self) -> Option<&mut Self::Storage> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.storage)
+    }
+
+    fn logger(&mut self) -> Option<&mut Self::Logger> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.logger)
+    }
+}
@@ -0,0 +1,434 @@
+//! Linux 1-Wire driver implementation
+//!
+//! Kernel bus masters (`w1_bus_masterN`, including `w1-gpio`) are driven
+//! through the w1 netlink connector, which requires `CAP_NET_ADMIN`.
+//! Buses may also be bit-banged on a sysfs GPIO (with an external pull-up),
+//! however sysfs access cannot reliably meet 1-Wire slot timing, so this
+//! must be explicitly enabled and the kernel `w1-gpio` driver should be
+//! used wherever possible.
+
+use std::{format, string::String, vec, vec::Vec, collections::HashMap, path::PathBuf};
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn, error};
+use embedded_hal::digital::blocking::InputPin;
+use linux_embedded_hal::{SysfsPin, sysfs_gpio::Direction};
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::{OneWire, OneWireBits, Rom, search_rom};
+
+/// w1 sysfs device directory
+const W1_ROOT: &str = "/sys/bus/w1/devices";
+
+// Connector and w1 netlink definitions from `linux/connector.h` and `w1_netlink.h`
+const CN_W1_IDX: u32 = 3;
+const CN_W1_VAL: u32 = 1;
+const W1_MASTER_CMD: u8 = 4;
+const W1_CMD_READ: u8 = 0;
+const W1_CMD_WRITE: u8 = 1;
+const W1_CMD_SEARCH: u8 = 2;
+const W1_CMD_RESET: u8 = 5;
+
+/// Header lengths (`nlmsghdr`, `cn_msg`, `w1_netlink_msg`, `w1_netlink_cmd`)
+const NL_HDR_LEN: usize = 16;
+const CN_MSG_LEN: usize = 20;
+const W1_MSG_LEN: usize = 12;
+const W1_CMD_LEN: usize = 4;
+
+/// Time to wait for w1 command replies
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Kernel bus master, accessed via netlink
+struct Master {
+    id: u32,
+    sock: RawFd,
+    seq: u32,
+}
+
+impl Master {
+    fn open(id: u32) -> Result<Self, Error> {
+        let sock = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, libc::NETLINK_CONNECTOR) };
+        if sock < 0 {
+            error!("Failed to open netlink socket: {:?}", std::io::Error::last_os_error());
+            return Err(Error::Failed);
+        }
+        let m = Self{ id, sock, seq: 0 };
+
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = 1 << (CN_W1_IDX - 1);
+
+        let res = unsafe {
+            libc::bind(m.sock, &addr as *const libc::sockaddr_nl as *const libc::sockaddr, std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t)
+        };
+        if res < 0 {
+            error!("Failed to bind netlink socket: {:?}", std::io::Error::last_os_error());
+            return Err(Error::Failed);
+        }
+
+        Ok(m)
+    }
+
+    /// Execute a master command, returning data from any replies
+    fn command(&mut self, cmd: u8, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let seq = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+
+        let cmd_len = W1_CMD_LEN + data.len();
+        let msg_len = W1_MSG_LEN + cmd_len;
+        let len = NL_HDR_LEN + CN_MSG_LEN + msg_len;
+
+        let mut b = Vec::with_capacity(len);
+        // nlmsghdr
+        b.extend_from_slice(&(len as u32).to_ne_bytes());
+        b.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+        b.extend_from_slice(&0u16.to_ne_bytes());
+        b.extend_from_slice(&seq.to_ne_bytes());
+        b.extend_from_slice(&0u32.to_ne_bytes());
+        // cn_msg
+        b.extend_from_slice(&CN_W1_IDX.to_ne_bytes());
+        b.extend_from_slice(&CN_W1_VAL.to_ne_bytes());
+        b.extend_from_slice(&seq.to_ne_bytes());
+        b.extend_from_slice(&0u32.to_ne_bytes());
+        b.extend_from_slice(&(msg_len as u16).to_ne_bytes());
+        b.extend_from_slice(&0u16.to_ne_bytes());
+        // w1_netlink_msg
+        b.extend_from_slice(&[W1_MASTER_CMD, 0]);
+        b.extend_from_slice(&(cmd_len as u16).to_ne_bytes());
+        b.extend_from_slice(&self.id.to_ne_bytes());
+        b.extend_from_slice(&0u32.to_ne_bytes());
+        // w1_netlink_cmd
+        b.extend_from_slice(&[cmd, 0]);
+        b.extend_from_slice(&(data.len() as u16).to_ne_bytes());
+        b.extend_from_slice(data);
+
+        let n = unsafe { libc::send(self.sock, b.as_ptr() as *const libc::c_void, b.len(), 0) };
+        if n != b.len() as isize {
+            error!("Failed to send w1 command: {:?}", std::io::Error::last_os_error());
+            return Err(Error::Failed);
+        }
+
+        // Data replies precede a status reply (without data) for each command
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        let mut out = Vec::new();
+        let mut r = vec![0u8; 4096];
+
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now()).as_millis() as libc::c_int;
+            let mut p = libc::pollfd{ fd: self.sock, events: libc::POLLIN, revents: 0 };
+            if unsafe { libc::poll(&mut p, 1, timeout) } <= 0 {
+                error!("Timeout waiting for w1 command reply");
+                return Err(Error::Failed);
+            }
+
+            let n = unsafe { libc::recv(self.sock, r.as_mut_ptr() as *mut libc::c_void, r.len(), 0) };
+            if n < (NL_HDR_LEN + CN_MSG_LEN + W1_MSG_LEN) as isize {
+                error!("Failed to receive w1 reply: {:?}", std::io::Error::last_os_error());
+                return Err(Error::Failed);
+            }
+            let r = &r[..n as usize];
+
+            let u32_at = |i: usize| u32::from_ne_bytes([r[i], r[i+1], r[i+2], r[i+3]]);
+            let u16_at = |i: usize| u16::from_ne_bytes([r[i], r[i+1]]);
+
+            // Skip unrelated connector messages
+            let cn = NL_HDR_LEN;
+            if u32_at(cn) != CN_W1_IDX || u32_at(cn + 4) != CN_W1_VAL || u32_at(cn + 8) != seq {
+                continue;
+            }
+
+            let msg = cn + CN_MSG_LEN;
+            let status = r[msg + 1];
+            let msg_len = u16_at(msg + 2) as usize;
+
+            if status != 0 {
+                error!("w1 command {} failed (status: {})", cmd, status);
+                return Err(Error::Failed);
+            }
+
+            let c = msg + W1_MSG_LEN;
+            let data_len = match msg_len >= W1_CMD_LEN && r.len() >= c + W1_CMD_LEN {
+                true => u16_at(c + 2) as usize,
+                false => 0,
+            };
+
+            match data_len {
+                0 => return Ok(out),
+                n if r.len() >= c + W1_CMD_LEN + n => {
+                    out.extend_from_slice(&r[c + W1_CMD_LEN..][..n]);
+                },
+                _ => {
+                    error!("Truncated w1 reply");
+                    return Err(Error::Failed);
+                },
+            }
+        }
+    }
+
+    /// Read a master attribute
+    fn attr(&self, name: &str) -> Option<String> {
+        let p = PathBuf::from(W1_ROOT).join(format!("w1_bus_master{}", self.id)).join(name);
+        std::fs::read_to_string(p).ok().map(|s| s.trim().into())
+    }
+}
+
+impl Drop for Master {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.sock) };
+    }
+}
+
+/// Bit-banged bus, driving the line low or releasing it to the pull-up
+struct BitBang {
+    pin: SysfsPin,
+}
+
+impl BitBang {
+    /// Busy-wait, as sleeps are far too coarse for 1-Wire slots
+    fn wait(us: u64) {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_micros(us) {}
+    }
+
+    fn low(&self) -> Result<(), Error> {
+        self.pin.set_direction(Direction::Low).map_err(|e| {
+            error!("Failed to drive 1-Wire pin: {:?}", e);
+            Error::Failed
+        })
+    }
+
+    fn release(&self) -> Result<(), Error> {
+        self.pin.set_direction(Direction::In).map_err(|e| {
+            error!("Failed to release 1-Wire pin: {:?}", e);
+            Error::Failed
+        })
+    }
+
+    fn sample(&self) -> Result<bool, Error> {
+        self.pin.is_high().map_err(|e| {
+            error!("Failed to read 1-Wire pin: {:?}", e);
+            Error::Failed
+        })
+    }
+}
+
+impl OneWireBits for BitBang {
+    fn reset(&mut self) -> Result<bool, Error> {
+        self.low()?;
+        Self::wait(480);
+        self.release()?;
+        Self::wait(70);
+        let presence = !self.sample()?;
+        Self::wait(410);
+
+        Ok(presence)
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), Error> {
+        let (low, high) = match bit {
+            true => (6, 64),
+            false => (60, 10),
+        };
+
+        self.low()?;
+        Self::wait(low);
+        self.release()?;
+        Self::wait(high);
+
+        Ok(())
+    }
+
+    fn read_bit(&mut self) -> Result<bool, Error> {
+        self.low()?;
+        Self::wait(6);
+        self.release()?;
+        Self::wait(9);
+        let bit = self.sample()?;
+        Self::wait(55);
+
+        Ok(bit)
+    }
+}
+
+/// 1-Wire bus backend
+enum Bus {
+    Master(Master),
+    BitBang(BitBang),
+}
+
+pub struct OneWireDriver {
+    count: i32,
+    pub(super) allowed: Option<Vec<u32>>,
+    pub(super) pins: Option<Vec<i32>>,
+    pub(super) bitbang: bool,
+    onewire: HashMap<i32, Bus>
+}
+
+impl OneWireDriver {
+    pub fn new() -> Self {
+        Self{
+            count: 0,
+            allowed: None,
+            pins: None,
+            bitbang: false,
+            onewire: HashMap::new()
+        }
+    }
+
+    /// Close buses and unexport pins left open by the guest
+    pub(super) fn release(&mut self) {
+        let handles: Vec<i32> = self.onewire.keys().cloned().collect();
+        for h in handles {
+            debug!("Releasing 1-Wire handle: {}", h);
+            let _ = OneWire::deinit(self, h);
+        }
+    }
+
+    fn bus(&mut self, handle: i32) -> Result<&mut Bus, Error> {
+        match self.onewire.get_mut(&handle) {
+            Some(b) => Ok(b),
+            None => {
+                error!("No 1-Wire bus for handle: {}", handle);
+                Err(Error::NoDevice)
+            }
+        }
+    }
+}
+
+impl Drop for OneWireDriver {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl OneWire for OneWireDriver {
+    fn init(&mut self, bus: u32, pin: i32) -> Result<i32, Error> {
+        let b = match pin < 0 {
+            true => {
+                if !super::permitted(&self.allowed, &bus) {
+                    error!("1-Wire bus {} not permitted", bus);
+                    return Err(Error::NoDevice);
+                }
+
+                debug!("Opening 1-Wire bus master: {}", bus);
+
+                if !PathBuf::from(W1_ROOT).join(format!("w1_bus_master{}", bus)).exists() {
+                    error!("No 1-Wire bus master: {}", bus);
+                    return Err(Error::NoDevice);
+                }
+
+                Bus::Master(Master::open(bus)?)
+            },
+            false => {
+                if !self.bitbang {
+                    error!("Bit-banged 1-Wire buses are disabled, use a w1-gpio bus master or enable bit-banging");
+                    return Err(Error::Unsupported);
+                }
+
+                if !super::permitted(&self.pins, &pin) {
+                    error!("GPIO pin {} not permitted", pin);
+                    return Err(Error::NoDevice);
+                }
+
+                debug!("Opening bit-banged 1-Wire bus on pin: {}", pin);
+
+                let p = SysfsPin::new(pin as u64);
+                if let Err(e) = p.export() {
+                    error!("Failed to export pin: {:?}", e);
+                    return Err(Error::Failed);
+                }
+
+                let b = BitBang{ pin: p };
+                b.release()?;
+
+                Bus::BitBang(b)
+            },
+        };
+
+        let idx = self.count;
+        self.count += 1;
+
+        // Store for later use
+        self.onewire.insert(idx, b);
+
+        // Return index
+        Ok(idx)
+    }
+
+    fn deinit(&mut self, handle: i32) -> Result<(), Error> {
+        debug!("Dropping 1-Wire handle: {}", handle);
+
+        if let Some(Bus::BitBang(b)) = self.onewire.remove(&handle) {
+            if let Err(e) = b.pin.unexport() {
+                warn!("Failed to unexport pin: {:?}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn reset(&mut self, handle: i32) -> Result<bool, Error> {
+        let presence = match self.bus(handle)? {
+            // Masters do not report presence, so this uses the devices
+            // found by the last (automatic) search
+            Bus::Master(m) => {
+                m.command(W1_CMD_RESET, &[])?;
+                m.attr("w1_master_slave_count").and_then(|c| c.parse::<u32>().ok()).unwrap_or(0) > 0
+            },
+            Bus::BitBang(b) => b.reset()?,
+        };
+
+        debug!("1-Wire reset handle: {} presence: {}", handle, presence);
+
+        Ok(presence)
+    }
+
+    fn search(&mut self, handle: i32, roms: &mut [Rom]) -> Result<usize, Error> {
+        let n = match self.bus(handle)? {
+            // IDs are native u64s with the family code in the low byte
+            Bus::Master(m) => {
+                let ids = m.command(W1_CMD_SEARCH, &[])?;
+                let mut n = 0;
+                for (r, id) in roms.iter_mut().zip(ids.chunks_exact(8)) {
+                    let id = u64::from_ne_bytes([id[0], id[1], id[2], id[3], id[4], id[5], id[6], id[7]]);
+                    *r = id.to_le_bytes();
+                    n += 1;
+                }
+                n
+            },
+            Bus::BitBang(b) => search_rom(b, roms)?,
+        };
+
+        debug!("1-Wire search handle: {} roms: {:02x?}", handle, &roms[..n]);
+
+        Ok(n)
+    }
+
+    fn write_byte(&mut self, handle: i32, data: u8) -> Result<(), Error> {
+        debug!("1-Wire write handle: {} data: {:#04x}", handle, data);
+
+        match self.bus(handle)? {
+            Bus::Master(m) => m.command(W1_CMD_WRITE, &[data]).map(|_| ()),
+            Bus::BitBang(b) => b.write_byte(data),
+        }
+    }
+
+    fn read_byte(&mut self, handle: i32) -> Result<u8, Error> {
+        let data = match self.bus(handle)? {
+            Bus::Master(m) => match m.command(W1_CMD_READ, &[0xFF])?.first() {
+                Some(d) => *d,
+                None => {
+                    error!("No data in w1 read reply");
+                    return Err(Error::Failed);
+                }
+            },
+            Bus::BitBang(b) => b.read_byte()?,
+        };
+
+        debug!("1-Wire read handle: {} data: {:#04x}", handle, data);
+
+        Ok(data)
+    }
+}
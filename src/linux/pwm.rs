@@ -0,0 +1,184 @@
+//! Linux PWM driver implementation, using the sysfs PWM interface
+
+use std::{format, collections::HashMap, path::PathBuf, time::{Duration, Instant}};
+
+use log::{debug, warn, error};
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::{Pwm, Polarity};
+
+/// Sysfs PWM class directory
+const PWM_ROOT: &str = "/sys/class/pwm";
+
+/// Time to wait for exported channels to become available (pending udev permissions)
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Exported PWM channel
+struct PwmChannel {
+    chip: PathBuf,
+    channel: u32,
+    exported: bool,
+}
+
+impl PwmChannel {
+    /// Resolve a channel attribute path
+    fn attr(&self, name: &str) -> PathBuf {
+        self.chip.join(format!("pwm{}", self.channel)).join(name)
+    }
+
+    /// Write a channel attribute
+    fn write(&self, name: &str, value: &str) -> Result<(), Error> {
+        let p = self.attr(name);
+
+        if let Err(e) = std::fs::write(&p, value) {
+            error!("Failed to write PWM attribute {}: {:?}", p.display(), e);
+            return Err(Error::Failed);
+        }
+
+        Ok(())
+    }
+}
+
+pub struct PwmDriver {
+    count: i32,
+    pub(super) allowed: Option<Vec<u32>>,
+    pwm: HashMap<i32, PwmChannel>
+}
+
+impl PwmDriver {
+    pub fn new() -> Self {
+        Self{
+            count: 0,
+            allowed: None,
+            pwm: HashMap::new()
+        }
+    }
+
+    /// Disable and unexport PWM channels left enabled by the guest
+    pub(super) fn release(&mut self) {
+        let handles: Vec<i32> = self.pwm.keys().cloned().collect();
+        for h in handles {
+            debug!("Releasing PWM handle: {}", h);
+            let _ = Pwm::deinit(self, h);
+        }
+    }
+
+    fn channel(&self, handle: i32) -> Result<&PwmChannel, Error> {
+        match self.pwm.get(&handle) {
+            Some(c) => Ok(c),
+            None => {
+                error!("No PWM channel for handle: {}", handle);
+                Err(Error::NoDevice)
+            }
+        }
+    }
+}
+
+impl Drop for PwmDriver {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl Pwm for PwmDriver {
+    fn init(&mut self, chip: u32, channel: u32) -> Result<i32, Error> {
+        if !super::permitted(&self.allowed, &chip) {
+            error!("PWM chip {} not permitted", chip);
+            return Err(Error::NoDevice);
+        }
+
+        let chip_path = PathBuf::from(PWM_ROOT).join(format!("pwmchip{}", chip));
+        debug!("Opening PWM chip: {} channel: {}", chip_path.display(), channel);
+
+        let c = PwmChannel{ chip: chip_path, channel, exported: false };
+
+        // Export channel if not already available
+        let c = match c.attr("enable").exists() {
+            true => c,
+            false => {
+                if let Err(e) = std::fs::write(c.chip.join("export"), format!("{}", channel)) {
+                    error!("Failed to export PWM channel: {:?}", e);
+                    return Err(Error::Failed);
+                }
+
+                PwmChannel{ exported: true, ..c }
+            },
+        };
+
+        // Wait for channel attributes to become writable
+        let start = Instant::now();
+        while std::fs::OpenOptions::new().write(true).open(c.attr("enable")).is_err() {
+            if start.elapsed() > EXPORT_TIMEOUT {
+                error!("Timeout waiting for PWM channel {}", c.attr("enable").display());
+                return Err(Error::Failed);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let idx = self.count;
+        self.count += 1;
+
+        // Store for later use
+        self.pwm.insert(idx, c);
+
+        // Return index
+        Ok(idx)
+    }
+
+    fn deinit(&mut self, handle: i32) -> Result<(), Error> {
+        debug!("Dropping PWM handle: {}", handle);
+
+        let c = match self.pwm.remove(&handle) {
+            Some(c) => c,
+            None => return Err(Error::NoDevice),
+        };
+
+        // Disable output and unexport channels exported on init
+        if let Err(e) = c.write("enable", "0") {
+            warn!("Failed to disable PWM channel: {:?}", e);
+        }
+
+        if c.exported {
+            if let Err(e) = std::fs::write(c.chip.join("unexport"), format!("{}", c.channel)) {
+                warn!("Failed to unexport PWM channel: {:?}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_period(&mut self, handle: i32, period_ns: u32) -> Result<(), Error> {
+        debug!("PWM set period handle: {} period: {} ns", handle, period_ns);
+
+        self.channel(handle)?.write("period", &format!("{}", period_ns))
+    }
+
+    fn set_duty_cycle(&mut self, handle: i32, duty_ns: u32) -> Result<(), Error> {
+        debug!("PWM set duty cycle handle: {} duty: {} ns", handle, duty_ns);
+
+        self.channel(handle)?.write("duty_cycle", &format!("{}", duty_ns))
+    }
+
+    fn set_polarity(&mut self, handle: i32, polarity: Polarity) -> Result<(), Error> {
+        debug!("PWM set polarity handle: {} polarity: {:?}", handle, polarity);
+
+        let p = match polarity {
+            Polarity::Normal => "normal",
+            Polarity::Inversed => "inversed",
+        };
+
+        self.channel(handle)?.write("polarity", p)
+    }
+
+    fn enable(&mut self, handle: i32, enabled: bool) -> Result<(), Error> {
+        debug!("PWM enable handle: {} enabled: {}", handle, enabled);
+
+        let v = match enabled {
+            true => "1",
+            false => "0",
+        };
+
+        self.channel(handle)?.write("enable", v)
+    }
+}
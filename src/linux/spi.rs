@@ -9,6 +9,7 @@ use wasm_embedded_spec::{Error, Spi};
 
 pub struct SpiDriver {
     count: i32,
+    pub(super) allowed: Option<Vec<u32>>,
     spi: HashMap<i32, Spidev>
 }
 
@@ -16,13 +17,33 @@ impl SpiDriver {
     pub fn new() -> Self {
         Self{
             count: 0,
+            allowed: None,
             spi: HashMap::new()
         }
     }
+
+    /// Release SPI devices not deinitialised by the guest
+    pub(super) fn release(&mut self) {
+        let handles: Vec<i32> = self.spi.keys().cloned().collect();
+        for h in handles {
+            debug!("Releasing SPI handle: {}", h);
+            let _ = Spi::deinit(self, h);
+        }
+    }
+}
+
+impl Drop for SpiDriver {
+    fn drop(&mut self) {
+        self.release();
+    }
 }
 
 impl Spi for SpiDriver {
     fn init(&mut self, dev: u32, baud: u32, _mosi: i32, _miso: i32, _sck: i32, _cs: i32) -> Result<i32, Error> {
+        if !super::permitted(&self.allowed, &dev) {
+            error!("SPI device {} not permitted", dev);
+            return Err(Error::NoDevice);
+        }
 
         // TODO: how to deal with subdevices here?!
         let p = format!("/dev/spidev{}.{}", dev, 0);
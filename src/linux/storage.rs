@@ -0,0 +1,461 @@
+//! Linux persistent storage implementation, backed by files or an I2C EEPROM
+
+use std::{boxed::Box, collections::BTreeMap, format, fs::File, io::Write, string::String, vec, vec::Vec};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::{debug, info, warn, error};
+use embedded_hal::i2c::blocking::*;
+use linux_embedded_hal::I2cdev;
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::{Storage, valid_key};
+use crate::opts::StorageConfig;
+
+/// File-backed storage, with one file per key in a directory private to
+/// the guest
+///
+/// Values are written to a temporary file and renamed into place, so a
+/// power loss mid-write leaves either the old or the new value.
+pub struct FileStorage {
+    dir: PathBuf,
+    quota: usize,
+    used: usize,
+}
+
+impl FileStorage {
+    /// Open a storage directory (creating this if required), limiting the
+    /// total size of stored values to `quota` bytes
+    pub fn open(dir: impl AsRef<Path>, quota: usize) -> anyhow::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        debug!("Opening storage directory: {} (quota: {} bytes)", dir.display(), quota);
+
+        std::fs::create_dir_all(&dir)?;
+
+        let mut used = 0;
+        for e in std::fs::read_dir(&dir)? {
+            let e = e?;
+            let name = e.file_name();
+            let name = name.to_string_lossy();
+
+            // Temporary files are left by interrupted writes, the previous value remains
+            if name.starts_with('.') && name.ends_with(".tmp") {
+                warn!("Removing incomplete storage write: {}", name);
+                let _ = std::fs::remove_file(e.path());
+                continue;
+            }
+
+            if e.file_type()?.is_file() && valid_key(&name) {
+                used += e.metadata()?.len() as usize;
+            }
+        }
+
+        if used > quota {
+            warn!("Storage {} exceeds quota ({} of {} bytes)", dir.display(), used, quota);
+        }
+
+        Ok(Self{ dir, quota, used })
+    }
+
+    /// Resolve the file for a key, rejecting keys which could escape the directory
+    fn path(&self, key: &str) -> Result<PathBuf, Error> {
+        if !valid_key(key) {
+            error!("Invalid storage key: {:?}", key);
+            return Err(Error::InvalidArg);
+        }
+
+        Ok(self.dir.join(key))
+    }
+
+    /// Write a value via a temporary file, syncing both the file and the
+    /// directory so the rename is durable
+    fn write(&self, key: &str, path: &Path, value: &[u8]) -> std::io::Result<()> {
+        let tmp = self.dir.join(format!(".{}.tmp", key));
+
+        let mut f = File::create(&tmp)?;
+        f.write_all(value)?;
+        f.sync_all()?;
+
+        std::fs::rename(&tmp, path)?;
+
+        File::open(&self.dir)?.sync_all()
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&mut self, key: &str, buff: &mut [u8]) -> Result<Option<usize>, Error> {
+        let path = self.path(key)?;
+
+        match std::fs::read(&path) {
+            Ok(d) => {
+                let n = d.len().min(buff.len());
+                buff[..n].copy_from_slice(&d[..n]);
+                Ok(Some(d.len()))
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => {
+                error!("Failed to read storage key {}: {:?}", key, e);
+                Err(Error::Failed)
+            },
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &[u8]) -> Result<(), Error> {
+        let path = self.path(key)?;
+
+        let existing = std::fs::metadata(&path).map(|m| m.len() as usize).unwrap_or(0);
+        let used = self.used - existing.min(self.used) + value.len();
+        if used > self.quota {
+            error!("Storage quota exceeded writing {} ({} of {} bytes)", key, used, self.quota);
+            return Err(Error::Failed);
+        }
+
+        debug!("Storage set {} ({} bytes)", key, value.len());
+
+        if let Err(e) = self.write(key, &path, value) {
+            error!("Failed to write storage key {}: {:?}", key, e);
+            return Err(Error::Failed);
+        }
+
+        self.used = used;
+
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<bool, Error> {
+        let path = self.path(key)?;
+
+        let existing = std::fs::metadata(&path).map(|m| m.len() as usize).unwrap_or(0);
+
+        match std::fs::remove_file(&path) {
+            Ok(_) => {
+                debug!("Storage removed {}", key);
+                self.used -= existing.min(self.used);
+                Ok(true)
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => {
+                error!("Failed to remove storage key {}: {:?}", key, e);
+                Err(Error::Failed)
+            },
+        }
+    }
+}
+
+/// EEPROM slot magic, identifying the image format
+const EEPROM_MAGIC: [u8; 4] = *b"WKV1";
+
+/// EEPROM slot header length (magic, sequence number, payload length and CRC32)
+const EEPROM_HEADER: usize = 16;
+
+/// Maximum EEPROM read transfer length
+const EEPROM_READ_CHUNK: usize = 256;
+
+/// EEPROM page write cycle time (t_WR), during which the device does not respond
+const EEPROM_WRITE_CYCLE: Duration = Duration::from_millis(5);
+
+/// Stored values by key
+type Values = BTreeMap<String, Vec<u8>>;
+
+/// I2C EEPROM storage, for 24Cxx devices with 16-bit word addresses
+///
+/// The EEPROM is split into two slots, each holding a complete image of
+/// the store with a sequence number and CRC. Writes go to the older slot,
+/// so an interrupted write leaves the newer image intact. The usable
+/// capacity is therefore a little under half the device.
+pub struct EepromStorage {
+    dev: I2cdev,
+    addr: u8,
+    slot: usize,
+    page: usize,
+    seq: u32,
+    current: usize,
+    values: Values,
+}
+
+impl EepromStorage {
+    /// Open an EEPROM on the provided I2C device, loading the newest valid image
+    pub fn open(bus: u32, addr: u8, size: usize, page: usize) -> anyhow::Result<Self> {
+        if !(2 * (EEPROM_HEADER + 1)..=0x1_0000).contains(&size) {
+            return Err(anyhow::anyhow!("Unsupported EEPROM size: {} bytes", size));
+        }
+        if page == 0 || !page.is_power_of_two() {
+            return Err(anyhow::anyhow!("Invalid EEPROM page size: {} bytes", page));
+        }
+
+        let p = format!("/dev/i2c-{}", bus);
+        debug!("Opening EEPROM {} addr: {:#04x} (size: {} page: {})", p, addr, size, page);
+
+        let dev = I2cdev::new(p)?;
+
+        let mut s = Self{ dev, addr, slot: size / 2, page, seq: 0, current: 1, values: BTreeMap::new() };
+
+        let mut found = false;
+        for i in 0..2 {
+            let (seq, values) = match s.load(i)? {
+                Some(v) => v,
+                None => continue,
+            };
+
+            // Sequence numbers wrap, so compare by difference
+            if !found || (seq.wrapping_sub(s.seq) as i32) > 0 {
+                s.seq = seq;
+                s.current = i;
+                s.values = values;
+                found = true;
+            }
+        }
+
+        match found {
+            true => debug!("Loaded EEPROM slot {} (seq: {}, {} keys)", s.current, s.seq, s.values.len()),
+            false => info!("No valid EEPROM image found, starting empty"),
+        }
+
+        Ok(s)
+    }
+
+    /// Read from the EEPROM at the provided offset
+    fn read(&mut self, offset: usize, buff: &mut [u8]) -> anyhow::Result<()> {
+        for (i, c) in buff.chunks_mut(EEPROM_READ_CHUNK).enumerate() {
+            let a = (offset + i * EEPROM_READ_CHUNK) as u16;
+            self.dev.write_read(self.addr, &a.to_be_bytes(), c)
+                .map_err(|e| anyhow::anyhow!("EEPROM read failed: {:?}", e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Write to the EEPROM at the provided offset
+    fn write(&mut self, offset: usize, data: &[u8]) -> anyhow::Result<()> {
+        let mut o = 0;
+
+        // Page writes wrap within the page, so split these at page boundaries
+        while o < data.len() {
+            let a = offset + o;
+            let n = (self.page - a % self.page).min(data.len() - o);
+
+            let mut b = Vec::with_capacity(n + 2);
+            b.extend_from_slice(&(a as u16).to_be_bytes());
+            b.extend_from_slice(&data[o..o+n]);
+
+            self.dev.write(self.addr, &b)
+                .map_err(|e| anyhow::anyhow!("EEPROM write failed: {:?}", e))?;
+            std::thread::sleep(EEPROM_WRITE_CYCLE);
+
+            o += n;
+        }
+
+        Ok(())
+    }
+
+    /// Load the image in a slot, returning `None` if this is not valid
+    fn load(&mut self, slot: usize) -> anyhow::Result<Option<(u32, Values)>> {
+        let base = slot * self.slot;
+
+        let mut h = [0u8; EEPROM_HEADER];
+        self.read(base, &mut h)?;
+
+        if h[..4] != EEPROM_MAGIC {
+            return Ok(None);
+        }
+
+        let seq = u32::from_le_bytes([h[4], h[5], h[6], h[7]]);
+        let len = u32::from_le_bytes([h[8], h[9], h[10], h[11]]) as usize;
+        let crc = u32::from_le_bytes([h[12], h[13], h[14], h[15]]);
+
+        if len > self.slot - EEPROM_HEADER {
+            warn!("EEPROM slot {} has invalid length: {}", slot, len);
+            return Ok(None);
+        }
+
+        let mut d = vec![0u8; len];
+        self.read(base + EEPROM_HEADER, &mut d)?;
+
+        if crc32(&d) != crc {
+            warn!("EEPROM slot {} failed CRC check (seq: {})", slot, seq);
+            return Ok(None);
+        }
+
+        Ok(decode(&d).map(|v| (seq, v)))
+    }
+
+    /// Write an updated image to the older slot
+    fn commit(&mut self, values: Values) -> Result<(), Error> {
+        let payload = encode(&values);
+        if EEPROM_HEADER + payload.len() > self.slot {
+            error!("Storage quota exceeded ({} of {} bytes)", payload.len(), self.slot - EEPROM_HEADER);
+            return Err(Error::Failed);
+        }
+
+        let slot = 1 - self.current;
+        let seq = self.seq.wrapping_add(1);
+
+        let mut h = Vec::with_capacity(EEPROM_HEADER);
+        h.extend_from_slice(&EEPROM_MAGIC);
+        h.extend_from_slice(&seq.to_le_bytes());
+        h.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        h.extend_from_slice(&crc32(&payload).to_le_bytes());
+
+        // The stale header is only valid until the payload is overwritten,
+        // after which the CRC fails until the new header is written
+        let base = slot * self.slot;
+        if let Err(e) = self.write(base + EEPROM_HEADER, &payload).and_then(|_| self.write(base, &h)) {
+            error!("Failed to write EEPROM slot {}: {:?}", slot, e);
+            return Err(Error::Failed);
+        }
+
+        self.seq = seq;
+        self.current = slot;
+        self.values = values;
+
+        Ok(())
+    }
+}
+
+impl Storage for EepromStorage {
+    fn get(&mut self, key: &str, buff: &mut [u8]) -> Result<Option<usize>, Error> {
+        if !valid_key(key) {
+            error!("Invalid storage key: {:?}", key);
+            return Err(Error::InvalidArg);
+        }
+
+        let v = match self.values.get(key) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let n = v.len().min(buff.len());
+        buff[..n].copy_from_slice(&v[..n]);
+
+        Ok(Some(v.len()))
+    }
+
+    fn set(&mut self, key: &str, value: &[u8]) -> Result<(), Error> {
+        if !valid_key(key) || value.len() > u16::MAX as usize {
+            error!("Invalid storage key: {:?} or value length: {}", key, value.len());
+            return Err(Error::InvalidArg);
+        }
+
+        debug!("Storage set {} ({} bytes)", key, value.len());
+
+        let mut values = self.values.clone();
+        values.insert(key.into(), value.to_vec());
+
+        self.commit(values)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<bool, Error> {
+        if !valid_key(key) {
+            error!("Invalid storage key: {:?}", key);
+            return Err(Error::InvalidArg);
+        }
+
+        if !self.values.contains_key(key) {
+            return Ok(false);
+        }
+
+        debug!("Storage removed {}", key);
+
+        let mut values = self.values.clone();
+        values.remove(key);
+
+        self.commit(values).map(|_| true)
+    }
+}
+
+/// Encode values as a sequence of (key length, key, value length, value) entries
+fn encode(values: &Values) -> Vec<u8> {
+    let mut d = Vec::new();
+
+    for (k, v) in values {
+        d.push(k.len() as u8);
+        d.extend_from_slice(k.as_bytes());
+        d.extend_from_slice(&(v.len() as u16).to_le_bytes());
+        d.extend_from_slice(v);
+    }
+
+    d
+}
+
+/// Decode values, returning `None` if these are malformed
+fn decode(mut d: &[u8]) -> Option<Values> {
+    let mut values = BTreeMap::new();
+
+    while !d.is_empty() {
+        let n = d[0] as usize;
+        let k = std::str::from_utf8(d.get(1..1+n)?).ok()?;
+        d = &d[1+n..];
+
+        let l = u16::from_le_bytes([*d.first()?, *d.get(1)?]) as usize;
+        let v = d.get(2..2+l)?;
+        d = &d[2+l..];
+
+        values.insert(k.into(), v.to_vec());
+    }
+
+    Some(values)
+}
+
+/// Compute the IEEE 802.3 CRC32 of the provided data
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+/// Storage driver, forwarding to the configured backend
+///
+/// Storage operations fail with [`Error::Unsupported`] where no backend
+/// is configured.
+pub struct StorageDriver {
+    backend: Option<Box<dyn Storage + Send>>,
+}
+
+impl StorageDriver {
+    pub fn new() -> Self {
+        Self{ backend: None }
+    }
+
+    /// Open the storage backend described by the provided configuration
+    pub fn open(config: &StorageConfig) -> anyhow::Result<Self> {
+        let backend: Box<dyn Storage + Send> = match config {
+            StorageConfig::File{ path, quota } => Box::new(FileStorage::open(path, *quota)?),
+            StorageConfig::Eeprom{ bus, addr, size, page } => Box::new(EepromStorage::open(*bus, *addr, *size, *page)?),
+        };
+
+        Ok(Self{ backend: Some(backend) })
+    }
+
+    fn backend(&mut self) -> Result<&mut (dyn Storage + Send), Error> {
+        match &mut self.backend {
+            Some(b) => Ok(b.as_mut()),
+            None => {
+                warn!("Storage access denied, no storage configured");
+                Err(Error::Unsupported)
+            },
+        }
+    }
+}
+
+impl Storage for StorageDriver {
+    fn get(&mut self, key: &str, buff: &mut [u8]) -> Result<Option<usize>, Error> {
+        self.backend()?.get(key, buff)
+    }
+
+    fn set(&mut self, key: &str, value: &[u8]) -> Result<(), Error> {
+        self.backend()?.set(key, value)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<bool, Error> {
+        self.backend()?.remove(key)
+    }
+}
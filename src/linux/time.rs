@@ -0,0 +1,115 @@
+//! Linux time driver implementation, using the host monotonic clock
+
+use std::time::{Duration, Instant};
+
+use log::{debug, error};
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::{Time, Timers};
+
+pub struct TimeDriver {
+    count: i32,
+    start: Instant,
+    timers: Timers,
+}
+
+impl TimeDriver {
+    pub fn new() -> Self {
+        Self{
+            count: 0,
+            start: Instant::now(),
+            timers: Timers::default(),
+        }
+    }
+
+    /// Stop timers left running by the guest
+    pub(super) fn release(&mut self) {
+        self.timers = Timers::default();
+    }
+
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_micros() as u64
+    }
+}
+
+impl Time for TimeDriver {
+    fn delay_us(&mut self, us: u32) -> Result<(), Error> {
+        std::thread::sleep(Duration::from_micros(us as u64));
+        Ok(())
+    }
+
+    fn delay_ms(&mut self, ms: u32) -> Result<(), Error> {
+        std::thread::sleep(Duration::from_millis(ms as u64));
+        Ok(())
+    }
+
+    fn now_us(&mut self) -> Result<u64, Error> {
+        Ok(self.now())
+    }
+
+    fn timer_start(&mut self, period_us: u32) -> Result<i32, Error> {
+        debug!("Starting timer with period: {} us", period_us);
+
+        let idx = self.count;
+        self.timers.insert(idx, self.now(), period_us)?;
+        self.count += 1;
+
+        Ok(idx)
+    }
+
+    fn timer_stop(&mut self, handle: i32) -> Result<(), Error> {
+        debug!("Stopping timer handle: {}", handle);
+
+        self.timers.remove(handle);
+
+        Ok(())
+    }
+
+    fn timer_wait(&mut self, handle: i32) -> Result<u32, Error> {
+        let now = self.now();
+        let deadline = match self.timers.deadline(handle, now) {
+            Some(d) => d,
+            None => {
+                error!("No timer for handle: {}", handle);
+                return Err(Error::NoDevice);
+            }
+        };
+
+        if deadline > now {
+            std::thread::sleep(Duration::from_micros(deadline - now));
+        }
+
+        // Sleeps may overrun, so expirations are counted at wake
+        let n = self.timers.take(handle, self.now().max(deadline)).unwrap_or(0);
+
+        debug!("Timer wait handle: {} expirations: {}", handle, n);
+
+        Ok(n)
+    }
+
+    fn timer_poll(&mut self) -> Result<Option<i32>, Error> {
+        Ok(self.timers.poll(self.now()))
+    }
+
+    fn timer_wait_any(&mut self, timeout_us: Option<u32>) -> Result<Option<i32>, Error> {
+        let now = self.now();
+        let wake = match self.timers.wake(now, timeout_us.map(|t| now + t as u64)) {
+            Some(w) => w,
+            None => {
+                error!("No timers running");
+                return Err(Error::NoDevice);
+            }
+        };
+
+        if wake > now {
+            std::thread::sleep(Duration::from_micros(wake - now));
+        }
+
+        let handle = self.timers.poll(self.now().max(wake));
+
+        debug!("Timer wait any: {:?}", handle);
+
+        Ok(handle)
+    }
+}
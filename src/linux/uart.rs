@@ -12,6 +12,7 @@ use wasm_embedded_spec::{Error, Uart};
 
 pub struct UartDriver {
     count: i32,
+    pub(super) allowed: Option<Vec<u32>>,
     uart: HashMap<i32, Serial>
 }
 
@@ -19,14 +20,34 @@ impl UartDriver {
     pub fn new() -> Self {
         Self{
             count: 0,
+            allowed: None,
             uart: HashMap::new()
         }
     }
+
+    /// Close any ports left open on drop
+    pub(super) fn release(&mut self) {
+        let handles: Vec<i32> = self.uart.keys().cloned().collect();
+        for h in handles {
+            debug!("Releasing UART handle: {}", h);
+            let _ = Uart::deinit(self, h);
+        }
+    }
+}
+
+impl Drop for UartDriver {
+    fn drop(&mut self) {
+        self.release();
+    }
 }
 
 impl Uart for UartDriver {
     fn init(&mut self, dev: u32, _baud: u32, _tx: i32, _rx: i32) -> Result<i32, Error> {
-        
+        if !super::permitted(&self.allowed, &dev) {
+            error!("UART device {} not permitted", dev);
+            return Err(Error::NoDevice);
+        }
+
         // TODO: swap to string for naming... easier to reverse than otherwise
         let p = format!("/dev/tty{}", dev);
         debug!("Opening UART device: {}", p);
@@ -0,0 +1,79 @@
+//! Linux watchdog implementation, using the `/dev/watchdog` interface
+
+use std::{fs::File, io::Write, path::Path};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::Watchdog;
+use crate::watchdog::{Backend, WatchdogHandle};
+
+/// `WDIOC_SETTIMEOUT` from `linux/watchdog.h`
+const WDIOC_SETTIMEOUT: u32 = 0xC004_5706;
+
+/// Watchdog device backend
+///
+/// Once opened the device must be petted within its timeout, or the
+/// system is reset. Devices are only disarmed by [`Backend::disarm`]
+/// (the magic close), and remain armed if the runtime exits uncleanly.
+pub struct WatchdogDevice {
+    file: File,
+}
+
+impl WatchdogDevice {
+    /// Open a watchdog device, setting its timeout
+    pub fn open(path: impl AsRef<Path>, timeout: Duration) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        debug!("Opening watchdog: {} (timeout: {:?})", path.display(), timeout);
+
+        let file = std::fs::OpenOptions::new().write(true).open(path)?;
+
+        // Not all devices support setting the timeout, in which case the default is used
+        let mut t = timeout.as_secs().max(1) as libc::c_int;
+        let res = unsafe { libc::ioctl(file.as_raw_fd(), WDIOC_SETTIMEOUT as _, &mut t as *mut libc::c_int) };
+        if res < 0 {
+            warn!("Failed to set watchdog timeout: {:?}", std::io::Error::last_os_error());
+        } else if t as u64 != timeout.as_secs() {
+            warn!("Watchdog timeout set to {} s", t);
+        }
+
+        Ok(Self{ file })
+    }
+}
+
+impl Backend for WatchdogDevice {
+    fn pet(&mut self) -> anyhow::Result<()> {
+        self.file.write_all(b"k")?;
+        Ok(())
+    }
+
+    fn disarm(&mut self) -> anyhow::Result<()> {
+        self.file.write_all(b"V")?;
+        Ok(())
+    }
+}
+
+pub struct WatchdogDriver {
+    pub(super) handle: Option<WatchdogHandle>,
+}
+
+impl WatchdogDriver {
+    pub fn new() -> Self {
+        Self{ handle: None }
+    }
+}
+
+impl Watchdog for WatchdogDriver {
+    fn kick(&mut self) -> Result<(), Error> {
+        // Kicks are ignored where no watchdog is configured
+        match &self.handle {
+            Some(h) => h.kick(),
+            None => debug!("Watchdog kick ignored, no watchdog configured"),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+//! Guest logging, routing guest messages into the host `log` facade
+//!
+//! Messages are logged with the target `guest::<module>` and prefixed with
+//! the module name, so these respect the host log level and may be told
+//! apart when supervising multiple modules. Each module applies its own
+//! level filter, then a rate limit so a chatty guest cannot flood the host
+//! logs, summarising suppressed messages once logging resumes.
+
+use std::{format, string::String};
+use std::time::Instant;
+
+use log::{Level, LevelFilter, warn};
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::Logger;
+
+/// Default guest log rate limit in messages per second
+pub const LOG_RATE: u32 = 50;
+
+/// Maximum guest log message length in bytes, longer messages are truncated
+pub const LOG_MAX_LEN: usize = 512;
+
+/// Guest logging configuration
+#[derive(Clone, PartialEq, Debug)]
+pub struct LogConfig {
+    /// Maximum guest log level, further limited by the host log level
+    pub level: LevelFilter,
+    /// Messages per second, allowing bursts of up to one second of
+    /// messages (unlimited where zero)
+    pub rate: u32,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self{ level: LevelFilter::Trace, rate: LOG_RATE }
+    }
+}
+
+/// Guest logger for a single module
+pub struct GuestLog {
+    module: String,
+    target: String,
+    config: LogConfig,
+    tokens: f64,
+    last: Instant,
+    suppressed: u64,
+    dropped: u64,
+}
+
+impl GuestLog {
+    /// Create a logger for the named module
+    pub fn new(module: &str, config: LogConfig) -> Self {
+        Self{
+            module: module.into(),
+            target: format!("guest::{}", module),
+            tokens: config.rate as f64,
+            config,
+            last: Instant::now(),
+            suppressed: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Fetch the total number of messages dropped by rate limiting
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Take a token for a message, returning whether this may be logged
+    fn acquire(&mut self) -> bool {
+        if self.config.rate == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        let rate = self.config.rate as f64;
+        self.tokens = (self.tokens + now.duration_since(self.last).as_secs_f64() * rate).min(rate);
+        self.last = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+
+    /// Report messages suppressed since the last logged message
+    fn flush(&mut self) {
+        if self.suppressed > 0 {
+            warn!(target: &self.target, "[{}] {} messages suppressed by rate limit", self.module, self.suppressed);
+            self.suppressed = 0;
+        }
+    }
+}
+
+impl Logger for GuestLog {
+    fn write(&mut self, level: Level, message: &str) -> Result<(), Error> {
+        // Filtered messages are not counted against the rate limit
+        if level > self.config.level || level > log::max_level() {
+            return Ok(());
+        }
+
+        if !self.acquire() {
+            self.suppressed += 1;
+            self.dropped += 1;
+            return Ok(());
+        }
+
+        self.flush();
+
+        log::log!(target: &self.target, level, "[{}] {}", self.module, sanitise(message));
+
+        Ok(())
+    }
+}
+
+impl Drop for GuestLog {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Truncate a guest message and escape control characters, so a message
+/// always occupies a single log line
+fn sanitise(message: &str) -> String {
+    let mut s = String::with_capacity(message.len().min(LOG_MAX_LEN));
+
+    for (i, c) in message.char_indices() {
+        if i + c.len_utf8() > LOG_MAX_LEN {
+            s.push_str("...");
+            break;
+        }
+
+        match c.is_control() {
+            true => s.extend(c.escape_default()),
+            false => s.push(c),
+        }
+    }
+
+    s
+}
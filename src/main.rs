@@ -1,40 +1,162 @@
 
+use std::time::{Duration, Instant};
+
 use clap::Parser;
 use strum::{Display, EnumString, EnumVariantNames};
-use log::{LevelFilter, debug};
+use log::{LevelFilter, debug, info, warn};
 
-use wasm_embedded_rt::opts::*;
+use wasm_embedded_rt::{Server, StopToken, server::ServeConfig, opts::*, limits::LimitExceeded, module::{Guest, Exit, Trap}};
+use wasm_embedded_rt::logging::{GuestLog, LogConfig, LOG_RATE};
+use wasm_embedded_rt::trace::{Traced, FileSink};
 
 #[cfg(feature="hal-mock")]
-use wasm_embedded_rt::mock::MockCtx;
+use wasm_embedded_rt::mock::{MockCtx, MockHandle, Summary};
 
 #[cfg(feature="hal-linux")]
-use wasm_embedded_rt::linux::LinuxCtx;
-
-#[cfg(feature="rt-wasm3")]
-use wasm_embedded_rt_wasm3::{Wasm3Runtime};
+use wasm_embedded_rt::linux::{LinuxCtx, StorageDriver, WatchdogDevice};
 
-#[cfg(feature="rt-wasmtime")]
-use wasm_embedded_rt_wasmtime::{WasmtimeRuntime};
+#[cfg(feature="hal-linux")]
+use wasm_embedded_rt::watchdog::{Watchdog, Backend, SoftwareWatchdog};
 
 
 #[derive(Clone, PartialEq, Debug, Parser)]
 struct Args {
     /// Backing engine
-    #[clap(long, value_enum)]
+    #[clap(long, value_enum, default_value_t)]
     engine: Engine,
 
     /// WASM Runtime
-    #[clap(long, value_enum)]
+    #[clap(long, value_enum, default_value_t)]
     runtime: Runtime,
 
+    /// Supervisor manifest, executing the listed modules concurrently
+    #[clap(long, conflicts_with = "bin")]
+    manifest: Option<String>,
+
+    /// Reload the WASM binary whenever this is modified
+    #[clap(long)]
+    watch: bool,
+
+    /// Listen on the provided port for WASM binaries, each replacing the executing instance
+    #[clap(long, conflicts_with_all = ["bin", "manifest", "watch"])]
+    serve: Option<u16>,
+
+    /// Address to listen on when serving
+    #[clap(long, default_value = "127.0.0.1")]
+    serve_addr: std::net::IpAddr,
+
+    /// Token clients must present when serving (falls back to `WASME_SERVE_TOKEN`)
+    #[clap(long)]
+    serve_token: Option<String>,
+
     /// Optional configuration file
     #[clap(long)]
     config: Option<String>,
 
+    /// Mock configuration format (detected from file extension if unset)
+    #[clap(long, value_enum)]
+    mock_format: Option<Format>,
+
+    /// Record executed mock operations to the provided file
+    #[clap(long)]
+    record: Option<String>,
+
+    /// Mock recording format (detected from file extension if unset)
+    #[clap(long, value_enum)]
+    record_format: Option<Format>,
+
+    /// Write the mock divergence report to the provided file on mismatch
+    #[clap(long)]
+    diff: Option<String>,
+
+    /// Mock divergence report format (detected from file extension if unset)
+    #[clap(long, value_enum)]
+    diff_format: Option<Format>,
+
+    /// Write a mock run summary to the provided file (JUnit XML for `.xml`, otherwise JSON)
+    #[clap(long)]
+    report: Option<String>,
+
+    /// Mock run summary format (detected from file extension if unset)
+    #[clap(long, value_enum)]
+    report_format: Option<ReportFormat>,
+
+    /// Guest instruction budget (fuel), unlimited if unset
+    #[clap(long)]
+    fuel: Option<u64>,
+
+    /// Guest wall-clock timeout in seconds, unlimited if unset
+    #[clap(long, value_parser = parse_secs)]
+    timeout: Option<Duration>,
+
+    /// Maximum guest linear memory in 64KiB pages, unlimited if unset
+    #[clap(long)]
+    max_memory_pages: Option<u32>,
+
+    /// Maximum guest table size in elements, unlimited if unset
+    #[clap(long)]
+    max_table_elements: Option<u32>,
+
+    /// Maximum concurrently executing guest instances, unlimited if unset
+    #[clap(long)]
+    max_instances: Option<usize>,
+
+    /// Watchdog device (e.g. `/dev/watchdog`), or `software` for a software watchdog
+    #[clap(long)]
+    watchdog: Option<String>,
+
+    /// Watchdog timeout in seconds
+    #[clap(long, default_value_t = 10)]
+    watchdog_timeout: u64,
+
+    /// Watchdog petting mode
+    #[clap(long, value_enum, default_value_t)]
+    watchdog_mode: WatchdogMode,
+
+    /// Permit guests to bit-bang 1-Wire buses on GPIO pins (best-effort
+    /// timing, kernel w1-gpio bus masters should be preferred)
+    #[clap(long)]
+    onewire_bitbang: bool,
+
+    /// Guest storage directory, storage is unavailable to the guest if unset
+    #[clap(long)]
+    storage: Option<String>,
+
+    /// Guest storage quota in bytes
+    #[clap(long, default_value_t = STORAGE_QUOTA)]
+    storage_quota: usize,
+
+    /// Maximum guest log level, further limited by --log-level
+    #[clap(long, default_value = "trace")]
+    guest_log_level: LevelFilter,
+
+    /// Guest log rate limit in messages per second (unlimited if zero)
+    #[clap(long, default_value_t = LOG_RATE)]
+    guest_log_rate: u32,
+
+    /// Write a trace of peripheral operations to the provided file (JSON lines)
+    #[clap(long)]
+    trace: Option<String>,
+
+    /// Guest export to invoke in place of `_start`
+    #[clap(long)]
+    invoke: Option<String>,
+
+    /// Guest environment variables (KEY=VALUE)
+    #[clap(long = "env", value_parser = parse_env)]
+    env: Vec<(String, String)>,
+
+    /// Directories preopened for the guest via WASI, at the same path
+    #[clap(long = "dir")]
+    dirs: Vec<std::path::PathBuf>,
+
     /// WASM binary to execute
-    #[clap()]
-    bin: String,
+    #[clap(required_unless_present_any = ["manifest", "serve"])]
+    bin: Option<String>,
+
+    /// Arguments passed to the guest
+    #[clap(last = true)]
+    args: Vec<String>,
 
     #[clap(long = "log-level", default_value = "info")]
     /// Configure app logging levels (warn, info, debug, trace)
@@ -58,8 +180,25 @@ pub enum Mode {
     },
 }
 
+/// Maximum guest exit code passed through to the process exit status,
+/// guest codes outside `1..=EXIT_GUEST_MAX` being reported as this so
+/// these never collide with the host codes below
+const EXIT_GUEST_MAX: i32 = 123;
+
+/// Exit code for guest execution limits being exceeded (matching `timeout`)
+const EXIT_LIMIT_EXCEEDED: i32 = 124;
+
+/// Environment variable providing the serve token
+const SERVE_TOKEN_ENV: &str = "WASME_SERVE_TOKEN";
+
+/// Exit code for failed execution
+const EXIT_FAILED: i32 = 125;
+
+/// Exit code for guest traps (matching a native process aborting, 128 + SIGABRT)
+const EXIT_TRAP: i32 = 134;
+
 #[tokio::main]
-async fn main() -> Result<(), anyhow::Error> {
+async fn main() {
     // Load options
     let opts = Args::parse();
 
@@ -71,54 +210,200 @@ async fn main() -> Result<(), anyhow::Error> {
         .build();
     let _ = simplelog::SimpleLogger::init(opts.log_level, log_config);
 
-    // Load WASM binary
-    debug!("Loading WASM binary: {}", opts.bin);
-    let bin = std::fs::read(opts.bin)?;
+    // Execute and map errors to exit codes, guest exit codes are passed
+    // through where these do not collide with host codes
+    let res = match &opts.manifest {
+        Some(m) => supervise(m).await,
+        None if opts.watch || opts.serve.is_some() => reload(&opts),
+        None => execute(&opts),
+    };
+
+    if let Err(e) = res {
+        let code = if let Some(Exit(c)) = e.downcast_ref::<Exit>() {
+            info!("Guest exited with code {}", c);
+            match *c {
+                c @ 1..=EXIT_GUEST_MAX => c,
+                c => {
+                    warn!("Guest exit code {} out of range, exiting with {}", c, EXIT_GUEST_MAX);
+                    EXIT_GUEST_MAX
+                },
+            }
+        } else if e.is::<LimitExceeded>() {
+            eprintln!("Error: {:?}", e);
+            EXIT_LIMIT_EXCEEDED
+        } else if let Some(t) = e.downcast_ref::<Trap>() {
+            eprintln!("Error: {:#}", e);
+            if let Some(b) = &t.backtrace {
+                eprintln!("Guest backtrace:\n{}", b);
+            }
+            EXIT_TRAP
+        } else {
+            eprintln!("Error: {:?}", e);
+            EXIT_FAILED
+        };
+
+        std::process::exit(code);
+    }
+}
+
+/// Execute the modules listed in a supervisor manifest
+#[cfg(feature="supervisor")]
+async fn supervise(manifest: &str) -> Result<(), anyhow::Error> {
+    let s = wasm_embedded_rt::supervisor::Supervisor::load(manifest)?;
+    s.run().await
+}
+
+#[cfg(not(feature="supervisor"))]
+async fn supervise(_manifest: &str) -> Result<(), anyhow::Error> {
+    Err(anyhow::anyhow!("Runtime was not built with supervisor enabled"))
+}
+
+/// Execute WASM binaries using a server, reloading these on modification
+/// or when received from a remote client
+fn reload(opts: &Args) -> Result<(), anyhow::Error> {
+    let config = Config{
+        runtime: opts.runtime.clone(),
+        engine: opts.engine.clone(),
+        limits: limits(opts),
+    };
+    let guest = guest(opts, opts.bin.as_deref().unwrap_or("guest"));
 
     #[allow(unreachable_patterns)]
-    match (&opts.runtime, &opts.engine) {
-        #[cfg(all(feature="rt-wasmtime", feature="hal-mock"))]
-        (Runtime::Wasmtime, Engine::Mock) => {
-            // Load mock configuration
-            let cfg = match &opts.config {
-                Some(c) => c,
-                None => return Err(anyhow::anyhow!("mock mode requires --config file")),
+    match &opts.engine {
+        #[cfg(feature="hal-mock")]
+        Engine::Mock => {
+            // Each instance replays the mock configuration
+            let o = opts.clone();
+            let s = Server::new(config, move |stop| Ok(load_mock(&o)?.with_stop(stop)));
+            run_server(s.with_guest(guest), opts)
+        },
+        #[cfg(feature="hal-linux")]
+        Engine::Linux => {
+            let o = opts.clone();
+            let s = Server::new(config, move |stop| Ok(LinuxCtx::new().with_logger(logger(&o)).with_onewire_bitbang(o.onewire_bitbang).with_stop(stop)));
+            run_server(s.with_guest(guest), opts)
+        },
+        _ => {
+            Err(anyhow::anyhow!("Runtime was not built with {}:{} enabled", opts.runtime, opts.engine))
+        },
+    }
+}
+
+/// Run a server, serving or watching as configured
+fn run_server<E: wasm_embedded_rt::EngineExt + Send + 'static>(mut s: Server<E>, opts: &Args) -> Result<(), anyhow::Error> {
+    match (opts.serve, &opts.bin) {
+        (Some(port), _) => {
+            let token = match opts.serve_token.clone().or_else(|| std::env::var(SERVE_TOKEN_ENV).ok()) {
+                Some(t) => t,
+                None => return Err(anyhow::anyhow!("Serving requires a token (--serve-token or {})", SERVE_TOKEN_ENV)),
             };
-            let ctx = MockCtx::load(&cfg)?;
-            let mut rt = WasmtimeRuntime::new(ctx, &bin)?;
 
-            rt.run()?;
+            let listener = std::net::TcpListener::bind((opts.serve_addr, port))?;
+            s.serve(listener, &ServeConfig::new(token), &StopToken::new())
         },
-        #[cfg(all(feature="rt-wasmtime", feature="hal-linux"))]
-        (Runtime::Wasmtime, Engine::Linux) => {
-            // Load linux configuration
-            // TODO: config files?
-            let ctx = LinuxCtx::new();
-            let mut rt = WasmtimeRuntime::new(ctx, &bin)?;
+        (None, Some(bin)) => s.watch(bin, &StopToken::new()),
+        (None, None) => Err(anyhow::anyhow!("No WASM binary provided")),
+    }
+}
 
-            rt.run()?;
-        },
-        #[cfg(all(feature="rt-wasm3", feature="hal-mock"))]
-        (Runtime::Wasm3, Engine::Mock) => {
-            // Load mock configuration
-            let cfg = match &opts.config {
-                Some(c) => c,
-                None => return Err(anyhow::anyhow!("mock mode requires --config file")),
+/// Build a guest logger from the provided arguments, tagged with the
+/// binary name
+fn logger(opts: &Args) -> GuestLog {
+    let name = opts.bin.as_deref()
+        .and_then(|b| std::path::Path::new(b).file_stem())
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "guest".into());
+
+    GuestLog::new(&name, LogConfig{ level: opts.guest_log_level, rate: opts.guest_log_rate })
+}
+
+/// Build guest execution limits from the provided arguments
+fn limits(opts: &Args) -> Limits {
+    Limits{
+        fuel: opts.fuel,
+        timeout: opts.timeout,
+        max_memory_pages: opts.max_memory_pages,
+        max_table_elements: opts.max_table_elements,
+        max_instances: opts.max_instances,
+        ..Default::default()
+    }
+}
+
+/// Build guest invocation options from the provided arguments
+fn guest(opts: &Args, path: &str) -> Guest {
+    Guest{
+        invoke: opts.invoke.clone(),
+        args: std::iter::once(path.to_string()).chain(opts.args.iter().cloned()).collect(),
+        env: opts.env.clone(),
+        dirs: opts.dirs.clone(),
+    }
+}
+
+/// Load and execute a WASM binary using the provided options
+fn execute(opts: &Args) -> Result<(), anyhow::Error> {
+    let path = match &opts.bin {
+        Some(b) => b,
+        None => return Err(anyhow::anyhow!("No WASM binary provided")),
+    };
+
+    let limits = limits(opts);
+    let guest = guest(opts, path);
+
+    // Engines are bound to the stop token, so timeouts refuse further
+    // driver access under runtimes which cannot interrupt guests
+    let stop = StopToken::new();
+
+    #[allow(unreachable_patterns)]
+    match &opts.engine {
+        #[cfg(feature="hal-mock")]
+        Engine::Mock => {
+            // Load binary and mock configuration, reporting failures as
+            // these occur prior to verification
+            let start = Instant::now();
+            let loaded = load_bin(path, &guest)
+                .and_then(|b| Ok((b, load_mock(opts)?.with_stop(stop.clone()))));
+            let (bin, ctx) = match loaded {
+                Ok(v) => v,
+                Err(e) => {
+                    report_failure(opts, path, &e, start.elapsed());
+                    return Err(e);
+                },
             };
-            let mut ctx = MockCtx::load(&cfg)?;
-            let mut rt = Wasm3Runtime::new(&mut ctx, &bin)?;
-            
-            // TODO: bind drivers
+            let handle = ctx.handle();
 
-            rt.run()?;
+            let res = run_traced(opts, ctx, &bin, &guest, &limits, &stop);
+            check_mock(opts, path, &handle, res, start.elapsed())?;
         },
-        #[cfg(all(feature="rt-wasm3", feature="hal-linux"))]
-        (Runtime::Wasm3, Engine::Linux) => {
+        #[cfg(feature="hal-linux")]
+        Engine::Linux => {
+            let bin = load_bin(path, &guest)?;
+
             // Load linux configuration
             // TODO: config files?
-            let mut ctx = LinuxCtx::new();
-            let mut rt = Wasm3Runtime::new(&mut ctx, &bin)?;
-            rt.run()?;
+            let ctx = LinuxCtx::new().with_logger(logger(opts))
+                .with_onewire_bitbang(opts.onewire_bitbang)
+                .with_stop(stop.clone());
+            let ctx = match &opts.storage {
+                Some(path) => ctx.with_storage(StorageDriver::open(&StorageConfig::File{ path: path.into(), quota: opts.storage_quota })?),
+                None => ctx,
+            };
+
+            let mut watchdog = watchdog(opts)?;
+            let ctx = match &watchdog {
+                Some(w) => ctx.with_watchdog(w.handle()),
+                None => ctx,
+            };
+
+            let res = run_traced(opts, ctx, &bin, &guest, &limits, &stop);
+
+            // Leave the watchdog to expire unless the guest exited cleanly
+            match (watchdog.take(), &res) {
+                (Some(w), Ok(_)) => w.close()?,
+                (Some(mut w), Err(_)) => w.fail(),
+                (None, _) => (),
+            }
+
+            res?;
         },
         _ => {
             return Err(anyhow::anyhow!("Runtime was not built with {}:{} enabled", opts.runtime, opts.engine))
@@ -127,3 +412,114 @@ async fn main() -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+/// Load a WASM binary, applying guest entrypoint, arguments and environment
+fn load_bin(path: &str, guest: &Guest) -> Result<Vec<u8>, anyhow::Error> {
+    debug!("Loading WASM binary: {}", path);
+    let bin = std::fs::read(path)?;
+
+    guest.apply(&bin)
+}
+
+/// Execute a WASM binary, tracing peripheral operations if enabled
+fn run_traced<E: wasm_embedded_rt::EngineExt + Send + 'static>(opts: &Args, ctx: E, bin: &[u8], guest: &Guest, limits: &Limits, stop: &StopToken) -> Result<(), anyhow::Error> {
+    match &opts.trace {
+        Some(p) => wasm_embedded_rt::run_limited(&opts.runtime, Traced::new(ctx, FileSink::create(p)?), bin, guest, limits, stop),
+        None => wasm_embedded_rt::run_limited(&opts.runtime, ctx, bin, guest, limits, stop),
+    }
+}
+
+/// Start the watchdog service configured by the provided arguments
+#[cfg(feature="hal-linux")]
+fn watchdog(opts: &Args) -> Result<Option<Watchdog>, anyhow::Error> {
+    let timeout = Duration::from_secs(opts.watchdog_timeout);
+
+    let backend: Box<dyn Backend> = match opts.watchdog.as_deref() {
+        None => return Ok(None),
+        Some("software") => Box::new(SoftwareWatchdog::new(timeout)),
+        Some(path) => Box::new(WatchdogDevice::open(path, timeout)?),
+    };
+
+    // Pet at a fraction of the timeout to tolerate scheduling delays
+    Ok(Some(Watchdog::new(backend, opts.watchdog_mode, (timeout / 4).max(Duration::from_millis(10)))))
+}
+
+/// Parse a period in seconds
+fn parse_secs(s: &str) -> Result<Duration, String> {
+    let v: f64 = s.parse().map_err(|_| format!("invalid period '{}', expected seconds", s))?;
+    secs(v)
+}
+
+/// Parse a KEY=VALUE environment variable
+fn parse_env(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.is_empty() => Ok((k.into(), v.into())),
+        _ => Err(format!("invalid environment variable '{}', expected KEY=VALUE", s)),
+    }
+}
+
+/// Load mock configuration from the provided arguments
+#[cfg(feature="hal-mock")]
+fn load_mock(opts: &Args) -> Result<MockCtx, anyhow::Error> {
+    let cfg = match &opts.config {
+        Some(c) => c,
+        None => return Err(anyhow::anyhow!("mock mode requires --config file")),
+    };
+
+    let mut ctx = MockCtx::load_format(cfg, opts.mock_format)?.with_logger(logger(opts));
+    if let Some(r) = &opts.record {
+        ctx.record(r, opts.record_format);
+    }
+
+    Ok(ctx)
+}
+
+/// Fetch the summary report class for the configured runtime and engine
+#[cfg(feature="hal-mock")]
+fn report_class(opts: &Args) -> String {
+    format!("{}.{}", opts.runtime, opts.engine)
+}
+
+/// Write a summary report for a mock run failing prior to execution,
+/// if enabled
+#[cfg(feature="hal-mock")]
+fn report_failure(opts: &Args, path: &str, error: &anyhow::Error, elapsed: Duration) {
+    if let Some(p) = &opts.report {
+        let s = Summary::failed(path, &report_class(opts), error, elapsed);
+        if let Err(e) = s.store(p, opts.report_format) {
+            log::error!("Failed to write report: {:?}", e);
+        }
+    }
+}
+
+/// Verify mock operations following execution, writing a summary
+/// report if enabled
+///
+/// Mismatches are reported in preference to runtime errors, as failed
+/// mock operations are generally the cause of these, excepting exceeded
+/// limits which truncate execution.
+#[cfg(feature="hal-mock")]
+fn check_mock(opts: &Args, path: &str, handle: &MockHandle, res: Result<(), anyhow::Error>, elapsed: Duration) -> Result<(), anyhow::Error> {
+    let report = handle.verify();
+
+    if let Some(p) = &opts.report {
+        let s = Summary::new(path, &report_class(opts), &report, res.as_ref().err(), elapsed);
+        s.store(p, opts.report_format)?;
+    }
+
+    if let (Some(p), Err(m)) = (&opts.diff, &report) {
+        m.store(p, opts.diff_format)?;
+    }
+
+    if let Err(e) = &res {
+        if e.is::<LimitExceeded>() {
+            return res;
+        }
+    }
+
+    let report = report?;
+
+    info!("Mock run complete, {} operations matched in {:?}", report.matched, elapsed);
+
+    res
+}
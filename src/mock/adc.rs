@@ -0,0 +1,84 @@
+//! Mock ADC driver implementation, returning scripted samples
+
+use std::sync::{Arc, Mutex};
+
+use log::debug;
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::Adc;
+use super::{Inner, Kind};
+
+pub struct MockAdc {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl MockAdc {
+    pub(crate) const fn new(inner: Arc<Mutex<Inner>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Adc for MockAdc {
+    fn init(&mut self, device: u32, channel: u32) -> Result<i32, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("Configuring ADC device: {} channel: {}", device, channel);
+
+        inner.push_actual(Kind::AdcInit{device, channel})
+    }
+
+    fn deinit(&mut self, handle: i32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("Closing ADC handle: {}", handle);
+
+        inner.push_actual(Kind::AdcDeinit{handle})?;
+
+        Ok(())
+    }
+
+    fn read_raw(&mut self, handle: i32) -> Result<i32, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let value = match inner.next() {
+            Some(Kind::AdcReadRaw{value, ..}) => *value,
+            _ => 0,
+        };
+
+        debug!("ADC read raw handle: {} value: {}", handle, value);
+
+        inner.push_actual(Kind::AdcReadRaw{handle, value})?;
+
+        Ok(value)
+    }
+
+    fn read(&mut self, handle: i32) -> Result<f32, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let value = match inner.next() {
+            Some(Kind::AdcRead{value, ..}) => *value,
+            _ => 0.0,
+        };
+
+        debug!("ADC read handle: {} value: {} mV", handle, value);
+
+        inner.push_actual(Kind::AdcRead{handle, value})?;
+
+        Ok(value)
+    }
+
+    fn capture(&mut self, handle: i32, trigger: u32, samples: &mut [i32]) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some(Kind::AdcCapture{samples: s, ..}) = inner.next() {
+            super::fill(samples, s);
+        }
+
+        debug!("ADC capture handle: {} trigger: {} samples: {:?}", handle, trigger, samples);
+
+        inner.push_actual(Kind::AdcCapture{handle, trigger, samples: samples.to_vec()})?;
+
+        Ok(())
+    }
+}
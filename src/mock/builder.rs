@@ -0,0 +1,75 @@
+//! Mock context builder for programmatic configuration
+
+use std::{collections::BTreeMap, string::String, vec::Vec};
+
+use super::{MockConfig, MockCtx, Op, Kind, Timing};
+
+/// Builder for mock contexts, allowing expectations to be defined in code
+///
+/// ```no_run
+/// use wasm_embedded_rt::mock::{MockCtx, Kind};
+///
+/// let ctx = MockCtx::builder()
+///     .expect(Kind::I2cInit{ port: 1, baud: 100_000, sda: 2, scl: 3 }).returns(0)
+///     .expect(Kind::I2cWrite{ handle: 0, addr: 0x40, data_out: vec![0x01] })
+///     .build();
+/// ```
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct MockBuilder {
+    ops: Vec<Op>,
+    storage: BTreeMap<String, Vec<u8>>,
+    expect_storage: Option<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MockBuilder {
+    /// Append an expected operation (returning 0 unless overridden)
+    pub fn expect(mut self, kind: Kind) -> Self {
+        self.ops.push(Op{ kind, res: 0, timing: None });
+        self
+    }
+
+    /// Set the result returned for the most recently added operation
+    ///
+    /// This has no effect if no operations have been added.
+    pub fn returns(mut self, res: i32) -> Self {
+        if let Some(op) = self.ops.last_mut() {
+            op.res = res;
+        }
+        self
+    }
+
+    /// Set a timing constraint for the most recently added operation
+    ///
+    /// This has no effect if no operations have been added.
+    pub fn timing(mut self, timing: Timing) -> Self {
+        if let Some(op) = self.ops.last_mut() {
+            op.timing = Some(timing);
+        }
+        self
+    }
+
+    /// Set an initial storage value
+    pub fn storage(mut self, key: &str, value: &[u8]) -> Self {
+        self.storage.insert(key.into(), value.to_vec());
+        self
+    }
+
+    /// Expect a storage value on completion
+    ///
+    /// Once any value is expected, the final storage contents must match
+    /// the expected values exactly.
+    pub fn expect_storage(mut self, key: &str, value: &[u8]) -> Self {
+        self.expect_storage.get_or_insert_with(BTreeMap::new).insert(key.into(), value.to_vec());
+        self
+    }
+
+    /// Fetch the mock configuration described by the builder
+    pub fn config(self) -> MockConfig {
+        MockConfig{ ops: self.ops, storage: self.storage, expect_storage: self.expect_storage }
+    }
+
+    /// Build a mock context from the configured expectations
+    pub fn build(self) -> MockCtx {
+        MockCtx::from_config(self.config())
+    }
+}
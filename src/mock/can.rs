@@ -0,0 +1,83 @@
+//! Mock CAN driver implementation, receiving scripted frames
+
+use std::sync::{Arc, Mutex};
+
+use log::{debug, error};
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::{Can, Frame, Filter};
+use super::{Inner, Kind, CanFrame};
+
+pub struct MockCan {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl MockCan {
+    pub(crate) const fn new(inner: Arc<Mutex<Inner>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Can for MockCan {
+    fn init(&mut self, port: u32, fd: bool) -> Result<i32, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("Configuring CAN port: {} fd: {}", port, fd);
+
+        inner.push_actual(Kind::CanInit{port, fd})
+    }
+
+    fn deinit(&mut self, handle: i32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("Closing CAN handle: {}", handle);
+
+        inner.push_actual(Kind::CanDeinit{handle})?;
+
+        Ok(())
+    }
+
+    fn set_filters(&mut self, handle: i32, filters: &[Filter]) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("CAN set filters handle: {} filters: {:?}", handle, filters);
+
+        inner.push_actual(Kind::CanSetFilters{handle, filters: filters.to_vec()})?;
+
+        Ok(())
+    }
+
+    fn send(&mut self, handle: i32, frame: &Frame) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("CAN send handle: {} frame: {:?}", handle, frame);
+
+        inner.push_actual(Kind::CanSend{handle, frame: frame.into()})?;
+
+        Ok(())
+    }
+
+    fn receive(&mut self, handle: i32, timeout_ms: Option<u32>) -> Result<Option<Frame>, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        // Scripted frames are returned immediately, `None` simulating a timeout
+        let frame: Option<CanFrame> = match inner.next() {
+            Some(Kind::CanReceive{frame, ..}) => frame.clone(),
+            _ => None,
+        };
+
+        debug!("CAN receive handle: {} timeout: {:?} frame: {:?}", handle, timeout_ms, frame);
+
+        inner.push_actual(Kind::CanReceive{handle, timeout_ms, frame: frame.clone()})?;
+
+        match frame.map(|f| f.frame()) {
+            Some(Some(f)) => Ok(Some(f)),
+            Some(None) => {
+                error!("Invalid scripted CAN frame");
+                Err(Error::InvalidArg)
+            },
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Mock file format handling
+
+use std::{path::Path, vec::Vec};
+
+use serde::{Serialize, de::DeserializeOwned};
+use log::debug;
+
+pub use crate::opts::Format;
+
+impl Format {
+    /// Detect file format from a path extension
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?;
+
+        match ext.to_lowercase().as_str() {
+            "toml" => Some(Format::Toml),
+            "json" => Some(Format::Json),
+            "yaml" | "yml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Decode an object from the provided data
+    pub fn decode<T: DeserializeOwned>(&self, d: &[u8]) -> anyhow::Result<T> {
+        let v = match self {
+            Format::Toml => toml::from_slice(d)?,
+            Format::Json => serde_json::from_slice(d)?,
+            Format::Yaml => serde_yaml::from_slice(d)?,
+        };
+        Ok(v)
+    }
+
+    /// Encode an object to the provided format
+    ///
+    /// TOML requires values to precede tables, so objects are converted to
+    /// a TOML value prior to encoding, which emits nested tables (such as
+    /// CAN frames in mock operations) after the scalars of each table.
+    pub fn encode<T: Serialize>(&self, v: &T) -> anyhow::Result<Vec<u8>> {
+        let d = match self {
+            Format::Toml => toml::to_vec(&toml::Value::try_from(v)?)?,
+            Format::Json => serde_json::to_vec_pretty(v)?,
+            Format::Yaml => serde_yaml::to_string(v)?.into_bytes(),
+        };
+        Ok(d)
+    }
+
+    /// Load an object from a file, detecting the format from the extension
+    /// where not provided
+    pub fn load<T: DeserializeOwned>(path: impl AsRef<Path>, format: Option<Format>) -> anyhow::Result<T> {
+        let path = path.as_ref();
+        let format = format.or_else(|| Format::from_path(path)).unwrap_or_default();
+
+        debug!("Loading {} file: {}", format, path.display());
+
+        let d = std::fs::read(path)?;
+        format.decode(&d)
+    }
+
+    /// Store an object to a file, detecting the format from the extension
+    /// where not provided
+    pub fn store<T: Serialize>(path: impl AsRef<Path>, format: Option<Format>, v: &T) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let format = format.or_else(|| Format::from_path(path)).unwrap_or_default();
+
+        debug!("Writing {} file: {}", format, path.display());
+
+        let d = format.encode(v)?;
+        std::fs::write(path, d)?;
+
+        Ok(())
+    }
+}
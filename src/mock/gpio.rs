@@ -6,7 +6,7 @@ use log::debug;
 
 use wasm_embedded_spec::{Error, Gpio};
 
-use super::{Inner, Op, Kind};
+use super::{Inner, Kind};
 
 pub struct MockGpio {
     inner: Arc<Mutex<Inner>>,
@@ -24,28 +24,15 @@ impl Gpio for MockGpio {
 
         debug!("Configuring GPIO port: {} pin: {} (mode: {})", port, pin, output);
 
-        let op = Kind::GpioInit{port, pin, output};
-        let Op{kind, res} = inner.expected[inner.index].clone();
-
-        assert_eq!(op, kind);
-
-        inner.actual.push(op);
-        inner.index += 1;
-
-        Ok(res)
+        inner.push_actual(Kind::GpioInit{port, pin, output})
     }
 
     fn deinit(&mut self, handle: i32) -> Result<(), Error> {
         let mut inner = self.inner.lock().unwrap();
 
-        debug!("Closing I2C handle: {}", handle);
-        let op = Kind::I2cDeinit{handle};
-        let Op{kind, ..} = inner.expected[inner.index].clone();
-
-        assert_eq!(op, kind);
+        debug!("Closing GPIO handle: {}", handle);
 
-        inner.actual.push(op);
-        inner.index += 1;
+        inner.push_actual(Kind::GpioDeinit{handle})?;
 
         Ok(())
     }
@@ -53,15 +40,9 @@ impl Gpio for MockGpio {
     fn set(&mut self, handle: i32, state: embedded_hal::digital::PinState) -> Result<(), Error> {
         let mut inner = self.inner.lock().unwrap();
 
-        let Op{kind, ..} = inner.expected[inner.index].clone();
-
         debug!("GPIO set handle: {} value: {:?}", handle, state);
 
-        let op = Kind::GpioSet{handle, state: state.into()};
-        assert_eq!(op, kind);
-
-        inner.actual.push(op);
-        inner.index += 1;
+        inner.push_actual(Kind::GpioSet{handle, state: state.into()})?;
 
         Ok(())
     }
@@ -69,21 +50,15 @@ impl Gpio for MockGpio {
     fn get(&mut self, handle: i32) -> Result<embedded_hal::digital::PinState, Error> {
         let mut inner = self.inner.lock().unwrap();
 
-        let Op{kind, ..} = inner.expected[inner.index].clone();
-
-        let state = if let Kind::GpioGet{state, ..} = kind {
-            state.clone().into()
+        let state = if let Some(Kind::GpioGet{state, ..}) = inner.next() {
+            (*state).into()
         } else {
             embedded_hal::digital::PinState::Low
         };
 
         debug!("GPIO get handle: {} value: {:?}", handle, state);
 
-        let op = Kind::GpioGet{handle, state: state.into()};
-        assert_eq!(op, kind);
-
-        inner.actual.push(op);
-        inner.index += 1;
+        inner.push_actual(Kind::GpioGet{handle, state: state.into()})?;
 
         Ok(state)
     }
@@ -1,11 +1,13 @@
 //! Mock driver implementation for application and API testing
 
-use std::{vec, vec::Vec, sync::{Arc, Mutex}};
+use std::{vec, vec::Vec, collections::BTreeMap, string::String, path::{Path, PathBuf}, sync::{Arc, Mutex}};
 
 use serde::{Serialize, Deserialize};
-use log::debug;
+use log::{debug, warn, error};
 
-use wasm_embedded_spec::Engine;
+use wasm_embedded_spec::{Engine, Error};
+
+use crate::{EngineExt, server::StopToken, logging::{GuestLog, LogConfig}};
 
 mod spi;
 pub use spi::MockSpi;
@@ -15,9 +17,32 @@ mod uart;
 pub use uart::MockUart;
 mod gpio;
 pub use gpio::MockGpio;
+mod pwm;
+pub use pwm::MockPwm;
+mod adc;
+pub use adc::MockAdc;
+mod can;
+pub use can::MockCan;
+mod onewire;
+pub use onewire::MockOneWire;
+mod time;
+pub use time::MockTime;
+mod watchdog;
+pub use watchdog::MockWatchdog;
+mod storage;
+pub use storage::MockStorage;
 
 mod ops;
-pub use ops::{Op, Kind};
+pub use ops::{Op, Kind, Method, PinState, CanFrame, Timing};
+
+mod builder;
+pub use builder::MockBuilder;
+
+mod format;
+pub use format::Format;
+
+mod report;
+pub use report::{Report, MismatchReport, Divergence, TimingViolation, StorageMismatch, Summary};
 
 /// Mock configuration
 #[derive(Clone, PartialEq, Debug)]
@@ -25,6 +50,12 @@ pub use ops::{Op, Kind};
 #[serde(rename_all="snake_case")]
 pub struct MockConfig {
     pub ops: Vec<Op>,
+    /// Initial storage contents
+    #[serde(default, skip_serializing_if="BTreeMap::is_empty")]
+    pub storage: BTreeMap<String, Vec<u8>>,
+    /// Expected storage contents on completion, unchecked if unset
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub expect_storage: Option<BTreeMap<String, Vec<u8>>>,
 }
 
 /// Mock driver context
@@ -35,39 +66,301 @@ pub struct MockCtx {
     i2c: MockI2c,
     spi: MockSpi,
     uart: MockUart,
+    pwm: MockPwm,
+    adc: MockAdc,
+    can: MockCan,
+    onewire: MockOneWire,
+    time: MockTime,
+    watchdog: MockWatchdog,
+    storage: MockStorage,
+    logger: GuestLog,
+
+    stop: Option<StopToken>,
 }
 
 /// Inner storage for mock drivers
 pub(crate) struct Inner {
     expected: Vec<Op>,
     actual: Vec<Kind>,
+    /// Virtual timestamps of executed operations in microseconds
+    times: Vec<u64>,
     index: usize,
+    divergence: Option<Divergence>,
+    verified: bool,
+    record: Option<(PathBuf, Option<Format>)>,
+    /// Virtual clock in microseconds
+    pub(crate) now: u64,
+    /// Initial storage contents, retained for recordings
+    initial_storage: BTreeMap<String, Vec<u8>>,
+    expect_storage: Option<BTreeMap<String, Vec<u8>>>,
+    /// Storage contents
+    pub(crate) storage: BTreeMap<String, Vec<u8>>,
+}
+
+impl Inner {
+    /// Fetch the next expected operation, if execution has not yet diverged
+    pub(crate) fn next(&self) -> Option<&Kind> {
+        match &self.divergence {
+            Some(_) => None,
+            None => self.expected.get(self.index).map(|o| &o.kind),
+        }
+    }
+
+    /// Record an executed operation, returning the expected result
+    /// or an error if this does not match the expectation
+    pub(crate) fn push_actual(&mut self, op: Kind) -> Result<i32, Error> {
+        self.actual.push(op.clone());
+        self.times.push(self.now);
+
+        // Fail all operations following a divergence
+        if self.divergence.is_some() {
+            return Err(Error::Failed);
+        }
+
+        match self.expected.get(self.index) {
+            Some(e) if e.kind == op => {
+                if let Some(timing) = self.check_timing(e.timing.as_ref()) {
+                    warn!("Mock timing mismatch at operation {} ({:?}): {}", self.index, op, timing);
+
+                    self.divergence = Some(Divergence{ index: self.index, expected: Some(e.kind.clone()), actual: Some(op), timing: Some(timing), storage: None });
+                    return Err(Error::Failed);
+                }
+
+                self.index += 1;
+                Ok(e.res)
+            },
+            e => {
+                let expected = e.map(|e| e.kind.clone());
+
+                warn!("Mock mismatch at operation {} (expected: {:?} actual: {:?})", self.index, expected, op);
+
+                self.divergence = Some(Divergence{ index: self.index, expected, actual: Some(op), timing: None, storage: None });
+                Err(Error::Failed)
+            }
+        }
+    }
+
+    /// Check the current operation against an optional timing constraint,
+    /// returning the violation if not met
+    ///
+    /// As execution has matched up to the current operation, expected
+    /// indices correspond to executed operations.
+    fn check_timing(&self, timing: Option<&Timing>) -> Option<TimingViolation> {
+        let timing = timing?;
+
+        // Operations without a predecessor are relative to the engine start
+        let reference = match timing.after {
+            Some(i) if i < self.index => Some(self.times[i]),
+            Some(_) => None,
+            None => Some(self.index.checked_sub(1).map(|i| self.times[i]).unwrap_or(0)),
+        };
+
+        let elapsed_ms = reference.map(|r| (self.now - r) as f64 / 1000.0);
+
+        match elapsed_ms {
+            Some(e) if timing.check(e) => None,
+            _ => Some(TimingViolation{ expected: timing.clone(), elapsed_ms }),
+        }
+    }
+
+    /// Check executed operations against expectations
+    fn verify(&mut self) -> Result<Report, MismatchReport> {
+        self.verified = true;
+
+        // Execution finishing early is only detected on completion
+        let divergence = match (&self.divergence, self.expected.get(self.index)) {
+            (Some(d), _) => d.clone(),
+            (None, Some(e)) => Divergence{ index: self.index, expected: Some(e.kind.clone()), actual: None, timing: None, storage: None },
+            (None, None) => match self.check_storage() {
+                Some(storage) => Divergence{ index: self.index, expected: None, actual: None, timing: None, storage: Some(storage) },
+                None => return Ok(Report{ matched: self.index, ops: self.actual.clone() }),
+            },
+        };
+
+        Err(MismatchReport{
+            matched: self.index,
+            divergence,
+            expected: self.expected.iter().map(|o| o.kind.clone()).collect(),
+            actual: self.actual.clone(),
+        })
+    }
+
+    /// Check final storage contents against expectations, returning the
+    /// first mismatching key if any
+    fn check_storage(&self) -> Option<StorageMismatch> {
+        let expected = self.expect_storage.as_ref()?;
+
+        expected.keys().chain(self.storage.keys())
+            .find(|k| expected.get(*k) != self.storage.get(*k))
+            .map(|k| StorageMismatch{
+                key: k.clone(),
+                expected: expected.get(k).cloned(),
+                actual: self.storage.get(k).cloned(),
+            })
+    }
+
+    /// Build a replayable configuration from the recorded operations,
+    /// using expected results where these match
+    fn recording(&self) -> MockConfig {
+        let ops = self.actual.iter().enumerate().map(|(i, kind)| {
+            let res = match self.expected.get(i) {
+                Some(e) if &e.kind == kind => e.res,
+                _ => 0,
+            };
+            Op{ kind: kind.clone(), res, timing: None }
+        }).collect();
+
+        MockConfig{ ops, storage: self.initial_storage.clone(), expect_storage: None }
+    }
+}
+
+/// Fill a read buffer with expected data, leaving the buffer untouched on
+/// length mismatch so this is reported as a divergence rather than a panic
+pub(crate) fn fill<T: Copy>(buff: &mut [T], data: &[T]) {
+    if buff.len() == data.len() {
+        buff.copy_from_slice(data);
+    }
 }
 
 impl MockCtx {
-    /// Load a new mock context
+    /// Load a new mock context, detecting the configuration format
+    /// from the file extension
     pub fn load(config: &str) -> anyhow::Result<Self> {
+        Self::load_format(config, None)
+    }
+
+    /// Load a new mock context using the provided configuration format
+    /// (or detecting this from the file extension if not provided)
+    pub fn load_format(config: &str, format: Option<Format>) -> anyhow::Result<Self> {
         debug!("Loading mock config: {}", config);
 
         // Load expectations from config file
-        let d = std::fs::read(config)?;
-        let f: MockConfig = toml::from_slice(&d)?;
+        let f: MockConfig = Format::load(config, format)?;
 
-        debug!("Using expectations: {:?}", f);
+        Ok(Self::from_config(f))
+    }
+
+    /// Create a mock context from the provided configuration
+    pub fn from_config(config: MockConfig) -> Self {
+        debug!("Using expectations: {:?}", config);
 
         let inner = Arc::new(Mutex::new(Inner{
-            expected: f.ops,
+            expected: config.ops,
             actual: Vec::new(),
+            times: Vec::new(),
             index: 0,
+            divergence: None,
+            verified: false,
+            record: None,
+            now: 0,
+            initial_storage: config.storage.clone(),
+            expect_storage: config.expect_storage,
+            storage: config.storage,
         }));
 
-        Ok(Self{
+        Self{
             inner: inner.clone(),
             gpio: MockGpio::new(inner.clone()),
             i2c: MockI2c::new(inner.clone()),
             spi: MockSpi::new(inner.clone()),
             uart: MockUart::new(inner.clone()),
-        })
+            pwm: MockPwm::new(inner.clone()),
+            adc: MockAdc::new(inner.clone()),
+            can: MockCan::new(inner.clone()),
+            onewire: MockOneWire::new(inner.clone()),
+            time: MockTime::new(inner.clone()),
+            watchdog: MockWatchdog::new(inner.clone()),
+            storage: MockStorage::new(inner.clone()),
+            logger: GuestLog::new("guest", LogConfig::default()),
+            stop: None,
+        }
+    }
+
+    /// Bind the context to a [`StopToken`], refusing driver access once stopped
+    pub fn with_stop(mut self, stop: StopToken) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Route guest log messages through the provided logger
+    ///
+    /// Guest log messages are not recorded as operations, so logging does
+    /// not affect verification.
+    pub fn with_logger(mut self, logger: GuestLog) -> Self {
+        self.logger = logger;
+        self
+    }
+
+    /// Check whether the context has been stopped
+    fn stopped(&self) -> bool {
+        self.stop.as_ref().map(|s| s.is_stopped()).unwrap_or(false)
+    }
+
+    /// Create a builder for programmatic mock configuration
+    pub fn builder() -> MockBuilder {
+        MockBuilder::default()
+    }
+
+    /// Fetch a handle to the mock state, allowing operations to be inspected
+    /// after the context has been passed to a runtime
+    pub fn handle(&self) -> MockHandle {
+        MockHandle{ inner: self.inner.clone() }
+    }
+
+    /// Verify executed operations against expectations
+    pub fn verify(&self) -> Result<Report, MismatchReport> {
+        self.inner.lock().unwrap().verify()
+    }
+
+    /// Record executed operations to the provided file on completion,
+    /// detecting the format from the file extension if not provided
+    pub fn record(&mut self, path: impl AsRef<Path>, format: Option<Format>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.record = Some((path.as_ref().to_path_buf(), format));
+    }
+}
+
+impl From<MockConfig> for MockCtx {
+    fn from(config: MockConfig) -> Self {
+        Self::from_config(config)
+    }
+}
+
+/// Shared handle to mock state
+#[derive(Clone)]
+pub struct MockHandle {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl MockHandle {
+    /// Fetch expected operations
+    pub fn expected(&self) -> Vec<Op> {
+        self.inner.lock().unwrap().expected.clone()
+    }
+
+    /// Fetch operations executed so far
+    pub fn actual(&self) -> Vec<Kind> {
+        self.inner.lock().unwrap().actual.clone()
+    }
+
+    /// Fetch virtual timestamps (in microseconds) of operations executed so far
+    pub fn times(&self) -> Vec<u64> {
+        self.inner.lock().unwrap().times.clone()
+    }
+
+    /// Fetch current storage contents
+    pub fn storage(&self) -> BTreeMap<String, Vec<u8>> {
+        self.inner.lock().unwrap().storage.clone()
+    }
+
+    /// Build a replayable configuration from the executed operations
+    pub fn recording(&self) -> MockConfig {
+        self.inner.lock().unwrap().recording()
+    }
+
+    /// Verify executed operations against expectations
+    pub fn verify(&self) -> Result<Report, MismatchReport> {
+        self.inner.lock().unwrap().verify()
     }
 }
 
@@ -80,19 +373,126 @@ impl Engine for MockCtx {
 
     type Uart = MockUart;
 
-    fn gpio(&mut self) -> Option<&mut Self::Gpio> { return Some(&mut self.gpio) }
+    fn gpio(&mut self) -> Option<&mut Self::Gpio> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.gpio)
+    }
+
+    fn i2c(&mut self) -> Option<&mut Self::I2c> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.i2c)
+    }
+
+    fn spi(&mut self) -> Option<&mut Self::Spi> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.spi)
+    }
+
+    fn uart(&mut self) -> Option<&mut Self::Uart> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.uart)
+    }
+}
+
+impl EngineExt for MockCtx {
+    type Pwm = MockPwm;
+
+    type Adc = MockAdc;
+
+    type Can = MockCan;
+
+    type OneWire = MockOneWire;
+
+    type Time = MockTime;
+
+    type Watchdog = MockWatchdog;
+
+    type Storage = MockStorage;
+
+    type Logger = GuestLog;
+
+    fn pwm(&mut self) -> Option<&mut Self::Pwm> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.pwm)
+    }
+
+    fn adc(&mut self) -> Option<&mut Self::Adc> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.adc)
+    }
 
-    fn i2c(&mut self) -> Option<&mut Self::I2c> { return Some(&mut self.i2c) }
+    fn can(&mut self) -> Option<&mut Self::Can> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.can)
+    }
 
-    fn spi(&mut self) -> Option<&mut Self::Spi> { return Some(&mut self.spi) }
+    fn onewire(&mut self) -> Option<&mut Self::OneWire> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.onewire)
+    }
 
-    fn uart(&mut self) -> Option<&mut Self::Uart> { return Some(&mut self.uart) }
+    fn time(&mut self) -> Option<&mut Self::Time> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.time)
+    }
+
+    fn watchdog(&mut self) -> Option<&mut Self::Watchdog> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.watchdog)
+    }
+
+    fn storage(&mut self) -> Option<&mut Self::Storage> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.storage)
+    }
+
+    fn logger(&mut self) -> Option<&mut Self::Logger> {
+        if self.stopped() {
+            return None;
+        }
+        Some(&mut self.logger)
+    }
 }
 
-impl Drop for MockCtx {
+// Checked on dropping the shared state rather than the context, as runtimes
+// drop engines prior to verification via a [`MockHandle`]
+impl Drop for Inner {
     fn drop(&mut self) {
-        let mut inner = self.inner.lock().unwrap();
-        let ex: Vec<Kind> = inner.expected.iter().map(|v| v.kind.clone()).collect();
-        assert_eq!(&ex, &inner.actual, "Mock result mismatch");
+        // Write out recorded operations if enabled
+        if let Some((path, format)) = &self.record {
+            if let Err(e) = Format::store(path, *format, &self.recording()) {
+                error!("Failed to write mock recording: {:?}", e);
+            }
+        }
+
+        // Warn where results have not been checked
+        if !self.verified {
+            match self.verify() {
+                Ok(_) => warn!("Mock context dropped without verification"),
+                Err(e) => warn!("Mock context dropped without verification: {}", e),
+            }
+        }
     }
 }
@@ -0,0 +1,99 @@
+//! Mock 1-Wire driver implementation, enumerating scripted devices
+
+use std::sync::{Arc, Mutex};
+
+use log::debug;
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::{OneWire, Rom};
+use super::{Inner, Kind};
+
+pub struct MockOneWire {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl MockOneWire {
+    pub(crate) const fn new(inner: Arc<Mutex<Inner>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl OneWire for MockOneWire {
+    fn init(&mut self, bus: u32, pin: i32) -> Result<i32, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("Configuring 1-Wire bus: {} pin: {}", bus, pin);
+
+        inner.push_actual(Kind::OneWireInit{bus, pin})
+    }
+
+    fn deinit(&mut self, handle: i32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("Closing 1-Wire handle: {}", handle);
+
+        inner.push_actual(Kind::OneWireDeinit{handle})?;
+
+        Ok(())
+    }
+
+    fn reset(&mut self, handle: i32) -> Result<bool, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let presence = match inner.next() {
+            Some(Kind::OneWireReset{presence, ..}) => *presence,
+            _ => false,
+        };
+
+        debug!("1-Wire reset handle: {} presence: {}", handle, presence);
+
+        inner.push_actual(Kind::OneWireReset{handle, presence})?;
+
+        Ok(presence)
+    }
+
+    fn search(&mut self, handle: i32, roms: &mut [Rom]) -> Result<usize, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        // Devices beyond the buffer length are not reported, as with hardware drivers
+        let mut n = 0;
+        if let Some(Kind::OneWireSearch{roms: r, ..}) = inner.next() {
+            for (a, b) in roms.iter_mut().zip(r.iter()) {
+                *a = *b;
+                n += 1;
+            }
+        }
+
+        debug!("1-Wire search handle: {} roms: {:02x?}", handle, &roms[..n]);
+
+        inner.push_actual(Kind::OneWireSearch{handle, roms: roms[..n].to_vec()})?;
+
+        Ok(n)
+    }
+
+    fn write_byte(&mut self, handle: i32, data: u8) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("1-Wire write handle: {} data: {:#04x}", handle, data);
+
+        inner.push_actual(Kind::OneWireWriteByte{handle, data})?;
+
+        Ok(())
+    }
+
+    fn read_byte(&mut self, handle: i32) -> Result<u8, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let data = match inner.next() {
+            Some(Kind::OneWireReadByte{data, ..}) => *data,
+            _ => 0xFF,
+        };
+
+        debug!("1-Wire read handle: {} data: {:#04x}", handle, data);
+
+        inner.push_actual(Kind::OneWireReadByte{handle, data})?;
+
+        Ok(data)
+    }
+}
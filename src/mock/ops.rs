@@ -1,4 +1,7 @@
 use serde::{Serialize, Deserialize};
+use strum::{Display, EnumDiscriminants, EnumIter};
+
+use crate::ext::{Polarity, Filter, Frame, Rom};
 
 /// Mock operation
 #[derive(Clone, PartialEq, Debug)]
@@ -8,6 +11,60 @@ pub struct Op {
     #[serde(flatten)]
     pub kind: Kind,
     pub res: i32,
+    /// Optional timing constraint, checked against the virtual clock
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub timing: Option<Timing>,
+}
+
+/// Timing constraint on an expected operation, relative to an earlier operation
+///
+/// For example, to expect a GPIO write at least 10 ms after operation 2:
+///
+/// ```toml
+/// [[ops]]
+/// kind = "gpio_set"
+/// handle = 0
+/// state = "high"
+/// res = 0
+/// timing = { after = 2, min_ms = 10.0 }
+/// ```
+#[derive(Clone, PartialEq, Default, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct Timing {
+    /// Index of the reference operation, the preceding operation if unset
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub after: Option<usize>,
+    /// Minimum time since the reference operation in milliseconds
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub min_ms: Option<f64>,
+    /// Maximum time since the reference operation in milliseconds
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub max_ms: Option<f64>,
+}
+
+impl Timing {
+    /// Check whether an elapsed time satisfies the constraint
+    pub fn check(&self, elapsed_ms: f64) -> bool {
+        self.min_ms.map(|m| elapsed_ms >= m).unwrap_or(true)
+            && self.max_ms.map(|m| elapsed_ms <= m).unwrap_or(true)
+    }
+}
+
+impl core::fmt::Display for Timing {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match (self.min_ms, self.max_ms) {
+            (Some(min), Some(max)) => write!(f, "{} to {} ms", min, max)?,
+            (Some(min), None) => write!(f, "at least {} ms", min)?,
+            (None, Some(max)) => write!(f, "at most {} ms", max)?,
+            (None, None) => write!(f, "any time")?,
+        }
+
+        match self.after {
+            Some(i) => write!(f, " after operation {}", i),
+            None => write!(f, " after the preceding operation"),
+        }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -40,10 +97,53 @@ impl From<embedded_hal::digital::PinState> for PinState {
     }
 }
 
+/// CAN frame, as recorded in mock operations
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct CanFrame {
+    pub id: u32,
+    #[serde(default)]
+    pub extended: bool,
+    #[serde(default)]
+    pub fd: bool,
+    /// Remote transmission request, with the requested data length
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub remote: Option<usize>,
+    #[serde(default)]
+    pub data: Vec<u8>,
+}
+
+impl CanFrame {
+    /// Convert to a driver frame, returning `None` if this is not valid
+    pub fn frame(&self) -> Option<Frame> {
+        match self.remote {
+            Some(len) => Frame::remote(self.id, self.extended, len),
+            None => Frame::new(self.id, self.extended, self.fd, &self.data),
+        }
+    }
+}
+
+impl From<&Frame> for CanFrame {
+    fn from(f: &Frame) -> Self {
+        Self{
+            id: f.id,
+            extended: f.extended,
+            fd: f.fd,
+            remote: if f.remote { Some(f.len()) } else { None },
+            data: f.data().to_vec(),
+        }
+    }
+}
+
 /// Mock operation kind enumeration
+///
+/// Each driver method has exactly one corresponding operation kind,
+/// with [`Method`] providing the field-less discriminant for each.
 #[derive(Clone, PartialEq, Debug)]
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, EnumDiscriminants)]
 #[serde(tag="kind", rename_all="snake_case")]
+#[strum_discriminants(name(Method), derive(Hash, Display, EnumIter))]
+#[strum_discriminants(strum(serialize_all = "snake_case"))]
 pub enum Kind {
     I2cInit{
         port: u32,
@@ -94,6 +194,11 @@ pub enum Kind {
         data_out: Vec<u8>,
         data_in: Vec<u8>,
     },
+    SpiTransferInplace{
+        handle: i32,
+        data_out: Vec<u8>,
+        data_in: Vec<u8>,
+    },
     UartInit{
         port: u32,
         baud: u32,
@@ -129,4 +234,131 @@ pub enum Kind {
         handle: i32,
         state: PinState,
     },
+    PwmInit{
+        chip: u32,
+        channel: u32,
+    },
+    PwmDeinit{
+        handle: i32,
+    },
+    PwmSetPeriod{
+        handle: i32,
+        period_ns: u32,
+    },
+    PwmSetDutyCycle{
+        handle: i32,
+        duty_ns: u32,
+    },
+    PwmSetPolarity{
+        handle: i32,
+        polarity: Polarity,
+    },
+    PwmEnable{
+        handle: i32,
+        enabled: bool,
+    },
+    AdcInit{
+        device: u32,
+        channel: u32,
+    },
+    AdcDeinit{
+        handle: i32,
+    },
+    AdcReadRaw{
+        handle: i32,
+        value: i32,
+    },
+    AdcRead{
+        handle: i32,
+        value: f32,
+    },
+    AdcCapture{
+        handle: i32,
+        trigger: u32,
+        samples: Vec<i32>,
+    },
+    CanInit{
+        port: u32,
+        fd: bool,
+    },
+    CanDeinit{
+        handle: i32,
+    },
+    CanSetFilters{
+        handle: i32,
+        filters: Vec<Filter>,
+    },
+    CanSend{
+        handle: i32,
+        frame: CanFrame,
+    },
+    CanReceive{
+        handle: i32,
+        timeout_ms: Option<u32>,
+        frame: Option<CanFrame>,
+    },
+    OneWireInit{
+        bus: u32,
+        pin: i32,
+    },
+    OneWireDeinit{
+        handle: i32,
+    },
+    OneWireReset{
+        handle: i32,
+        presence: bool,
+    },
+    OneWireSearch{
+        handle: i32,
+        roms: Vec<Rom>,
+    },
+    OneWireWriteByte{
+        handle: i32,
+        data: u8,
+    },
+    OneWireReadByte{
+        handle: i32,
+        data: u8,
+    },
+    DelayUs{
+        us: u32,
+    },
+    DelayMs{
+        ms: u32,
+    },
+    TimeNow{
+        us: u64,
+    },
+    TimerStart{
+        period_us: u32,
+    },
+    TimerStop{
+        handle: i32,
+    },
+    TimerWait{
+        handle: i32,
+        expirations: u32,
+    },
+    TimerPoll{
+        handle: Option<i32>,
+    },
+    TimerWaitAny{
+        timeout_us: Option<u32>,
+        handle: Option<i32>,
+    },
+    WatchdogKick,
+    StorageGet{
+        key: String,
+        /// Stored value, `None` if the key was unset
+        value: Option<Vec<u8>>,
+    },
+    StorageSet{
+        key: String,
+        value: Vec<u8>,
+    },
+    StorageRemove{
+        key: String,
+        /// Whether the key was set
+        existed: bool,
+    },
 }
@@ -0,0 +1,80 @@
+//! Mock PWM driver implementation
+
+use std::sync::{Arc, Mutex};
+
+use log::debug;
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::{Pwm, Polarity};
+use super::{Inner, Kind};
+
+pub struct MockPwm {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl MockPwm {
+    pub(crate) const fn new(inner: Arc<Mutex<Inner>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Pwm for MockPwm {
+    fn init(&mut self, chip: u32, channel: u32) -> Result<i32, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("Configuring PWM chip: {} channel: {}", chip, channel);
+
+        inner.push_actual(Kind::PwmInit{chip, channel})
+    }
+
+    fn deinit(&mut self, handle: i32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("Closing PWM handle: {}", handle);
+
+        inner.push_actual(Kind::PwmDeinit{handle})?;
+
+        Ok(())
+    }
+
+    fn set_period(&mut self, handle: i32, period_ns: u32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("PWM set period handle: {} period: {} ns", handle, period_ns);
+
+        inner.push_actual(Kind::PwmSetPeriod{handle, period_ns})?;
+
+        Ok(())
+    }
+
+    fn set_duty_cycle(&mut self, handle: i32, duty_ns: u32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("PWM set duty cycle handle: {} duty: {} ns", handle, duty_ns);
+
+        inner.push_actual(Kind::PwmSetDutyCycle{handle, duty_ns})?;
+
+        Ok(())
+    }
+
+    fn set_polarity(&mut self, handle: i32, polarity: Polarity) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("PWM set polarity handle: {} polarity: {:?}", handle, polarity);
+
+        inner.push_actual(Kind::PwmSetPolarity{handle, polarity})?;
+
+        Ok(())
+    }
+
+    fn enable(&mut self, handle: i32, enabled: bool) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("PWM enable handle: {} enabled: {}", handle, enabled);
+
+        inner.push_actual(Kind::PwmEnable{handle, enabled})?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+//! Mock verification reports
+
+use std::{fmt, format, path::Path, string::{String, ToString}, time::Duration, vec::Vec};
+
+use serde::{Serialize, Deserialize};
+use log::debug;
+
+use super::{Kind, Timing, Format};
+use crate::opts::ReportFormat;
+
+/// Report for a successful mock run
+#[derive(Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct Report {
+    /// Number of operations matched
+    pub matched: usize,
+    /// Executed operations
+    pub ops: Vec<Kind>,
+}
+
+/// Report for a failed mock run, describing where execution diverged
+/// from the expected operations
+#[derive(Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct MismatchReport {
+    /// Number of operations matched prior to divergence
+    pub matched: usize,
+    /// First divergence from the expected operations
+    pub divergence: Divergence,
+    /// Expected operations
+    pub expected: Vec<Kind>,
+    /// Executed operations
+    pub actual: Vec<Kind>,
+}
+
+/// Divergence between expected and executed operations
+#[derive(Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct Divergence {
+    /// Index of the diverging operation
+    pub index: usize,
+    /// Expected operation, `None` if more operations were executed than expected
+    pub expected: Option<Kind>,
+    /// Executed operation, `None` if execution finished early
+    pub actual: Option<Kind>,
+    /// Timing constraint violated by an otherwise matching operation
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub timing: Option<TimingViolation>,
+    /// Final storage contents mismatch, where all operations matched
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub storage: Option<StorageMismatch>,
+}
+
+/// Violation of an operation timing constraint
+#[derive(Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct TimingViolation {
+    /// Virtual time elapsed since the reference operation in milliseconds,
+    /// `None` if the reference operation had not been executed
+    // Precedes `expected` as TOML requires values be emitted before tables
+    pub elapsed_ms: Option<f64>,
+    /// Expected timing
+    pub expected: Timing,
+}
+
+/// Mismatch between expected and final storage contents
+#[derive(Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct StorageMismatch {
+    /// First mismatching key
+    pub key: String,
+    /// Expected value, `None` if the key was expected to be unset
+    pub expected: Option<Vec<u8>>,
+    /// Stored value, `None` if the key was unset
+    pub actual: Option<Vec<u8>>,
+}
+
+impl fmt::Display for StorageMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "key {} expected: {:02x?} actual: {:02x?}", self.key, self.expected, self.actual)
+    }
+}
+
+impl fmt::Display for TimingViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.elapsed_ms {
+            Some(e) => write!(f, "expected {} (elapsed: {} ms)", self.expected, e),
+            None => write!(f, "expected {} (reference operation not executed)", self.expected),
+        }
+    }
+}
+
+impl MismatchReport {
+    /// Write the report to a file, detecting the format from the
+    /// extension where not provided
+    pub fn store(&self, path: impl AsRef<Path>, format: Option<Format>) -> anyhow::Result<()> {
+        Format::store(path, format, self)
+    }
+}
+
+impl fmt::Display for MismatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let d = &self.divergence;
+
+        if let Some(s) = &d.storage {
+            writeln!(f, "Mock storage mismatch ({} of {} operations matched)", self.matched, self.expected.len())?;
+            writeln!(f, "  storage:  {}", s)?;
+            return Ok(());
+        }
+
+        writeln!(f, "Mock result mismatch at operation {} ({} of {} matched)", d.index, self.matched, self.expected.len())?;
+
+        match &d.expected {
+            Some(k) => writeln!(f, "  expected: {:?}", k)?,
+            None => writeln!(f, "  expected: <end of operations>")?,
+        }
+        match &d.actual {
+            Some(k) => writeln!(f, "  actual:   {:?}", k)?,
+            None => writeln!(f, "  actual:   <end of operations>")?,
+        }
+        if let Some(t) = &d.timing {
+            writeln!(f, "  timing:   {}", t)?;
+        }
+
+        writeln!(f, "Executed operations:")?;
+        for (i, k) in self.actual.iter().enumerate() {
+            let m = if i == d.index { '>' } else { ' ' };
+            writeln!(f, "{} {:3}: {:?}", m, i, k)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for MismatchReport {}
+
+/// Summary of a mock run for CI reporting
+#[derive(Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct Summary {
+    /// Test name (typically the WASM binary)
+    pub name: String,
+    /// Test class (typically the runtime and engine)
+    pub class: String,
+    /// Overall test result
+    pub passed: bool,
+    /// Number of operations matched
+    pub matched: usize,
+    /// Number of operations expected
+    pub expected: usize,
+    /// Number of operations executed
+    pub executed: usize,
+    /// First divergence from expected operations, if any
+    pub divergence: Option<Divergence>,
+    /// Runtime error, if any
+    pub error: Option<String>,
+    /// Execution time in seconds
+    pub time: f64,
+}
+
+impl Summary {
+    /// Build a summary from mock verification and runtime results
+    pub fn new(name: &str, class: &str, result: &Result<Report, MismatchReport>, error: Option<&anyhow::Error>, time: Duration) -> Self {
+        let (matched, expected, executed, divergence) = match result {
+            Ok(r) => (r.matched, r.matched, r.ops.len(), None),
+            Err(e) => (e.matched, e.expected.len(), e.actual.len(), Some(e.divergence.clone())),
+        };
+
+        Self{
+            name: name.into(),
+            class: class.into(),
+            passed: divergence.is_none() && error.is_none(),
+            matched,
+            expected,
+            executed,
+            divergence,
+            error: error.map(|e| format!("{:#}", e)),
+            time: time.as_secs_f64(),
+        }
+    }
+
+    /// Build a summary for a run failing prior to execution, such as
+    /// where the binary or mock configuration could not be loaded
+    pub fn failed(name: &str, class: &str, error: &anyhow::Error, time: Duration) -> Self {
+        Self{
+            name: name.into(),
+            class: class.into(),
+            passed: false,
+            matched: 0,
+            expected: 0,
+            executed: 0,
+            divergence: None,
+            error: Some(format!("{:#}", error)),
+            time: time.as_secs_f64(),
+        }
+    }
+
+    /// Write the summary to a file, detecting the format from the
+    /// extension (`.xml` for JUnit, otherwise JSON) if not provided
+    pub fn store(&self, path: impl AsRef<Path>, format: Option<ReportFormat>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let format = format.unwrap_or_else(|| match path.extension().and_then(|e| e.to_str()) {
+            Some("xml") => ReportFormat::Junit,
+            _ => ReportFormat::Json,
+        });
+
+        debug!("Writing {} report: {}", format, path.display());
+
+        let d = match format {
+            ReportFormat::Junit => self.junit().into_bytes(),
+            ReportFormat::Json => serde_json::to_vec_pretty(self)?,
+        };
+        std::fs::write(path, d)?;
+
+        Ok(())
+    }
+
+    /// Render the summary as a JUnit XML document
+    pub fn junit(&self) -> String {
+        let failures = usize::from(self.divergence.is_some());
+        let errors = usize::from(self.divergence.is_none() && self.error.is_some());
+
+        let mut s = String::new();
+        s.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        s.push_str(&format!("<testsuites tests=\"1\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n", failures, errors, self.time));
+        s.push_str(&format!("  <testsuite name=\"{}\" tests=\"1\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n", escape(&self.class), failures, errors, self.time));
+        s.push_str(&format!("    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\">\n", escape(&self.name), escape(&self.class), self.time));
+
+        if let Some(m) = self.divergence.as_ref().and_then(|d| d.storage.as_ref()) {
+            let msg = format!("storage mismatch for key {}", m.key);
+            s.push_str(&format!("      <failure message=\"{}\" type=\"storage\">{}</failure>\n", escape(&msg), escape(&m.to_string())));
+        } else if let Some(d) = &self.divergence {
+            let msg = format!("mismatch at operation {} ({} of {} matched)", d.index, self.matched, self.expected);
+            let mut detail = format!("expected: {:?}\nactual: {:?}", d.expected, d.actual);
+            if let Some(t) = &d.timing {
+                detail.push_str(&format!("\ntiming: {}", t));
+            }
+            s.push_str(&format!("      <failure message=\"{}\" type=\"mismatch\">{}</failure>\n", escape(&msg), escape(&detail)));
+        } else if let Some(e) = &self.error {
+            s.push_str(&format!("      <error message=\"{}\" type=\"runtime\"/>\n", escape(e)));
+        }
+
+        s.push_str(&format!("      <system-out>matched {} of {} operations ({} executed)</system-out>\n", self.matched, self.expected, self.executed));
+        s.push_str("    </testcase>\n");
+        s.push_str("  </testsuite>\n");
+        s.push_str("</testsuites>\n");
+
+        s
+    }
+}
+
+/// Escape a string for inclusion in XML text or attributes,
+/// replacing control characters XML 1.0 cannot represent with U+FFFD
+fn escape(s: &str) -> String {
+    let mut o = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => o.push_str("&amp;"),
+            '<' => o.push_str("&lt;"),
+            '>' => o.push_str("&gt;"),
+            '"' => o.push_str("&quot;"),
+            '\'' => o.push_str("&apos;"),
+            '\t' | '\n' | '\r' => o.push(c),
+            '\u{0}'..='\u{1f}' => o.push(char::REPLACEMENT_CHARACTER),
+            _ => o.push(c),
+        }
+    }
+    o
+}
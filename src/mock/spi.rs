@@ -5,7 +5,7 @@ use std::sync::{Arc, Mutex};
 use log::debug;
 
 use wasm_embedded_spec::{Error, Spi};
-use super::{Inner, Op, Kind};
+use super::{Inner, Kind};
 
 pub struct MockSpi {
     inner: Arc<Mutex<Inner>>,
@@ -24,28 +24,15 @@ impl Spi for MockSpi {
 
         debug!("Opening SPI port: {} (baud: {} mosi: {} miso: {} sck: {} cs: {})", port, baud, mosi, miso, sck, cs);
 
-        let op = Kind::SpiInit{port, baud, mosi, miso, sck, cs};
-        let Op{kind, res} = inner.expected[inner.index].clone();
-
-        assert_eq!(op, kind);
-
-        inner.actual.push(op);
-        inner.index += 1;
-
-        Ok(res)
+        inner.push_actual(Kind::SpiInit{port, baud, mosi, miso, sck, cs})
     }
 
     fn deinit(&mut self, handle: i32) -> Result<(), Error> {
         let mut inner = self.inner.lock().unwrap();
 
         debug!("Closing SPI handle: {}", handle);
-        let op = Kind::SpiDeinit{handle};
-        let Op{kind, ..} = inner.expected[inner.index].clone();
 
-        assert_eq!(op, kind);
-
-        inner.actual.push(op);
-        inner.index += 1;
+        inner.push_actual(Kind::SpiDeinit{handle})?;
 
         Ok(())
     }
@@ -53,19 +40,13 @@ impl Spi for MockSpi {
     fn read<'a>(&mut self, handle: i32, data: &mut [u8]) -> Result<(), Error> {
         let mut inner = self.inner.lock().unwrap();
 
-        let kind = inner.expected[inner.index].kind.clone();
-
-        if let Kind::SpiRead{data_in, ..} = &kind {
-            data.copy_from_slice(&data_in);
+        if let Some(Kind::SpiRead{data_in, ..}) = inner.next() {
+            super::fill(data, data_in);
         }
 
         debug!("SPI write read: {} data: {:02x?}", handle, data);
 
-        let op = Kind::SpiRead{handle, data_in: data.to_vec()};
-        assert_eq!(op, kind);
-
-        inner.actual.push(op);
-        inner.index += 1;
+        inner.push_actual(Kind::SpiRead{handle, data_in: data.to_vec()})?;
 
         Ok(())
     }
@@ -73,15 +54,9 @@ impl Spi for MockSpi {
     fn write<'a>(&mut self, handle: i32, data: &[u8]) -> Result<(), Error> {
         let mut inner = self.inner.lock().unwrap();
 
-        let op = Kind::SpiWrite{handle, data_out: data.to_vec()};
-        let kind = inner.expected[inner.index].kind.clone();
-
         debug!("SPI write handle: {} data: {:02x?}", handle, data);
 
-        assert_eq!(op, kind);
-
-        inner.actual.push(op);
-        inner.index += 1;
+        inner.push_actual(Kind::SpiWrite{handle, data_out: data.to_vec()})?;
 
         Ok(())
     }
@@ -89,21 +64,15 @@ impl Spi for MockSpi {
     fn transfer_inplace<'a>(&mut self, handle: i32, data: &mut [u8]) -> Result<(), Error> {
         let mut inner = self.inner.lock().unwrap();
 
-        let kind = inner.expected[inner.index].kind.clone();
-
         let d = data.to_vec();
 
-        if let Kind::SpiTransfer{data_in, ..} = &kind {
-            data.copy_from_slice(&data_in);
+        if let Some(Kind::SpiTransferInplace{data_in, ..}) = inner.next() {
+            super::fill(data, data_in);
         }
 
-        debug!("SPI transfer handle: {} write: {:02x?} read: {:02x?}", handle, d, data);
+        debug!("SPI transfer inplace handle: {} write: {:02x?} read: {:02x?}", handle, d, data);
 
-        let op = Kind::SpiTransfer{handle, data_out: d, data_in: data.to_vec()};
-        assert_eq!(op, kind);
-
-        inner.actual.push(op);
-        inner.index += 1;
+        inner.push_actual(Kind::SpiTransferInplace{handle, data_out: d, data_in: data.to_vec()})?;
         
         Ok(())
     }
@@ -111,19 +80,13 @@ impl Spi for MockSpi {
     fn transfer<'a>(&mut self, handle: i32, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
         let mut inner = self.inner.lock().unwrap();
 
-        let kind = inner.expected[inner.index].kind.clone();
-
-        if let Kind::SpiTransfer{data_in, ..} = &kind {
-            read.copy_from_slice(&data_in);
+        if let Some(Kind::SpiTransfer{data_in, ..}) = inner.next() {
+            super::fill(read, data_in);
         }
 
         debug!("SPI transfer handle: {} write: {:02x?} read: {:02x?}", handle, write, read);
 
-        let op = Kind::SpiTransfer{handle, data_out: write.to_vec(), data_in: read.to_vec()};
-        assert_eq!(op, kind);
-
-        inner.actual.push(op);
-        inner.index += 1;
+        inner.push_actual(Kind::SpiTransfer{handle, data_out: write.to_vec(), data_in: read.to_vec()})?;
         
         Ok(())
     }
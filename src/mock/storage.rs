@@ -0,0 +1,80 @@
+//! Mock storage driver implementation, backed by an in-memory store
+//!
+//! Values read are taken from the store rather than the expected
+//! operations, so scripts check the values a guest reads as well as
+//! those it writes.
+
+use std::sync::{Arc, Mutex};
+
+use log::debug;
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::{Storage, valid_key};
+use super::{Inner, Kind};
+
+pub struct MockStorage {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl MockStorage {
+    pub(crate) const fn new(inner: Arc<Mutex<Inner>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Storage for MockStorage {
+    fn get(&mut self, key: &str, buff: &mut [u8]) -> Result<Option<usize>, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let value = inner.storage.get(key).cloned();
+
+        debug!("Storage get {}: {:02x?}", key, value);
+
+        inner.push_actual(Kind::StorageGet{key: key.into(), value: value.clone()})?;
+
+        if !valid_key(key) {
+            return Err(Error::InvalidArg);
+        }
+
+        Ok(value.map(|v| {
+            let n = v.len().min(buff.len());
+            buff[..n].copy_from_slice(&v[..n]);
+            v.len()
+        }))
+    }
+
+    fn set(&mut self, key: &str, value: &[u8]) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("Storage set {}: {:02x?}", key, value);
+
+        inner.push_actual(Kind::StorageSet{key: key.into(), value: value.to_vec()})?;
+
+        if !valid_key(key) {
+            return Err(Error::InvalidArg);
+        }
+
+        inner.storage.insert(key.into(), value.to_vec());
+
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<bool, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let existed = inner.storage.contains_key(key);
+
+        debug!("Storage remove {} (existed: {})", key, existed);
+
+        inner.push_actual(Kind::StorageRemove{key: key.into(), existed})?;
+
+        if !valid_key(key) {
+            return Err(Error::InvalidArg);
+        }
+
+        inner.storage.remove(key);
+
+        Ok(existed)
+    }
+}
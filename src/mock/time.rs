@@ -0,0 +1,150 @@
+//! Mock time driver implementation, using a virtual clock
+//!
+//! Delays advance the clock instantly, as do waits on timers. Scripts
+//! advance the clock to the scripted value of each `time_now` operation.
+
+use std::sync::{Arc, Mutex};
+
+use log::{debug, error};
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::{Time, Timers};
+use super::{Inner, Kind};
+
+pub struct MockTime {
+    inner: Arc<Mutex<Inner>>,
+    timers: Timers,
+}
+
+impl MockTime {
+    pub(crate) fn new(inner: Arc<Mutex<Inner>>) -> Self {
+        Self { inner, timers: Timers::default() }
+    }
+}
+
+impl Time for MockTime {
+    fn delay_us(&mut self, us: u32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        inner.now += us as u64;
+
+        debug!("Delay: {} us (now: {} us)", us, inner.now);
+
+        inner.push_actual(Kind::DelayUs{us})?;
+
+        Ok(())
+    }
+
+    fn delay_ms(&mut self, ms: u32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        inner.now += ms as u64 * 1000;
+
+        debug!("Delay: {} ms (now: {} us)", ms, inner.now);
+
+        inner.push_actual(Kind::DelayMs{ms})?;
+
+        Ok(())
+    }
+
+    fn now_us(&mut self) -> Result<u64, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        // The virtual clock never runs backwards
+        if let Some(Kind::TimeNow{us}) = inner.next() {
+            inner.now = inner.now.max(*us);
+        }
+        let us = inner.now;
+
+        debug!("Time now: {} us", us);
+
+        inner.push_actual(Kind::TimeNow{us})?;
+
+        Ok(us)
+    }
+
+    fn timer_start(&mut self, period_us: u32) -> Result<i32, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("Starting timer with period: {} us", period_us);
+
+        let handle = inner.push_actual(Kind::TimerStart{period_us})?;
+        self.timers.insert(handle, inner.now, period_us)?;
+
+        Ok(handle)
+    }
+
+    fn timer_stop(&mut self, handle: i32) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("Stopping timer handle: {}", handle);
+
+        self.timers.remove(handle);
+        inner.push_actual(Kind::TimerStop{handle})?;
+
+        Ok(())
+    }
+
+    fn timer_wait(&mut self, handle: i32) -> Result<u32, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let expirations = match self.timers.deadline(handle, inner.now) {
+            Some(d) => {
+                inner.now = d;
+                self.timers.take(handle, d)
+            },
+            None => None,
+        };
+
+        debug!("Timer wait handle: {} expirations: {:?} (now: {} us)", handle, expirations, inner.now);
+
+        inner.push_actual(Kind::TimerWait{handle, expirations: expirations.unwrap_or(0)})?;
+
+        match expirations {
+            Some(n) => Ok(n),
+            None => {
+                error!("No timer for handle: {}", handle);
+                Err(Error::NoDevice)
+            }
+        }
+    }
+
+    fn timer_poll(&mut self) -> Result<Option<i32>, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let handle = self.timers.poll(inner.now);
+
+        debug!("Timer poll: {:?} (now: {} us)", handle, inner.now);
+
+        inner.push_actual(Kind::TimerPoll{handle})?;
+
+        Ok(handle)
+    }
+
+    fn timer_wait_any(&mut self, timeout_us: Option<u32>) -> Result<Option<i32>, Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let now = inner.now;
+        let wake = self.timers.wake(now, timeout_us.map(|t| now + t as u64));
+        let handle = match wake {
+            Some(w) => {
+                inner.now = w;
+                self.timers.poll(w)
+            },
+            None => None,
+        };
+
+        debug!("Timer wait any: {:?} (now: {} us)", handle, inner.now);
+
+        inner.push_actual(Kind::TimerWaitAny{timeout_us, handle})?;
+
+        match wake {
+            Some(_) => Ok(handle),
+            None => {
+                error!("No timers running");
+                Err(Error::NoDevice)
+            }
+        }
+    }
+}
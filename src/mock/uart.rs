@@ -5,7 +5,7 @@ use std::sync::{Arc, Mutex};
 use log::debug;
 
 use wasm_embedded_spec::{Error, Uart};
-use super::{Inner, Op, Kind};
+use super::{Inner, Kind};
 
 pub struct MockUart {
     inner: Arc<Mutex<Inner>>,
@@ -23,28 +23,15 @@ impl Uart for MockUart {
 
         debug!("Opening UART port: {} (baud: {} tx: {} rx: {})", port, baud, tx, rx);
 
-        let op = Kind::UartInit{port, baud, tx, rx};
-        let Op{kind, res} = inner.expected[inner.index].clone();
-
-        assert_eq!(op, kind);
-
-        inner.actual.push(op);
-        inner.index += 1;
-
-        Ok(res)
+        inner.push_actual(Kind::UartInit{port, baud, tx, rx})
     }
 
     fn deinit(&mut self, handle: i32) -> Result<(), Error> {
         let mut inner = self.inner.lock().unwrap();
 
         debug!("Closing UART handle: {}", handle);
-        let op = Kind::UartDeinit{handle};
-        let Op{kind, ..} = inner.expected[inner.index].clone();
-
-        assert_eq!(op, kind);
 
-        inner.actual.push(op);
-        inner.index += 1;
+        inner.push_actual(Kind::UartDeinit{handle})?;
 
         Ok(())
     }
@@ -52,15 +39,9 @@ impl Uart for MockUart {
     fn write(&mut self, handle: i32, flags: u32, data: &[u8]) -> Result<(), Error> {
         let mut inner = self.inner.lock().unwrap();
 
-        let op = Kind::UartWrite{handle, flags, data_out: data.to_vec()};
-        let Op{kind, ..} = inner.expected[inner.index].clone();
-
         debug!("UART write handle: {} flags: {} data: {:02x?}", handle, flags, data);
 
-        assert_eq!(op, kind);
-
-        inner.actual.push(op);
-        inner.index += 1;
+        inner.push_actual(Kind::UartWrite{handle, flags, data_out: data.to_vec()})?;
 
         Ok(())
     }
@@ -68,19 +49,13 @@ impl Uart for MockUart {
     fn read(&mut self, handle: i32, flags: u32, buff: &mut [u8]) -> Result<(), Error> {
         let mut inner = self.inner.lock().unwrap();
 
-        let Op{kind, ..} = &inner.expected[inner.index];
-
         debug!("UART read handle: {} flags: {}", handle, flags);
 
-        if let Kind::UartRead{data_in, ..} = kind {
-            buff.copy_from_slice(&data_in);
+        if let Some(Kind::UartRead{data_in, ..}) = inner.next() {
+            super::fill(buff, data_in);
         }
 
-        let op = Kind::UartRead{handle, flags, data_in: buff.to_vec()};
-        assert_eq!(&op, kind);
-
-        inner.actual.push(op);
-        inner.index += 1;
+        inner.push_actual(Kind::UartRead{handle, flags, data_in: buff.to_vec()})?;
 
         Ok(())
     }
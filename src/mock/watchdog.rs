@@ -0,0 +1,32 @@
+//! Mock watchdog driver implementation
+
+use std::sync::{Arc, Mutex};
+
+use log::debug;
+
+use wasm_embedded_spec::Error;
+
+use crate::ext::Watchdog;
+use super::{Inner, Kind};
+
+pub struct MockWatchdog {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl MockWatchdog {
+    pub(crate) const fn new(inner: Arc<Mutex<Inner>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Watchdog for MockWatchdog {
+    fn kick(&mut self) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+
+        debug!("Watchdog kick (now: {} us)", inner.now);
+
+        inner.push_actual(Kind::WatchdogKick)?;
+
+        Ok(())
+    }
+}
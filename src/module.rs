@@ -0,0 +1,504 @@
+//! Guest module inspection and invocation
+//!
+//! Modules are only rewritten where required (to apply `--invoke`, or to
+//! shim WASI arguments under wasm3), so modules using features parity-wasm
+//! cannot parse are otherwise loaded unmodified.
+
+use std::{format, path::PathBuf, string::String, vec, vec::Vec};
+
+use log::debug;
+use parity_wasm::elements::{
+    self, Module, External, Internal, ExportEntry,
+    Func, FuncBody, Instruction, Instructions, IndexMap,
+    Type, FunctionType,
+    ValueType,
+};
+
+/// WASI (preview 1) import module name
+pub const WASI_MODULE: &str = "wasi_snapshot_preview1";
+
+/// Guest entrypoint export, called by the runtimes
+pub const ENTRYPOINT: &str = "_start";
+
+/// Reactor initialisation export, called prior to invoked functions
+pub const INITIALIZE: &str = "_initialize";
+
+/// Static constructor function, called prior to invoked functions where
+/// the module does not export [`INITIALIZE`]
+pub const CALL_CTORS: &str = "__wasm_call_ctors";
+
+/// Name of the entrypoint wrapper generated for invoked functions
+const INVOKE_FN: &str = "__wasme_invoke";
+
+/// Guest exited with a non-zero exit code, from WASI `proc_exit` or an
+/// entrypoint returning an `i32`
+#[derive(Clone, PartialEq, Debug, thiserror::Error)]
+#[error("guest exited with code {0}")]
+pub struct Exit(pub i32);
+
+/// Guest trapped during execution
+#[derive(Clone, PartialEq, Debug, thiserror::Error)]
+#[error("guest trapped: {reason}")]
+pub struct Trap {
+    /// Trap reason
+    pub reason: String,
+    /// Guest backtrace, symbolized using the module name section and
+    /// DWARF debug info where available
+    pub backtrace: Option<String>,
+}
+
+/// Map a guest exit code to a result, exiting with zero being success
+pub fn exit(code: i32) -> anyhow::Result<()> {
+    match code {
+        0 => {
+            debug!("Guest exited with code 0");
+            Ok(())
+        },
+        c => Err(Exit(c).into()),
+    }
+}
+
+/// Guest invocation options
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Guest {
+    /// Export to invoke in place of the default entrypoint
+    pub invoke: Option<String>,
+    /// Arguments passed to the guest (including the program name)
+    pub args: Vec<String>,
+    /// Environment variables passed to the guest
+    pub env: Vec<(String, String)>,
+    /// Directories preopened for the guest at the same path, where the
+    /// runtime links WASI
+    pub dirs: Vec<PathBuf>,
+}
+
+impl Guest {
+    /// Apply invocation options to a guest module, returning the module
+    /// unmodified where no options require rewriting
+    pub fn apply(&self, bin: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let invoke = match self.invoke.as_deref() {
+            Some(e) if e != ENTRYPOINT => e,
+            _ => return Ok(bin.to_vec()),
+        };
+
+        let m = parse(bin)?;
+
+        // Parse any existing names so these are preserved
+        let mut m = m.parse_names().unwrap_or_else(|(_, m)| m);
+
+        set_entrypoint(&mut m, invoke)?;
+
+        elements::serialize(m)
+            .map_err(|e| anyhow::anyhow!("Failed to serialise module: {:?}", e))
+    }
+
+    /// Provide arguments and environment to guests importing the WASI
+    /// `args_*` and `environ_*` functions, for runtimes not linking WASI,
+    /// by replacing these imports with functions returning the configured
+    /// values (returning the module unmodified where these are not imported)
+    pub fn shim_args(&self, bin: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let m = parse(bin)?;
+        let mut m = m.parse_names().unwrap_or_else(|(_, m)| m);
+
+        let args: Vec<&[u8]> = self.args.iter().map(|a| a.as_bytes()).collect();
+        let env: Vec<String> = self.env.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        let env: Vec<&[u8]> = env.iter().map(|e| e.as_bytes()).collect();
+
+        let shims = [
+            ("args_sizes_get", sizes_get(&args)),
+            ("args_get", strings_get(&args)),
+            ("environ_sizes_get", sizes_get(&env)),
+            ("environ_get", strings_get(&env)),
+        ];
+        if !replace_imports(&mut m, WASI_MODULE, &shims)? {
+            return Ok(bin.to_vec());
+        }
+
+        elements::serialize(m)
+            .map_err(|e| anyhow::anyhow!("Failed to serialise module: {:?}", e))
+    }
+}
+
+/// Function imported by a guest module
+#[derive(Clone, PartialEq, Debug)]
+pub struct Import {
+    /// Import module name
+    pub module: String,
+    /// Import function name
+    pub name: String,
+}
+
+/// Parse a guest module
+pub fn parse(bin: &[u8]) -> anyhow::Result<Module> {
+    elements::deserialize_buffer(bin)
+        .map_err(|e| anyhow::anyhow!("Failed to parse module: {:?}", e))
+}
+
+/// Fetch functions imported by a guest module
+pub fn imports(bin: &[u8]) -> anyhow::Result<Vec<Import>> {
+    let m = parse(bin)?;
+
+    let imports = match m.import_section() {
+        Some(s) => s.entries().iter()
+            .filter(|e| matches!(e.external(), External::Function(_)))
+            .map(|e| Import{ module: e.module().into(), name: e.field().into() })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    debug!("Module imports: {:?}", imports);
+
+    Ok(imports)
+}
+
+/// Check a guest module does not require WASI imports, for runtimes not
+/// linking WASI, so this fails with a clear error prior to loading
+pub fn check_wasi(bin: &[u8]) -> anyhow::Result<()> {
+    let wasi: Vec<String> = imports(bin)?.into_iter()
+        .filter(|i| i.module == WASI_MODULE)
+        .map(|i| i.name)
+        .collect();
+
+    if !wasi.is_empty() {
+        return Err(anyhow::anyhow!("Module requires unsupported WASI imports: {}", wasi.join(", ")));
+    }
+
+    Ok(())
+}
+
+/// Check a guest module does not import extended peripherals, for runtimes
+/// not binding these, so this fails with a clear error prior to loading
+pub fn check_ext(bin: &[u8]) -> anyhow::Result<()> {
+    let ext: Vec<String> = imports(bin)?.into_iter()
+        .filter(|i| crate::ext::MODULES.contains(&i.module.as_str()))
+        .map(|i| format!("{}::{}", i.module, i.name))
+        .collect();
+
+    if !ext.is_empty() {
+        return Err(anyhow::anyhow!("Module requires extended peripherals unsupported by this runtime: {}", ext.join(", ")));
+    }
+
+    Ok(())
+}
+
+/// Find the index of an exported function
+fn find_export(m: &Module, name: &str) -> Option<u32> {
+    m.export_section()
+        .and_then(|s| s.entries().iter().find(|e| e.field() == name))
+        .and_then(|e| match e.internal() {
+            Internal::Function(idx) => Some(*idx),
+            _ => None,
+        })
+}
+
+/// Fetch the type index of a function by index
+fn type_ref(m: &Module, idx: u32) -> Option<u32> {
+    let imported = m.import_count(elements::ImportCountType::Function) as u32;
+
+    match idx < imported {
+        true => m.import_section()?.entries().iter()
+            .filter_map(|e| match e.external() {
+                External::Function(t) => Some(*t),
+                _ => None,
+            })
+            .nth(idx as usize),
+        false => Some(m.function_section()?.entries().get((idx - imported) as usize)?.type_ref()),
+    }
+}
+
+/// Fetch the type of a function by index
+fn function_type(m: &Module, idx: u32) -> Option<&FunctionType> {
+    match m.type_section()?.types().get(type_ref(m, idx)? as usize)? {
+        Type::Function(f) => Some(f),
+    }
+}
+
+/// Check a guest module entrypoint does not return an exit code, for
+/// runtimes not reporting these, so this fails with a clear error prior to
+/// loading
+pub fn check_exit(bin: &[u8]) -> anyhow::Result<()> {
+    let m = parse(bin)?;
+
+    let returns = match find_export(&m, ENTRYPOINT) {
+        Some(idx) => entry_returns(&m, idx)?,
+        None => false,
+    };
+
+    if returns {
+        return Err(anyhow::anyhow!("Module entrypoint returns an exit code, unsupported by this runtime"));
+    }
+
+    Ok(())
+}
+
+/// Check whether an entrypoint returns an exit code, rejecting entrypoints
+/// with unsupported signatures
+fn entry_returns(m: &Module, idx: u32) -> anyhow::Result<bool> {
+    let t = match function_type(m, idx) {
+        Some(t) => t,
+        None => return Err(anyhow::anyhow!("Failed to resolve entrypoint type")),
+    };
+
+    match (t.params(), t.results()) {
+        ([], []) => Ok(false),
+        ([], [ValueType::I32]) => Ok(true),
+        _ => Err(anyhow::anyhow!("Entrypoint must take no arguments and return nothing or an i32 (found {:?})", t)),
+    }
+}
+
+/// Export the named function as the guest entrypoint
+///
+/// Where the module has an initialisation function ([`INITIALIZE`] or
+/// [`CALL_CTORS`]) not called by the target, the entrypoint is a generated
+/// wrapper calling this prior to the target, as `_start` would.
+fn set_entrypoint(m: &mut Module, name: &str) -> anyhow::Result<()> {
+    let target = match find_export(m, name) {
+        Some(i) => i,
+        None => return Err(anyhow::anyhow!("Module has no exported function: {}", name)),
+    };
+
+    // Check the target signature prior to rewriting
+    entry_returns(m, target)?;
+
+    let idx = match find_init(m) {
+        Some(init) if !calls(m, target, init) => {
+            debug!("Calling initialisation function {} prior to {}", init, name);
+            add_wrapper(m, init, target)?
+        },
+        _ => target,
+    };
+
+    debug!("Using entrypoint: {} (function {})", name, idx);
+
+    let exports = match m.export_section_mut() {
+        Some(s) => s.entries_mut(),
+        None => return Err(anyhow::anyhow!("Module has no exports")),
+    };
+    exports.retain(|e| e.field() != ENTRYPOINT);
+    exports.push(ExportEntry::new(ENTRYPOINT.into(), Internal::Function(idx)));
+
+    Ok(())
+}
+
+/// Find the module initialisation function, exported as [`INITIALIZE`] or
+/// [`CALL_CTORS`], or named [`CALL_CTORS`] in the name section
+fn find_init(m: &Module) -> Option<u32> {
+    let idx = find_export(m, INITIALIZE)
+        .or_else(|| find_export(m, CALL_CTORS))
+        .or_else(|| {
+            m.names_section()?.functions()?.names().iter()
+                .find(|(_, n)| n.as_str() == CALL_CTORS)
+                .map(|(idx, _)| idx)
+        })?;
+
+    // Initialisation functions take no arguments and return nothing
+    match function_type(m, idx) {
+        Some(t) if t.params().is_empty() && t.results().is_empty() => Some(idx),
+        _ => None,
+    }
+}
+
+/// Check whether a module function directly calls another function
+fn calls(m: &Module, caller: u32, callee: u32) -> bool {
+    let imported = m.import_count(elements::ImportCountType::Function) as u32;
+
+    let body = match caller.checked_sub(imported) {
+        Some(i) => m.code_section().and_then(|s| s.bodies().get(i as usize)),
+        None => None,
+    };
+
+    body.map(|b| b.code().elements().contains(&Instruction::Call(callee)))
+        .unwrap_or(false)
+}
+
+/// Append a function calling `init` then `target`, with the signature of
+/// `target`, returning the index of the new function
+fn add_wrapper(m: &mut Module, init: u32, target: u32) -> anyhow::Result<u32> {
+    let t = match type_ref(m, target) {
+        Some(t) => t,
+        None => return Err(anyhow::anyhow!("Failed to resolve type of function {}", target)),
+    };
+
+    let idx = m.functions_space() as u32;
+    let body = vec![Instruction::Call(init), Instruction::Call(target), Instruction::End];
+
+    match m.function_section_mut() {
+        Some(s) => s.entries_mut().push(Func::new(t)),
+        None => return Err(anyhow::anyhow!("Module has no function section")),
+    }
+    match m.code_section_mut() {
+        Some(s) => s.bodies_mut().push(FuncBody::new(vec![], Instructions::new(body))),
+        None => return Err(anyhow::anyhow!("Module has no code section")),
+    }
+
+    // Name the wrapper for backtraces, where the module has names
+    if let Some(f) = m.names_section_mut().and_then(|n| n.functions_mut().as_mut()) {
+        f.names_mut().insert(idx, INVOKE_FN.into());
+    }
+
+    Ok(idx)
+}
+
+/// Build a WASI `*_sizes_get(count_ptr, buff_size_ptr)` function body
+fn sizes_get(strings: &[&[u8]]) -> Vec<Instruction> {
+    let size: usize = strings.iter().map(|s| s.len() + 1).sum();
+
+    vec![
+        Instruction::GetLocal(0),
+        Instruction::I32Const(strings.len() as i32),
+        Instruction::I32Store(2, 0),
+        Instruction::GetLocal(1),
+        Instruction::I32Const(size as i32),
+        Instruction::I32Store(2, 0),
+        Instruction::I32Const(0),
+        Instruction::End,
+    ]
+}
+
+/// Build a WASI `*_get(ptrs, buff)` function body, writing a pointer to each
+/// string followed by the nul-terminated strings
+fn strings_get(strings: &[&[u8]]) -> Vec<Instruction> {
+    let mut i = Vec::new();
+    let mut offset = 0;
+
+    for (n, s) in strings.iter().enumerate() {
+        // Write pointer to string
+        i.push(Instruction::GetLocal(0));
+        i.push(Instruction::GetLocal(1));
+        i.push(Instruction::I32Const(offset as i32));
+        i.push(Instruction::I32Add);
+        i.push(Instruction::I32Store(2, 4 * n as u32));
+
+        // Write string and terminator
+        for b in s.iter().chain(&[0]) {
+            i.push(Instruction::GetLocal(1));
+            i.push(Instruction::I32Const(*b as i32));
+            i.push(Instruction::I32Store8(0, offset as u32));
+            offset += 1;
+        }
+    }
+
+    i.push(Instruction::I32Const(0));
+    i.push(Instruction::End);
+
+    i
+}
+
+/// Replace imported functions with module functions using the provided bodies,
+/// remapping function indices as imports are removed, returning whether any
+/// imports were replaced
+fn replace_imports(m: &mut Module, module: &str, shims: &[(&str, Vec<Instruction>)]) -> anyhow::Result<bool> {
+    // Locate imports to be replaced, along with their types
+    let mut replaced = Vec::new();
+    if let Some(s) = m.import_section() {
+        let funcs = s.entries().iter().filter_map(|e| match e.external() {
+            External::Function(t) => Some((e, *t)),
+            _ => None,
+        });
+
+        for (idx, (e, t)) in funcs.enumerate() {
+            if e.module() != module {
+                continue;
+            }
+            if let Some((_, body)) = shims.iter().find(|(n, _)| *n == e.field()) {
+                replaced.push((idx as u32, t, body.clone()));
+            }
+        }
+    }
+
+    if replaced.is_empty() {
+        return Ok(false);
+    }
+
+    debug!("Replacing {} {} imports", replaced.len(), module);
+
+    // Build function index mapping, with replacements appended to module functions
+    let imported = m.import_count(elements::ImportCountType::Function) as u32;
+    let total = m.functions_space() as u32;
+    let remaining = imported - replaced.len() as u32;
+
+    let map = |idx: u32| -> u32 {
+        if let Some(n) = replaced.iter().position(|(i, ..)| *i == idx) {
+            return total - replaced.len() as u32 + n as u32;
+        }
+        match idx < imported {
+            true => idx - replaced.iter().filter(|(i, ..)| *i < idx).count() as u32,
+            false => idx - replaced.len() as u32,
+        }
+    };
+
+    // Remove replaced imports
+    if let Some(s) = m.import_section_mut() {
+        let mut idx = 0;
+        s.entries_mut().retain(|e| {
+            match e.external() {
+                External::Function(_) => {
+                    idx += 1;
+                    !replaced.iter().any(|(i, ..)| *i == idx - 1)
+                },
+                _ => true,
+            }
+        });
+    }
+
+    // Remap function references
+    if let Some(s) = m.code_section_mut() {
+        for b in s.bodies_mut() {
+            for i in b.code_mut().elements_mut() {
+                if let Instruction::Call(idx) = i {
+                    *idx = map(*idx);
+                }
+            }
+        }
+    }
+    if let Some(s) = m.export_section_mut() {
+        for e in s.entries_mut() {
+            if let Internal::Function(idx) = e.internal_mut() {
+                *idx = map(*idx);
+            }
+        }
+    }
+    if let Some(s) = m.elements_section_mut() {
+        for e in s.entries_mut() {
+            for idx in e.members_mut() {
+                *idx = map(*idx);
+            }
+        }
+    }
+    if let Some(idx) = m.start_section() {
+        m.set_start_section(map(idx));
+    }
+    if let Some(n) = m.names_section_mut() {
+        if let Some(f) = n.functions_mut() {
+            let mut names = IndexMap::default();
+            for (idx, name) in f.names().iter() {
+                names.insert(map(idx), name.clone());
+            }
+            *f.names_mut() = names;
+        }
+
+        // Local names are keyed by function index, replacements taking none
+        if let Some(l) = n.locals_mut() {
+            let mut locals = IndexMap::default();
+            for (idx, names) in l.local_names().iter() {
+                if !replaced.iter().any(|(i, ..)| *i == idx) {
+                    locals.insert(map(idx), names.clone());
+                }
+            }
+            *l.local_names_mut() = locals;
+        }
+    }
+
+    // Append replacement functions
+    match m.function_section_mut() {
+        Some(s) => s.entries_mut().extend(replaced.iter().map(|(_, t, _)| Func::new(*t))),
+        None => return Err(anyhow::anyhow!("Module has no function section")),
+    }
+    if let Some(s) = m.code_section_mut() {
+        s.bodies_mut().extend(replaced.into_iter().map(|(_, _, b)| FuncBody::new(vec![], Instructions::new(b))));
+    }
+
+    debug!("Module now imports {} functions", remaining);
+
+    Ok(true)
+}
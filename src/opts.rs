@@ -1,4 +1,9 @@
 
+use core::time::Duration;
+
+#[cfg(feature="std")]
+use std::{vec::Vec, path::PathBuf};
+
 use strum::{Display, EnumString, EnumVariantNames};
 
 /// WASM Server Configuration
@@ -8,13 +13,120 @@ pub struct Config {
     pub runtime: Runtime,
     /// Engine providing embedded-wasm APIs
     pub engine: Engine,
+    /// Guest execution limits
+    pub limits: Limits,
+}
+
+/// Guest execution limits
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Limits {
+    /// Instruction budget (fuel), enforced by wasmtime fuel metering
+    pub fuel: Option<u64>,
+    /// Wall-clock execution timeout, interrupting the guest once expired
+    pub timeout: Option<Duration>,
+    /// Maximum linear memory size in 64KiB pages
+    pub max_memory_pages: Option<u32>,
+    /// Maximum table size in elements
+    pub max_table_elements: Option<u32>,
+    /// Maximum number of concurrently executing guest instances
+    pub max_instances: Option<usize>,
+    /// Executing instances, counted against `max_instances`
+    #[cfg(feature="std")]
+    pub instances: crate::limits::Instances,
+}
+
+/// Convert a period in seconds to a [`Duration`], rejecting negative,
+/// non-finite and out of range values
+#[cfg(feature="std")]
+pub fn secs(secs: f64) -> Result<Duration, std::string::String> {
+    if !secs.is_finite() || secs < 0.0 || secs >= u64::MAX as f64 {
+        return Err(std::format!("invalid period '{}', expected a non-negative number of seconds", secs));
+    }
+
+    Ok(Duration::from_secs_f64(secs))
+}
+
+/// Peripherals available to a guest, used to partition hardware between
+/// concurrently executing modules (unrestricted where unset)
+#[cfg(feature="std")]
+#[derive(Clone, PartialEq, Default, Debug)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature="serde", serde(default))]
+pub struct Peripherals {
+    /// Permitted GPIO pins, using global sysfs numbering (port 0)
+    pub gpio: Option<Vec<i32>>,
+    /// Permitted I2C devices
+    pub i2c: Option<Vec<u32>>,
+    /// Permitted SPI devices
+    pub spi: Option<Vec<u32>>,
+    /// Permitted UART devices
+    pub uart: Option<Vec<u32>>,
+    /// Permitted PWM chips
+    pub pwm: Option<Vec<u32>>,
+    /// Permitted ADC (IIO) devices
+    pub adc: Option<Vec<u32>>,
+    /// Permitted CAN ports
+    pub can: Option<Vec<u32>>,
+    /// Permitted 1-Wire bus masters (bit-banged buses use GPIO pins)
+    pub onewire: Option<Vec<u32>>,
+    /// Permit bit-banged 1-Wire buses, which have best-effort timing
+    pub onewire_bitbang: bool,
 }
 
+/// Default storage quota in bytes
+#[cfg(feature="std")]
+pub const STORAGE_QUOTA: usize = 64 * 1024;
+
+/// Persistent storage backend for a guest
+#[cfg(feature="std")]
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature="serde", serde(tag="backend", rename_all="snake_case"))]
+pub enum StorageConfig {
+    /// Directory of files, one per key
+    File{
+        /// Storage directory, private to the guest
+        path: PathBuf,
+        /// Maximum total size of stored values in bytes
+        #[cfg_attr(feature="serde", serde(default="storage_quota"))]
+        quota: usize,
+    },
+    /// I2C EEPROM with 16-bit addressing (24C32 and larger)
+    Eeprom{
+        /// I2C device
+        bus: u32,
+        /// EEPROM device address
+        #[cfg_attr(feature="serde", serde(default="eeprom_addr"))]
+        addr: u8,
+        /// EEPROM size in bytes
+        size: usize,
+        /// EEPROM write page size in bytes
+        #[cfg_attr(feature="serde", serde(default="eeprom_page"))]
+        page: usize,
+    },
+}
+
+#[cfg(all(feature="std", feature="serde"))]
+fn storage_quota() -> usize {
+    STORAGE_QUOTA
+}
+
+#[cfg(all(feature="std", feature="serde"))]
+fn eeprom_addr() -> u8 {
+    0x50
+}
+
+#[cfg(all(feature="std", feature="serde"))]
+fn eeprom_page() -> usize {
+    32
+}
 
 /// Server runtime selector
 #[derive(Clone, PartialEq, Debug, clap::ValueEnum)]
 #[derive(Display, EnumVariantNames, EnumString)]
 #[strum(serialize_all = "snake_case")]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature="serde", serde(rename_all="snake_case"))]
 #[non_exhaustive]
 pub enum Runtime {
     /// Wasmtime based runtime
@@ -23,6 +135,22 @@ pub enum Runtime {
     Wasm3,
 }
 
+impl Runtime {
+    /// Fetch runtimes enabled in this build
+    #[cfg(feature="std")]
+    pub fn enabled() -> std::vec::Vec<Runtime> {
+        let mut r = std::vec::Vec::new();
+
+        #[cfg(feature="rt-wasmtime")]
+        r.push(Runtime::Wasmtime);
+
+        #[cfg(feature="rt-wasm3")]
+        r.push(Runtime::Wasm3);
+
+        r
+    }
+}
+
 impl Default for Runtime {
     fn default() -> Self {
         #[cfg(feature="rt-wasmtime")]
@@ -37,6 +165,8 @@ impl Default for Runtime {
 #[derive(Clone, PartialEq, Debug, clap::ValueEnum)]
 #[derive(Display, EnumVariantNames, EnumString)]
 #[strum(serialize_all = "snake_case")]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature="serde", serde(rename_all="snake_case"))]
 #[non_exhaustive]
 pub enum Engine {
     /// Mock provides mocked drivers for testing
@@ -54,3 +184,52 @@ impl Default for Engine {
         return Engine::Mock;
     }
 }
+
+/// Watchdog petting mode selector
+#[derive(Copy, Clone, PartialEq, Debug, clap::ValueEnum)]
+#[derive(Display, EnumVariantNames, EnumString)]
+#[strum(serialize_all = "snake_case")]
+#[cfg_attr(feature="serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature="serde", serde(rename_all="snake_case"))]
+pub enum WatchdogMode {
+    /// The runtime pets the watchdog while the guest is executing
+    Host,
+    /// The guest pets the watchdog via the `kick` host call
+    Guest,
+}
+
+impl Default for WatchdogMode {
+    fn default() -> Self {
+        WatchdogMode::Host
+    }
+}
+
+/// File format selector for mock configurations and recordings
+#[derive(Copy, Clone, PartialEq, Debug, clap::ValueEnum)]
+#[derive(Display, EnumVariantNames, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum Format {
+    /// TOML (default)
+    Toml,
+    /// JSON
+    Json,
+    /// YAML
+    Yaml,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Toml
+    }
+}
+
+/// Report format selector for mock run summaries
+#[derive(Copy, Clone, PartialEq, Debug, clap::ValueEnum)]
+#[derive(Display, EnumVariantNames, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum ReportFormat {
+    /// JUnit XML
+    Junit,
+    /// JSON
+    Json,
+}
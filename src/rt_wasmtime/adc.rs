@@ -0,0 +1,61 @@
+//! ADC bindings
+//!
+//! | Function | Parameters |
+//! |---|---|
+//! | `adc::init` | `device, channel, *handle` |
+//! | `adc::deinit` | `handle` |
+//! | `adc::read_raw` | `handle, *value` (`i32`) |
+//! | `adc::read` | `handle, *value` (`f32` millivolts) |
+//! | `adc::capture` | `handle, trigger, *samples, count` (`i32` per sample) |
+
+use std::{vec, vec::Vec};
+
+use wasmtime::{Caller, Linker};
+
+use wasm_embedded_spec::Error;
+
+use crate::{EngineExt, ext::Adc};
+use super::{Host, call};
+
+pub(crate) fn add_to_linker<E: EngineExt + 'static>(l: &mut Linker<Host<E>>) -> anyhow::Result<()> {
+    l.func_wrap("adc", "init", |mut c: Caller<'_, Host<E>>, device: i32, channel: i32, handle: i32| {
+        call(&mut c, |m, e| {
+            let h = e.adc().ok_or(Error::NoDevice)?.init(device as u32, channel as u32)?;
+            m.write_i32(handle, h)
+        })
+    })?;
+
+    l.func_wrap("adc", "deinit", |mut c: Caller<'_, Host<E>>, handle: i32| {
+        call(&mut c, |_m, e| e.adc().ok_or(Error::NoDevice)?.deinit(handle))
+    })?;
+
+    l.func_wrap("adc", "read_raw", |mut c: Caller<'_, Host<E>>, handle: i32, value: i32| {
+        call(&mut c, |m, e| {
+            let v = e.adc().ok_or(Error::NoDevice)?.read_raw(handle)?;
+            m.write_i32(value, v)
+        })
+    })?;
+
+    l.func_wrap("adc", "read", |mut c: Caller<'_, Host<E>>, handle: i32, value: i32| {
+        call(&mut c, |m, e| {
+            let v = e.adc().ok_or(Error::NoDevice)?.read(handle)?;
+            m.write(value, &v.to_le_bytes())
+        })
+    })?;
+
+    l.func_wrap("adc", "capture", |mut c: Caller<'_, Host<E>>, handle: i32, trigger: i32, samples: i32, count: i32| {
+        call(&mut c, |m, e| {
+            // Check the buffer prior to capture, so samples are not discarded
+            let len = (count as u32).checked_mul(4).ok_or(Error::InvalidArg)?;
+            m.slice(samples, len as i32)?;
+
+            let mut s = vec![0i32; count as u32 as usize];
+            e.adc().ok_or(Error::NoDevice)?.capture(handle, trigger as u32, &mut s)?;
+
+            let b: Vec<u8> = s.iter().flat_map(|v| v.to_le_bytes()).collect();
+            m.write(samples, &b)
+        })
+    })?;
+
+    Ok(())
+}
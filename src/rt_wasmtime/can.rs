@@ -0,0 +1,119 @@
+//! CAN bindings
+//!
+//! | Function | Parameters |
+//! |---|---|
+//! | `can::init` | `port, fd, *handle` |
+//! | `can::deinit` | `handle` |
+//! | `can::set_filters` | `handle, filters, count` |
+//! | `can::send` | `handle, frame` |
+//! | `can::receive` | `handle, timeout_ms, *frame, *received` (negative timeouts block indefinitely) |
+//!
+//! Frames are 72 bytes, a `u32` identifier followed by `u8` flags (bit 0
+//! extended, bit 1 remote, bit 2 CAN-FD), the `u8` data length (or the
+//! requested length for remote frames), two reserved bytes and 64 bytes of
+//! data. Filters are 12 bytes, `u32` identifier, mask and extended flag.
+
+use std::vec::Vec;
+
+use wasmtime::{Caller, Linker};
+
+use wasm_embedded_spec::Error;
+
+use crate::{EngineExt, ext::{Can, Frame, Filter, CANFD_MAX_LEN}};
+use super::{Host, GuestMem, call};
+
+/// Guest frame size in bytes
+const FRAME_SIZE: usize = 8 + CANFD_MAX_LEN;
+
+/// Guest filter size in bytes
+const FILTER_SIZE: usize = 12;
+
+const FLAG_EXTENDED: u8 = 1 << 0;
+const FLAG_REMOTE: u8 = 1 << 1;
+const FLAG_FD: u8 = 1 << 2;
+
+/// Read a frame from guest memory
+fn read_frame(m: &GuestMem, ptr: i32) -> Result<Frame, Error> {
+    let b = m.slice(ptr, FRAME_SIZE as i32)?;
+
+    let id = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+    let (flags, len) = (b[4], b[5] as usize);
+    let extended = flags & FLAG_EXTENDED != 0;
+
+    let f = match flags & FLAG_REMOTE != 0 {
+        true => Frame::remote(id, extended, len),
+        false if len <= CANFD_MAX_LEN => Frame::new(id, extended, flags & FLAG_FD != 0, &b[8..][..len]),
+        false => None,
+    };
+
+    f.ok_or(Error::InvalidArg)
+}
+
+/// Write a frame to guest memory
+fn write_frame(m: &mut GuestMem, ptr: i32, f: &Frame) -> Result<(), Error> {
+    let mut b = [0u8; FRAME_SIZE];
+
+    b[..4].copy_from_slice(&f.id.to_le_bytes());
+    b[4] = (f.extended as u8 * FLAG_EXTENDED) | (f.remote as u8 * FLAG_REMOTE) | (f.fd as u8 * FLAG_FD);
+    b[5] = f.len() as u8;
+    b[8..][..f.data().len()].copy_from_slice(f.data());
+
+    m.write(ptr, &b)
+}
+
+pub(crate) fn add_to_linker<E: EngineExt + 'static>(l: &mut Linker<Host<E>>) -> anyhow::Result<()> {
+    l.func_wrap("can", "init", |mut c: Caller<'_, Host<E>>, port: i32, fd: i32, handle: i32| {
+        call(&mut c, |m, e| {
+            let h = e.can().ok_or(Error::NoDevice)?.init(port as u32, fd != 0)?;
+            m.write_i32(handle, h)
+        })
+    })?;
+
+    l.func_wrap("can", "deinit", |mut c: Caller<'_, Host<E>>, handle: i32| {
+        call(&mut c, |_m, e| e.can().ok_or(Error::NoDevice)?.deinit(handle))
+    })?;
+
+    l.func_wrap("can", "set_filters", |mut c: Caller<'_, Host<E>>, handle: i32, filters: i32, count: i32| {
+        call(&mut c, |m, e| {
+            let len = (count as u32).checked_mul(FILTER_SIZE as u32).ok_or(Error::InvalidArg)?;
+            let b = m.slice(filters, len as i32)?;
+
+            let word = |f: &[u8], i: usize| u32::from_le_bytes([f[i], f[i + 1], f[i + 2], f[i + 3]]);
+            let f: Vec<Filter> = b.chunks_exact(FILTER_SIZE)
+                .map(|f| Filter{ id: word(f, 0), mask: word(f, 4), extended: word(f, 8) != 0 })
+                .collect();
+
+            e.can().ok_or(Error::NoDevice)?.set_filters(handle, &f)
+        })
+    })?;
+
+    l.func_wrap("can", "send", |mut c: Caller<'_, Host<E>>, handle: i32, frame: i32| {
+        call(&mut c, |m, e| {
+            let f = read_frame(m, frame)?;
+            e.can().ok_or(Error::NoDevice)?.send(handle, &f)
+        })
+    })?;
+
+    l.func_wrap("can", "receive", |mut c: Caller<'_, Host<E>>, handle: i32, timeout_ms: i32, frame: i32, received: i32| {
+        call(&mut c, |m, e| {
+            // Check output pointers prior to receiving, so frames are not discarded
+            m.slice(frame, FRAME_SIZE as i32)?;
+            m.slice(received, 4)?;
+
+            let timeout = match timeout_ms {
+                t if t < 0 => None,
+                t => Some(t as u32),
+            };
+
+            match e.can().ok_or(Error::NoDevice)?.receive(handle, timeout)? {
+                Some(f) => {
+                    write_frame(m, frame, &f)?;
+                    m.write_i32(received, 1)
+                },
+                None => m.write_i32(received, 0),
+            }
+        })
+    })?;
+
+    Ok(())
+}
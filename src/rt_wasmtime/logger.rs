@@ -0,0 +1,29 @@
+//! Logging bindings
+//!
+//! | Function | Parameters |
+//! |---|---|
+//! | `log::write` | `level, message, len` (1 error through 5 trace) |
+//!
+//! Messages are UTF-8, with invalid sequences replaced rather than
+//! rejected so partial messages still reach the host logs.
+
+use std::string::String;
+
+use wasmtime::{Caller, Linker};
+
+use wasm_embedded_spec::Error;
+
+use crate::{EngineExt, ext::{Logger, level}};
+use super::{Host, call};
+
+pub(crate) fn add_to_linker<E: EngineExt + 'static>(l: &mut Linker<Host<E>>) -> anyhow::Result<()> {
+    l.func_wrap("log", "write", |mut c: Caller<'_, Host<E>>, lvl: i32, message: i32, len: i32| {
+        call(&mut c, |m, e| {
+            let lvl = level(lvl as u32)?;
+            let msg = String::from_utf8_lossy(m.slice(message, len)?);
+            e.logger().ok_or(Error::NoDevice)?.write(lvl, &msg)
+        })
+    })?;
+
+    Ok(())
+}
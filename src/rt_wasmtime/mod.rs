@@ -0,0 +1,317 @@
+//! Wasmtime runtime, binding engine drivers to guest imports
+//!
+//! Host calls return an `i32` status, zero on success or an error code
+//! (see [`status`]), with results written through guest pointers (marked
+//! `*` in the binding tables of each module). Pointers and lengths are
+//! checked against guest memory, out of bounds accesses failing with
+//! [`Error::InvalidArg`] rather than trapping. Drivers not provided by the
+//! engine fail with [`Error::NoDevice`].
+//!
+//! Fuel limits use wasmtime fuel metering, and guests are interrupted via
+//! epoch interruption once the bound [`StopToken`] is stopped, so guests
+//! are stopped even where these do not call into the host. Memory and
+//! table limits are enforced by the store resource limiter, covering both
+//! defined and imported memories and tables.
+
+use core::ops::Range;
+
+use log::debug;
+use wasmtime::{Caller, Config, InstancePre, Linker, Module, ResourceLimiter, Store, Trap, WasmBacktrace, WasmBacktraceDetails};
+use wasi_common::{I32Exit, WasiCtx, sync::{Dir, WasiCtxBuilder, ambient_authority}};
+
+use wasm_embedded_spec::Error;
+
+use crate::{EngineExt, limits::LimitExceeded, module::{self, Guest, ENTRYPOINT}, opts::Limits, server::{StopToken, Stopped}};
+
+mod spec;
+mod pwm;
+mod adc;
+mod can;
+mod onewire;
+mod time;
+mod watchdog;
+mod storage;
+mod logger;
+
+pub use time::TIMER_HANDLER;
+
+/// Host call status on success
+pub const OK: i32 = 0;
+
+/// Convert a driver result to a host call status, errors following the
+/// `$errno` enum of the spec `common.witx`
+///
+/// The spec only generates its `Errno` type with wiggle bindings, which
+/// are not used here, so values are listed explicitly.
+pub fn status(res: Result<(), Error>) -> i32 {
+    match res {
+        Ok(_) => OK,
+        Err(Error::InvalidArg) => 1,
+        Err(Error::Failed) => 2,
+        Err(Error::NoDevice) => 3,
+        Err(Error::Unexpected) => 4,
+        Err(Error::Unsupported) => 5,
+    }
+}
+
+/// WebAssembly page size in bytes
+const PAGE_SIZE: usize = 64 * 1024;
+
+/// Store state, holding the engine and WASI context bound to the guest
+pub(crate) struct Host<E> {
+    engine: E,
+    wasi: WasiCtx,
+    limiter: Limiter,
+}
+
+/// Memory and table limits, applied to the guest store
+///
+/// Memories and tables exceeding the limits on instantiation fail with
+/// [`LimitExceeded`], while later growth fails in the guest.
+struct Limiter {
+    max_memory_pages: Option<u32>,
+    max_table_elements: Option<u32>,
+    instantiated: bool,
+}
+
+impl Limiter {
+    /// Check a requested size against a limit
+    fn check(&self, requested: usize, limit: Option<u32>, exceeded: impl FnOnce(u32, u32) -> LimitExceeded) -> anyhow::Result<bool> {
+        let limit = match limit {
+            Some(l) if requested > l as usize => l,
+            _ => return Ok(true),
+        };
+
+        let requested = requested.min(u32::MAX as usize) as u32;
+        match self.instantiated {
+            true => {
+                debug!("Rejecting guest growth: {}", exceeded(requested, limit));
+                Ok(false)
+            },
+            false => Err(exceeded(requested, limit).into()),
+        }
+    }
+}
+
+impl ResourceLimiter for Limiter {
+    fn memory_growing(&mut self, _current: usize, desired: usize, _maximum: Option<usize>) -> anyhow::Result<bool> {
+        let pages = desired.div_ceil(PAGE_SIZE);
+        self.check(pages, self.max_memory_pages, |requested, limit| LimitExceeded::Memory{ requested, limit })
+    }
+
+    fn table_growing(&mut self, _current: usize, desired: usize, _maximum: Option<usize>) -> anyhow::Result<bool> {
+        self.check(desired, self.max_table_elements, |requested, limit| LimitExceeded::Table{ requested, limit })
+    }
+}
+
+/// Wasmtime runtime, executing a guest module against the provided engine
+pub struct WasmtimeRuntime<E: EngineExt> {
+    store: Store<Host<E>>,
+    pre: InstancePre<Host<E>>,
+    fuel: Option<u64>,
+}
+
+impl<E: EngineExt + 'static> WasmtimeRuntime<E> {
+    /// Compile a guest module and resolve its imports, binding these to
+    /// the provided engine and the guest arguments, environment and
+    /// preopened directories, and applying the provided execution limits
+    pub fn new(engine: E, bin: &[u8], guest: &Guest, limits: &Limits) -> anyhow::Result<Self> {
+        let mut config = Config::new();
+        config.wasm_backtrace(true);
+        config.wasm_backtrace_details(WasmBacktraceDetails::Enable);
+        config.consume_fuel(limits.fuel.is_some());
+        config.epoch_interruption(true);
+
+        let wt = wasmtime::Engine::new(&config)?;
+        let module = Module::new(&wt, bin)?;
+
+        let mut linker = Linker::new(&wt);
+        wasi_common::sync::add_to_linker(&mut linker, |h: &mut Host<E>| &mut h.wasi)?;
+        spec::add_to_linker(&mut linker)?;
+        pwm::add_to_linker(&mut linker)?;
+        adc::add_to_linker(&mut linker)?;
+        can::add_to_linker(&mut linker)?;
+        onewire::add_to_linker(&mut linker)?;
+        time::add_to_linker(&mut linker)?;
+        watchdog::add_to_linker(&mut linker)?;
+        storage::add_to_linker(&mut linker)?;
+        logger::add_to_linker(&mut linker)?;
+
+        // Resolve imports prior to execution, so link failures are not
+        // reported as guest traps
+        let pre = linker.instantiate_pre(&module)?;
+
+        let wasi = wasi(guest)?;
+
+        let limiter = Limiter{
+            max_memory_pages: limits.max_memory_pages,
+            max_table_elements: limits.max_table_elements,
+            instantiated: false,
+        };
+
+        let mut store = Store::new(&wt, Host{ engine, wasi, limiter });
+        store.limiter(|h| &mut h.limiter);
+        if let Some(f) = limits.fuel {
+            store.set_fuel(f)?;
+        }
+
+        // Epochs are only incremented on stop, interrupting the guest
+        store.set_epoch_deadline(1);
+
+        Ok(Self{ store, pre, fuel: limits.fuel })
+    }
+
+    /// Bind the runtime to a [`StopToken`], interrupting the guest once
+    /// this is stopped
+    pub fn with_stop(self, stop: &StopToken) -> Self {
+        let wt = self.store.engine().clone();
+        stop.on_stop(move || wt.increment_epoch());
+        self
+    }
+
+    /// Instantiate the guest and execute its entrypoint
+    ///
+    /// Guest exits (via WASI `proc_exit` or an entrypoint returning an
+    /// `i32`) with non-zero codes are reported as [`module::Exit`], traps
+    /// as [`module::Trap`], exhausted fuel as [`LimitExceeded::Fuel`] and
+    /// interrupted guests as [`Stopped`].
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        // Guests may exit or trap during instantiation, via start functions
+        let instance = match self.pre.instantiate(&mut self.store) {
+            Ok(i) => i,
+            Err(e) => return self.guest_result(e),
+        };
+        self.store.data_mut().limiter.instantiated = true;
+
+        let start = match instance.get_func(&mut self.store, ENTRYPOINT) {
+            Some(f) => f,
+            None => return Err(anyhow::anyhow!("Module has no exported function: {}", ENTRYPOINT)),
+        };
+
+        debug!("Executing guest entrypoint");
+
+        let res = match start.typed::<(), ()>(&self.store) {
+            Ok(f) => f.call(&mut self.store, ()).map(|_| 0),
+            Err(_) => match start.typed::<(), i32>(&self.store) {
+                Ok(f) => f.call(&mut self.store, ()),
+                Err(_) => return Err(anyhow::anyhow!("Entrypoint must take no arguments and return nothing or an i32 (found {})", start.ty(&self.store))),
+            },
+        };
+
+        match res {
+            Ok(code) => module::exit(code),
+            Err(e) => self.guest_result(e),
+        }
+    }
+
+    /// Fetch the engine bound to the guest
+    pub fn engine(&mut self) -> &mut E {
+        &mut self.store.data_mut().engine
+    }
+
+    /// Map errors arising during guest execution, recovering exit codes
+    /// and symbolized backtraces for traps
+    fn guest_result(&self, e: anyhow::Error) -> anyhow::Result<()> {
+        if let Some(I32Exit(code)) = e.downcast_ref::<I32Exit>() {
+            return module::exit(*code);
+        }
+
+        match (e.downcast_ref::<Trap>(), self.fuel) {
+            (Some(Trap::OutOfFuel), Some(f)) => Err(LimitExceeded::Fuel(f).into()),
+            (Some(Trap::Interrupt), _) => Err(Stopped.into()),
+            (Some(t), _) => Err(module::Trap{
+                reason: t.to_string(),
+                backtrace: e.downcast_ref::<WasmBacktrace>().map(|b| b.to_string()),
+            }.into()),
+            (None, _) => Err(e),
+        }
+    }
+}
+
+/// Validate a guest module, without compiling or linking this
+pub fn validate(bin: &[u8]) -> anyhow::Result<()> {
+    Module::validate(&wasmtime::Engine::default(), bin)
+}
+
+/// Build the WASI context for a guest
+fn wasi(guest: &Guest) -> anyhow::Result<WasiCtx> {
+    let mut b = WasiCtxBuilder::new();
+    b.inherit_stdout()
+        .inherit_stderr()
+        .args(&guest.args)?
+        .envs(&guest.env)?;
+
+    for d in &guest.dirs {
+        debug!("Preopening directory: {}", d.display());
+
+        let dir = Dir::open_ambient_dir(d, ambient_authority())
+            .map_err(|e| anyhow::anyhow!("Failed to open directory {}: {}", d.display(), e))?;
+        b.preopened_dir(dir, d)?;
+    }
+
+    Ok(b.build())
+}
+
+/// Guest linear memory, checking accesses are within bounds
+pub(crate) struct GuestMem<'a>(&'a mut [u8]);
+
+impl GuestMem<'_> {
+    /// Resolve a guest pointer and length, pointers and lengths being unsigned
+    fn range(&self, ptr: i32, len: usize) -> Result<Range<usize>, Error> {
+        let start = ptr as u32 as usize;
+
+        match start.checked_add(len) {
+            Some(end) if end <= self.0.len() => Ok(start..end),
+            _ => Err(Error::InvalidArg),
+        }
+    }
+
+    /// Borrow a guest buffer
+    pub fn slice(&self, ptr: i32, len: i32) -> Result<&[u8], Error> {
+        let r = self.range(ptr, len as u32 as usize)?;
+        Ok(&self.0[r])
+    }
+
+    /// Mutably borrow a guest buffer
+    pub fn slice_mut(&mut self, ptr: i32, len: i32) -> Result<&mut [u8], Error> {
+        let r = self.range(ptr, len as u32 as usize)?;
+        Ok(&mut self.0[r])
+    }
+
+    /// Borrow a guest UTF-8 string
+    pub fn str(&self, ptr: i32, len: i32) -> Result<&str, Error> {
+        core::str::from_utf8(self.slice(ptr, len)?).map_err(|_| Error::InvalidArg)
+    }
+
+    /// Copy data to guest memory
+    pub fn write(&mut self, ptr: i32, data: &[u8]) -> Result<(), Error> {
+        let r = self.range(ptr, data.len())?;
+        self.0[r].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Read a little-endian `i32`
+    pub fn read_i32(&self, ptr: i32) -> Result<i32, Error> {
+        let b = self.slice(ptr, 4)?;
+        Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Write a little-endian `i32`
+    pub fn write_i32(&mut self, ptr: i32, v: i32) -> Result<(), Error> {
+        self.write(ptr, &v.to_le_bytes())
+    }
+}
+
+/// Execute a host call with access to the engine and guest memory (where
+/// exported), returning the call status
+pub(crate) fn call<E>(c: &mut Caller<'_, Host<E>>, f: impl FnOnce(&mut GuestMem, &mut E) -> Result<(), Error>) -> i32 {
+    let res = match c.get_export("memory").and_then(|e| e.into_memory()) {
+        Some(m) => {
+            let (data, host) = m.data_and_store_mut(c);
+            f(&mut GuestMem(data), &mut host.engine)
+        },
+        None => f(&mut GuestMem(&mut []), &mut c.data_mut().engine),
+    };
+
+    status(res)
+}
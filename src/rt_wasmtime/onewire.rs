@@ -0,0 +1,67 @@
+//! 1-Wire bindings
+//!
+//! | Function | Parameters |
+//! |---|---|
+//! | `onewire::init` | `bus, pin, *handle` (negative pins use bus master `bus`) |
+//! | `onewire::deinit` | `handle` |
+//! | `onewire::reset` | `handle, *presence` |
+//! | `onewire::search` | `handle, *roms, count, *found` (8 bytes per ROM code) |
+//! | `onewire::write_byte` | `handle, data` |
+//! | `onewire::read_byte` | `handle, *data` |
+
+use std::{vec, vec::Vec};
+
+use wasmtime::{Caller, Linker};
+
+use wasm_embedded_spec::Error;
+
+use crate::{EngineExt, ext::{OneWire, Rom}};
+use super::{Host, call};
+
+pub(crate) fn add_to_linker<E: EngineExt + 'static>(l: &mut Linker<Host<E>>) -> anyhow::Result<()> {
+    l.func_wrap("onewire", "init", |mut c: Caller<'_, Host<E>>, bus: i32, pin: i32, handle: i32| {
+        call(&mut c, |m, e| {
+            let h = e.onewire().ok_or(Error::NoDevice)?.init(bus as u32, pin)?;
+            m.write_i32(handle, h)
+        })
+    })?;
+
+    l.func_wrap("onewire", "deinit", |mut c: Caller<'_, Host<E>>, handle: i32| {
+        call(&mut c, |_m, e| e.onewire().ok_or(Error::NoDevice)?.deinit(handle))
+    })?;
+
+    l.func_wrap("onewire", "reset", |mut c: Caller<'_, Host<E>>, handle: i32, presence: i32| {
+        call(&mut c, |m, e| {
+            let p = e.onewire().ok_or(Error::NoDevice)?.reset(handle)?;
+            m.write_i32(presence, p as i32)
+        })
+    })?;
+
+    l.func_wrap("onewire", "search", |mut c: Caller<'_, Host<E>>, handle: i32, roms: i32, count: i32, found: i32| {
+        call(&mut c, |m, e| {
+            // Check output buffers prior to searching the bus
+            let len = (count as u32).checked_mul(8).ok_or(Error::InvalidArg)?;
+            m.slice(roms, len as i32)?;
+            m.slice(found, 4)?;
+
+            let mut r: Vec<Rom> = vec![[0u8; 8]; count as u32 as usize];
+            let n = e.onewire().ok_or(Error::NoDevice)?.search(handle, &mut r)?;
+
+            m.write(roms, &r[..n].concat())?;
+            m.write_i32(found, n as i32)
+        })
+    })?;
+
+    l.func_wrap("onewire", "write_byte", |mut c: Caller<'_, Host<E>>, handle: i32, data: i32| {
+        call(&mut c, |_m, e| e.onewire().ok_or(Error::NoDevice)?.write_byte(handle, data as u8))
+    })?;
+
+    l.func_wrap("onewire", "read_byte", |mut c: Caller<'_, Host<E>>, handle: i32, data: i32| {
+        call(&mut c, |m, e| {
+            let d = e.onewire().ok_or(Error::NoDevice)?.read_byte(handle)?;
+            m.write_i32(data, d as i32)
+        })
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+//! PWM bindings
+//!
+//! | Function | Parameters |
+//! |---|---|
+//! | `pwm::init` | `chip, channel, *handle` |
+//! | `pwm::deinit` | `handle` |
+//! | `pwm::set_period` | `handle, period_ns` |
+//! | `pwm::set_duty_cycle` | `handle, duty_ns` |
+//! | `pwm::set_polarity` | `handle, polarity` (0 normal, 1 inversed) |
+//! | `pwm::enable` | `handle, enabled` |
+
+use wasmtime::{Caller, Linker};
+
+use wasm_embedded_spec::Error;
+
+use crate::{EngineExt, ext::{Pwm, Polarity}};
+use super::{Host, call};
+
+pub(crate) fn add_to_linker<E: EngineExt + 'static>(l: &mut Linker<Host<E>>) -> anyhow::Result<()> {
+    l.func_wrap("pwm", "init", |mut c: Caller<'_, Host<E>>, chip: i32, channel: i32, handle: i32| {
+        call(&mut c, |m, e| {
+            let h = e.pwm().ok_or(Error::NoDevice)?.init(chip as u32, channel as u32)?;
+            m.write_i32(handle, h)
+        })
+    })?;
+
+    l.func_wrap("pwm", "deinit", |mut c: Caller<'_, Host<E>>, handle: i32| {
+        call(&mut c, |_m, e| e.pwm().ok_or(Error::NoDevice)?.deinit(handle))
+    })?;
+
+    l.func_wrap("pwm", "set_period", |mut c: Caller<'_, Host<E>>, handle: i32, period_ns: i32| {
+        call(&mut c, |_m, e| e.pwm().ok_or(Error::NoDevice)?.set_period(handle, period_ns as u32))
+    })?;
+
+    l.func_wrap("pwm", "set_duty_cycle", |mut c: Caller<'_, Host<E>>, handle: i32, duty_ns: i32| {
+        call(&mut c, |_m, e| e.pwm().ok_or(Error::NoDevice)?.set_duty_cycle(handle, duty_ns as u32))
+    })?;
+
+    l.func_wrap("pwm", "set_polarity", |mut c: Caller<'_, Host<E>>, handle: i32, polarity: i32| {
+        call(&mut c, |_m, e| {
+            let p = match polarity {
+                0 => Polarity::Normal,
+                1 => Polarity::Inversed,
+                _ => return Err(Error::InvalidArg),
+            };
+            e.pwm().ok_or(Error::NoDevice)?.set_polarity(handle, p)
+        })
+    })?;
+
+    l.func_wrap("pwm", "enable", |mut c: Caller<'_, Host<E>>, handle: i32, enabled: i32| {
+        call(&mut c, |_m, e| e.pwm().ok_or(Error::NoDevice)?.enable(handle, enabled != 0))
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,235 @@
+//! Spec peripheral bindings, following the spec WITX ABI
+//!
+//! | Function | Parameters |
+//! |---|---|
+//! | `gpio::init` | `port, pin, output, *handle` |
+//! | `gpio::deinit` | `handle` |
+//! | `gpio::set` | `handle, state` |
+//! | `gpio::get` | `handle, *state` |
+//! | `i2c::init` | `port, baud, sda, scl, *handle` |
+//! | `i2c::deinit` | `handle` |
+//! | `i2c::write` | `handle, addr, &data` |
+//! | `i2c::read` | `handle, addr, &buff` |
+//! | `i2c::write_read` | `handle, addr, &data, &buff` |
+//! | `spi::init` | `port, baud, mosi, miso, sck, cs, *handle` |
+//! | `spi::deinit` | `handle` |
+//! | `spi::read` | `handle, &buff` |
+//! | `spi::write` | `handle, &data` |
+//! | `spi::transfer` | `handle, &read, &write` |
+//! | `spi::transfer_inplace` | `handle, &data` |
+//! | `spi::exec` | `handle, ops, count` |
+//! | `uart::init` | `port, baud, tx, rx, *handle` |
+//! | `uart::deinit` | `handle` |
+//! | `uart::write` | `handle, flags, &data` |
+//! | `uart::read` | `handle, flags, &buff` |
+//! | `device::spi` | `&handles` |
+//! | `device::i2c` | `&handles` |
+//! | `device::gpio_in` | `&handles` |
+//! | `device::gpio_out` | `&handles` |
+//!
+//! Buffers (marked `&`) are passed as pointers to 8 byte records, a `u32`
+//! pointer followed by a `u32` length. SPI operations are 12 byte records,
+//! a `u32` kind (`0` in place transfer, `1` read, `2` write) followed by a
+//! buffer, executed in order until one fails. Device configuration is
+//! experimental in the spec and not provided by engines, so these calls
+//! fail with [`Error::Unsupported`].
+
+use core::convert::TryFrom;
+
+use embedded_hal::digital::PinState;
+use wasmtime::{Caller, Linker};
+
+use wasm_embedded_spec::{Error, Gpio, I2c, Spi, Uart};
+
+use crate::EngineExt;
+use super::{Host, GuestMem, call};
+
+/// Guest SPI operation size in bytes
+const OP_SIZE: i32 = 12;
+
+const OP_TRANSFER: i32 = 0;
+const OP_READ: i32 = 1;
+const OP_WRITE: i32 = 2;
+
+/// Convert a guest I2C address
+fn addr(addr: i32) -> Result<u16, Error> {
+    u16::try_from(addr).map_err(|_| Error::InvalidArg)
+}
+
+/// Resolve a guest buffer record to its pointer and length
+fn buff(m: &GuestMem, rec: i32) -> Result<(i32, i32), Error> {
+    Ok((m.read_i32(rec)?, m.read_i32(rec.wrapping_add(4))?))
+}
+
+pub(crate) fn add_to_linker<E: EngineExt + 'static>(l: &mut Linker<Host<E>>) -> anyhow::Result<()> {
+    l.func_wrap("gpio", "init", |mut c: Caller<'_, Host<E>>, port: i32, pin: i32, output: i32, handle: i32| {
+        call(&mut c, |m, e| {
+            let h = e.gpio().ok_or(Error::NoDevice)?.init(port, pin, output != 0)?;
+            m.write_i32(handle, h)
+        })
+    })?;
+
+    l.func_wrap("gpio", "deinit", |mut c: Caller<'_, Host<E>>, handle: i32| {
+        call(&mut c, |_m, e| e.gpio().ok_or(Error::NoDevice)?.deinit(handle))
+    })?;
+
+    l.func_wrap("gpio", "set", |mut c: Caller<'_, Host<E>>, handle: i32, state: i32| {
+        let state = match state {
+            0 => PinState::Low,
+            _ => PinState::High,
+        };
+
+        call(&mut c, |_m, e| e.gpio().ok_or(Error::NoDevice)?.set(handle, state))
+    })?;
+
+    l.func_wrap("gpio", "get", |mut c: Caller<'_, Host<E>>, handle: i32, state: i32| {
+        call(&mut c, |m, e| {
+            let s = e.gpio().ok_or(Error::NoDevice)?.get(handle)?;
+            m.write_i32(state, (s == PinState::High) as i32)
+        })
+    })?;
+
+    l.func_wrap("i2c", "init", |mut c: Caller<'_, Host<E>>, port: i32, baud: i32, sda: i32, scl: i32, handle: i32| {
+        call(&mut c, |m, e| {
+            let h = e.i2c().ok_or(Error::NoDevice)?.init(port as u32, baud as u32, sda, scl)?;
+            m.write_i32(handle, h)
+        })
+    })?;
+
+    l.func_wrap("i2c", "deinit", |mut c: Caller<'_, Host<E>>, handle: i32| {
+        call(&mut c, |_m, e| e.i2c().ok_or(Error::NoDevice)?.deinit(handle))
+    })?;
+
+    l.func_wrap("i2c", "write", |mut c: Caller<'_, Host<E>>, handle: i32, a: i32, data: i32| {
+        call(&mut c, |m, e| {
+            let (data, len) = buff(m, data)?;
+            let d = m.slice(data, len)?;
+            e.i2c().ok_or(Error::NoDevice)?.write(handle, addr(a)?, d)
+        })
+    })?;
+
+    l.func_wrap("i2c", "read", |mut c: Caller<'_, Host<E>>, handle: i32, a: i32, b: i32| {
+        call(&mut c, |m, e| {
+            let (b, len) = buff(m, b)?;
+            let b = m.slice_mut(b, len)?;
+            e.i2c().ok_or(Error::NoDevice)?.read(handle, addr(a)?, b)
+        })
+    })?;
+
+    l.func_wrap("i2c", "write_read", |mut c: Caller<'_, Host<E>>, handle: i32, a: i32, data: i32, b: i32| {
+        call(&mut c, |m, e| {
+            let (data, data_len) = buff(m, data)?;
+            let (b, b_len) = buff(m, b)?;
+
+            // Copy outgoing data, which may overlap the read buffer
+            let d = m.slice(data, data_len)?.to_vec();
+            let b = m.slice_mut(b, b_len)?;
+            e.i2c().ok_or(Error::NoDevice)?.write_read(handle, addr(a)?, &d, b)
+        })
+    })?;
+
+    l.func_wrap("spi", "init", |mut c: Caller<'_, Host<E>>, port: i32, baud: i32, mosi: i32, miso: i32, sck: i32, cs: i32, handle: i32| {
+        call(&mut c, |m, e| {
+            let h = e.spi().ok_or(Error::NoDevice)?.init(port as u32, baud as u32, mosi, miso, sck, cs)?;
+            m.write_i32(handle, h)
+        })
+    })?;
+
+    l.func_wrap("spi", "deinit", |mut c: Caller<'_, Host<E>>, handle: i32| {
+        call(&mut c, |_m, e| e.spi().ok_or(Error::NoDevice)?.deinit(handle))
+    })?;
+
+    l.func_wrap("spi", "read", |mut c: Caller<'_, Host<E>>, handle: i32, b: i32| {
+        call(&mut c, |m, e| {
+            let (b, len) = buff(m, b)?;
+            let b = m.slice_mut(b, len)?;
+            e.spi().ok_or(Error::NoDevice)?.read(handle, b)
+        })
+    })?;
+
+    l.func_wrap("spi", "write", |mut c: Caller<'_, Host<E>>, handle: i32, data: i32| {
+        call(&mut c, |m, e| {
+            let (data, len) = buff(m, data)?;
+            let d = m.slice(data, len)?;
+            e.spi().ok_or(Error::NoDevice)?.write(handle, d)
+        })
+    })?;
+
+    l.func_wrap("spi", "transfer", |mut c: Caller<'_, Host<E>>, handle: i32, read: i32, write: i32| {
+        call(&mut c, |m, e| {
+            let (read, read_len) = buff(m, read)?;
+            let (write, write_len) = buff(m, write)?;
+
+            let w = m.slice(write, write_len)?.to_vec();
+            let r = m.slice_mut(read, read_len)?;
+            e.spi().ok_or(Error::NoDevice)?.transfer(handle, r, &w)
+        })
+    })?;
+
+    l.func_wrap("spi", "transfer_inplace", |mut c: Caller<'_, Host<E>>, handle: i32, data: i32| {
+        call(&mut c, |m, e| {
+            let (data, len) = buff(m, data)?;
+            let d = m.slice_mut(data, len)?;
+            e.spi().ok_or(Error::NoDevice)?.transfer_inplace(handle, d)
+        })
+    })?;
+
+    l.func_wrap("spi", "exec", |mut c: Caller<'_, Host<E>>, handle: i32, ops: i32, count: i32| {
+        call(&mut c, |m, e| {
+            let spi = e.spi().ok_or(Error::NoDevice)?;
+
+            // Check the operation list is within bounds prior to execution
+            let len = (count as u32).checked_mul(OP_SIZE as u32).ok_or(Error::InvalidArg)?;
+            m.slice(ops, len as i32)?;
+
+            for i in 0..count {
+                let op = ops.wrapping_add(i.wrapping_mul(OP_SIZE));
+                let (data, len) = buff(m, op.wrapping_add(4))?;
+
+                match m.read_i32(op)? {
+                    OP_TRANSFER => spi.transfer_inplace(handle, m.slice_mut(data, len)?)?,
+                    OP_READ => spi.read(handle, m.slice_mut(data, len)?)?,
+                    OP_WRITE => spi.write(handle, m.slice(data, len)?)?,
+                    _ => return Err(Error::InvalidArg),
+                }
+            }
+
+            Ok(())
+        })
+    })?;
+
+    l.func_wrap("uart", "init", |mut c: Caller<'_, Host<E>>, port: i32, baud: i32, tx: i32, rx: i32, handle: i32| {
+        call(&mut c, |m, e| {
+            let h = e.uart().ok_or(Error::NoDevice)?.init(port as u32, baud as u32, tx, rx)?;
+            m.write_i32(handle, h)
+        })
+    })?;
+
+    l.func_wrap("uart", "deinit", |mut c: Caller<'_, Host<E>>, handle: i32| {
+        call(&mut c, |_m, e| e.uart().ok_or(Error::NoDevice)?.deinit(handle))
+    })?;
+
+    l.func_wrap("uart", "write", |mut c: Caller<'_, Host<E>>, handle: i32, flags: i32, data: i32| {
+        call(&mut c, |m, e| {
+            let (data, len) = buff(m, data)?;
+            let d = m.slice(data, len)?;
+            e.uart().ok_or(Error::NoDevice)?.write(handle, flags as u32, d)
+        })
+    })?;
+
+    l.func_wrap("uart", "read", |mut c: Caller<'_, Host<E>>, handle: i32, flags: i32, b: i32| {
+        call(&mut c, |m, e| {
+            let (b, len) = buff(m, b)?;
+            let b = m.slice_mut(b, len)?;
+            e.uart().ok_or(Error::NoDevice)?.read(handle, flags as u32, b)
+        })
+    })?;
+
+    for f in &["spi", "i2c", "gpio_in", "gpio_out"] {
+        l.func_wrap("device", f, |mut c: Caller<'_, Host<E>>, _handles: i32| {
+            call(&mut c, |_m, _e| Err(Error::Unsupported))
+        })?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+//! Storage bindings
+//!
+//! | Function | Parameters |
+//! |---|---|
+//! | `storage::get` | `key, key_len, *buff, buff_len, *len` (-1 where unset) |
+//! | `storage::set` | `key, key_len, value, value_len` |
+//! | `storage::remove` | `key, key_len, *existed` |
+//!
+//! Keys are UTF-8, with invalid keys failing with [`Error::InvalidArg`].
+//! Values longer than the buffer are truncated, with the full value length
+//! written to `len`.
+
+use std::string::String;
+
+use wasmtime::{Caller, Linker};
+
+use wasm_embedded_spec::Error;
+
+use crate::{EngineExt, ext::Storage};
+use super::{Host, call};
+
+pub(crate) fn add_to_linker<E: EngineExt + 'static>(l: &mut Linker<Host<E>>) -> anyhow::Result<()> {
+    l.func_wrap("storage", "get", |mut c: Caller<'_, Host<E>>, key: i32, key_len: i32, buff: i32, buff_len: i32, len: i32| {
+        call(&mut c, |m, e| {
+            // Copy the key, which may overlap the read buffer
+            let k = String::from(m.str(key, key_len)?);
+            m.slice(len, 4)?;
+
+            let b = m.slice_mut(buff, buff_len)?;
+            let n = e.storage().ok_or(Error::NoDevice)?.get(&k, b)?;
+
+            m.write_i32(len, n.map_or(-1, |n| n as i32))
+        })
+    })?;
+
+    l.func_wrap("storage", "set", |mut c: Caller<'_, Host<E>>, key: i32, key_len: i32, value: i32, value_len: i32| {
+        call(&mut c, |m, e| {
+            let k = m.str(key, key_len)?;
+            let v = m.slice(value, value_len)?;
+            e.storage().ok_or(Error::NoDevice)?.set(k, v)
+        })
+    })?;
+
+    l.func_wrap("storage", "remove", |mut c: Caller<'_, Host<E>>, key: i32, key_len: i32, existed: i32| {
+        call(&mut c, |m, e| {
+            let k = String::from(m.str(key, key_len)?);
+            m.slice(existed, 4)?;
+
+            let r = e.storage().ok_or(Error::NoDevice)?.remove(&k)?;
+            m.write_i32(existed, r as i32)
+        })
+    })?;
+
+    Ok(())
+}
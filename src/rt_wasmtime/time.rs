@@ -0,0 +1,123 @@
+//! Time bindings
+//!
+//! | Function | Parameters |
+//! |---|---|
+//! | `time::delay_us` | `us` |
+//! | `time::delay_ms` | `ms` |
+//! | `time::now_us` | `*us` (`u64`) |
+//! | `time::timer_start` | `period_us, *handle` |
+//! | `time::timer_stop` | `handle` |
+//! | `time::timer_wait` | `handle, *expirations` |
+//! | `time::timer_poll` | `*handle` (-1 where none expired) |
+//! | `time::dispatch` | `timeout_us, *dispatched` (negative timeouts block indefinitely) |
+//!
+//! `time::dispatch` delivers timer callbacks, blocking until a timer
+//! expires then calling the guest [`TIMER_HANDLER`] export with the handle
+//! of each expired timer, failing with [`Error::Unsupported`] where the
+//! guest does not export a handler. Traps in the handler abort the guest.
+
+use std::vec::Vec;
+
+use wasmtime::{Caller, Linker};
+
+use wasm_embedded_spec::Error;
+
+use crate::{EngineExt, ext::Time};
+use super::{Host, OK, call, status};
+
+/// Guest export receiving timer callbacks, `fn(handle: i32)`
+pub const TIMER_HANDLER: &str = "timer_handler";
+
+/// Execute a call against the time driver
+fn time<E: EngineExt, T>(c: &mut Caller<'_, Host<E>>, f: impl FnOnce(&mut E::Time) -> Result<T, Error>) -> Result<T, Error> {
+    f(c.data_mut().engine.time().ok_or(Error::NoDevice)?)
+}
+
+pub(crate) fn add_to_linker<E: EngineExt + 'static>(l: &mut Linker<Host<E>>) -> anyhow::Result<()> {
+    l.func_wrap("time", "delay_us", |mut c: Caller<'_, Host<E>>, us: i32| {
+        call(&mut c, |_m, e| e.time().ok_or(Error::NoDevice)?.delay_us(us as u32))
+    })?;
+
+    l.func_wrap("time", "delay_ms", |mut c: Caller<'_, Host<E>>, ms: i32| {
+        call(&mut c, |_m, e| e.time().ok_or(Error::NoDevice)?.delay_ms(ms as u32))
+    })?;
+
+    l.func_wrap("time", "now_us", |mut c: Caller<'_, Host<E>>, us: i32| {
+        call(&mut c, |m, e| {
+            let now = e.time().ok_or(Error::NoDevice)?.now_us()?;
+            m.write(us, &now.to_le_bytes())
+        })
+    })?;
+
+    l.func_wrap("time", "timer_start", |mut c: Caller<'_, Host<E>>, period_us: i32, handle: i32| {
+        call(&mut c, |m, e| {
+            let h = e.time().ok_or(Error::NoDevice)?.timer_start(period_us as u32)?;
+            m.write_i32(handle, h)
+        })
+    })?;
+
+    l.func_wrap("time", "timer_stop", |mut c: Caller<'_, Host<E>>, handle: i32| {
+        call(&mut c, |_m, e| e.time().ok_or(Error::NoDevice)?.timer_stop(handle))
+    })?;
+
+    l.func_wrap("time", "timer_wait", |mut c: Caller<'_, Host<E>>, handle: i32, expirations: i32| {
+        call(&mut c, |m, e| {
+            let n = e.time().ok_or(Error::NoDevice)?.timer_wait(handle)?;
+            m.write_i32(expirations, n as i32)
+        })
+    })?;
+
+    l.func_wrap("time", "timer_poll", |mut c: Caller<'_, Host<E>>, handle: i32| {
+        call(&mut c, |m, e| {
+            let h = e.time().ok_or(Error::NoDevice)?.timer_poll()?;
+            m.write_i32(handle, h.unwrap_or(-1))
+        })
+    })?;
+
+    l.func_wrap("time", "dispatch", |mut c: Caller<'_, Host<E>>, timeout_us: i32, dispatched: i32| -> anyhow::Result<i32> {
+        let handler = match c.get_export(TIMER_HANDLER).and_then(|e| e.into_func()) {
+            Some(f) => f.typed::<i32, ()>(&c)?,
+            None => return Ok(status(Err(Error::Unsupported))),
+        };
+
+        // Check the output pointer prior to consuming expirations
+        let s = call(&mut c, |m, _e| m.slice(dispatched, 4).map(|_| ()));
+        if s != OK {
+            return Ok(s);
+        }
+
+        let timeout = match timeout_us {
+            t if t < 0 => None,
+            t => Some(t as u32),
+        };
+
+        let mut next = match time(&mut c, |t| t.timer_wait_any(timeout)) {
+            Ok(h) => h,
+            Err(e) => return Ok(status(Err(e))),
+        };
+
+        // Deliver each expired timer, stopping once a timer repeats so
+        // handlers slower than the timer period do not dispatch forever
+        let mut handles = Vec::new();
+        while let Some(h) = next {
+            handler.call(&mut c, h)?;
+
+            if handles.contains(&h) {
+                break;
+            }
+            handles.push(h);
+
+            next = match time(&mut c, |t| t.timer_poll()) {
+                Ok(h) => h,
+                Err(e) => return Ok(status(Err(e))),
+            };
+        }
+
+        // Count deliveries, including a repeated final timer
+        let n = handles.len() + next.map_or(0, |_| 1);
+
+        Ok(call(&mut c, |m, _e| m.write_i32(dispatched, n as i32)))
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,20 @@
+//! Watchdog bindings
+//!
+//! | Function | Parameters |
+//! |---|---|
+//! | `watchdog::kick` | |
+
+use wasmtime::{Caller, Linker};
+
+use wasm_embedded_spec::Error;
+
+use crate::{EngineExt, ext::Watchdog};
+use super::{Host, call};
+
+pub(crate) fn add_to_linker<E: EngineExt + 'static>(l: &mut Linker<Host<E>>) -> anyhow::Result<()> {
+    l.func_wrap("watchdog", "kick", |mut c: Caller<'_, Host<E>>| {
+        call(&mut c, |_m, e| e.watchdog().ok_or(Error::NoDevice)?.kick())
+    })?;
+
+    Ok(())
+}
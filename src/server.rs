@@ -0,0 +1,468 @@
+//! WASM server, executing and hot reloading guest modules
+//!
+//! Instances are stopped via a [`StopToken`] shared with the engine and
+//! runtime. Once stopped, engines release all peripherals and refuse further
+//! driver access, and the wasmtime runtime interrupts the guest (via epoch
+//! interruption). The server joins the instance thread prior to starting a
+//! replacement, so old and new instances never hold the same hardware.
+//!
+//! The wasm3 runtime cannot be interrupted, so guests executing there stop
+//! only on their next driver access.
+//!
+//! Remote clients must present a shared token (see [`ServeConfig`]) within
+//! the connection timeout, and a bounded number of connections are handled
+//! concurrently with read and write timeouts, so slow or stalled clients
+//! cannot block others.
+
+use std::{boxed::Box, format, path::Path, string::String, vec::Vec};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, atomic::{AtomicBool, AtomicUsize, Ordering}};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+use log::{debug, info, warn, error};
+
+use crate::{EngineExt, opts, module::Guest};
+
+/// Interval between checks for stopped instances and modified binaries
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Interval between warnings while waiting for an instance to stop
+const STOP_WARN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Default maximum binary size accepted by the serve protocol
+pub const MAX_BIN_SIZE: usize = 16 * 1024 * 1024;
+
+/// Maximum token size accepted by the serve protocol
+const MAX_TOKEN_SIZE: usize = 256;
+
+/// Default read and write timeout for serve connections
+pub const SERVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default maximum concurrent serve connections
+pub const MAX_CONNECTIONS: usize = 4;
+
+/// Remote serve protocol configuration
+#[derive(Clone, PartialEq, Debug)]
+pub struct ServeConfig {
+    /// Shared token clients must present, required to be non-empty
+    pub token: String,
+    /// Maximum accepted binary size in bytes
+    pub max_bin_size: usize,
+    /// Connection read and write timeout, also bounding the time taken
+    /// to present the token
+    pub timeout: Duration,
+    /// Maximum concurrent connections, further connections being rejected
+    pub max_connections: usize,
+}
+
+impl ServeConfig {
+    /// Create a serve configuration with the provided token and default limits
+    pub fn new(token: impl Into<String>) -> Self {
+        Self{ token: token.into(), max_bin_size: MAX_BIN_SIZE, timeout: SERVE_TIMEOUT, max_connections: MAX_CONNECTIONS }
+    }
+}
+
+/// Token used to stop an executing instance, shared with its engine and runtime
+#[derive(Clone, Default)]
+pub struct StopToken(Arc<StopState>);
+
+/// Shared stop state
+#[derive(Default)]
+struct StopState {
+    stopped: AtomicBool,
+    hooks: Mutex<Vec<Box<dyn Fn() + Send>>>,
+}
+
+/// Error reported by runtimes for instances interrupted via their [`StopToken`]
+#[derive(Clone, PartialEq, Debug, thiserror::Error)]
+#[error("instance stopped")]
+pub struct Stopped;
+
+impl StopToken {
+    /// Create a new stop token
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request the instance stop, calling any registered interrupt hooks
+    pub fn stop(&self) {
+        if self.0.stopped.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        for h in self.0.hooks.lock().unwrap().iter() {
+            h();
+        }
+    }
+
+    /// Check whether a stop has been requested
+    pub fn is_stopped(&self) -> bool {
+        self.0.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Register a hook called when a stop is requested (or immediately
+    /// where already stopped), used by runtimes to interrupt guests
+    pub fn on_stop(&self, f: impl Fn() + Send + 'static) {
+        let mut hooks = self.0.hooks.lock().unwrap();
+
+        match self.is_stopped() {
+            true => f(),
+            false => hooks.push(Box::new(f)),
+        }
+    }
+}
+
+impl core::fmt::Debug for StopToken {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("StopToken").field(&self.is_stopped()).finish()
+    }
+}
+
+/// Executing guest instance
+struct Instance {
+    stop: StopToken,
+    thread: JoinHandle<anyhow::Result<()>>,
+}
+
+/// WASM server
+pub struct Server<E: EngineExt> {
+    config: opts::Config,
+    guest: Guest,
+    engine: Box<dyn FnMut(StopToken) -> anyhow::Result<E> + Send>,
+    instance: Option<Instance>,
+}
+
+impl <E: EngineExt + Send + 'static> Server<E> {
+    /// Create new server instance, using the provided function to create
+    /// an engine (bound to the provided [`StopToken`]) for each instance
+    pub fn new(config: opts::Config, engine: impl FnMut(StopToken) -> anyhow::Result<E> + Send + 'static) -> Self {
+        Self {
+            config,
+            guest: Guest::default(),
+            engine: Box::new(engine),
+            instance: None,
+        }
+    }
+
+    /// Set guest invocation options for executed binaries
+    pub fn with_guest(mut self, guest: Guest) -> Self {
+        self.guest = guest;
+        self
+    }
+
+    /// Load and execute a provided wasm binary, replacing any executing instance
+    pub fn exec(&mut self, bin: &[u8]) -> anyhow::Result<()> {
+        // Prepare the new module prior to stopping the current instance,
+        // so invalid binaries do not interrupt execution
+        let bin = self.guest.apply(bin)?;
+        crate::validate(&self.config.runtime, &bin, &self.config.limits)?;
+
+        self.stop()?;
+
+        let stop = StopToken::new();
+        let engine = (self.engine)(stop.clone())?;
+
+        let runtime = self.config.runtime.clone();
+        let limits = self.config.limits.clone();
+        let guest = self.guest.clone();
+        let token = stop.clone();
+
+        let thread = std::thread::spawn(move || {
+            let res = crate::run_limited(&runtime, engine, &bin, &guest, &limits, &token);
+
+            match &res {
+                Ok(_) => info!("Instance exited"),
+                Err(e) if e.is::<Stopped>() => info!("Instance stopped"),
+                Err(e) => warn!("Instance failed: {:?}", e),
+            }
+
+            res
+        });
+
+        info!("Started instance");
+
+        self.instance = Some(Instance{ stop, thread });
+
+        Ok(())
+    }
+
+    /// Stop any executing instance, waiting for this to exit and release
+    /// its peripherals
+    pub fn stop(&mut self) -> anyhow::Result<()> {
+        let i = match self.instance.take() {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+
+        debug!("Stopping instance");
+        i.stop.stop();
+
+        // Guests are interrupted under wasmtime, though under wasm3 are
+        // stopped only on their next driver access so this may wait
+        // indefinitely for guests not accessing peripherals
+        let start = Instant::now();
+        let mut warned = Duration::from_secs(0);
+        while !i.thread.is_finished() {
+            if start.elapsed() > warned + STOP_WARN_INTERVAL {
+                warned = start.elapsed();
+                warn!("Waiting for instance to stop ({:?})", warned);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        match i.thread.join() {
+            Ok(_) => debug!("Instance stopped"),
+            Err(_) => error!("Instance thread panicked"),
+        }
+
+        Ok(())
+    }
+
+    /// Check whether an instance is executing
+    pub fn running(&self) -> bool {
+        match &self.instance {
+            Some(i) => !i.thread.is_finished(),
+            None => false,
+        }
+    }
+
+    /// Execute the wasm binary at the provided path, reloading this
+    /// whenever the file is modified, until `shutdown` is stopped
+    pub fn watch(&mut self, path: impl AsRef<Path>, shutdown: &StopToken) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let mut modified = None;
+
+        info!("Watching binary: {}", path.display());
+
+        while !shutdown.is_stopped() {
+            let m = match std::fs::metadata(path).and_then(|m| m.modified()) {
+                Ok(m) => Some(m),
+                Err(e) => {
+                    debug!("Failed to read binary metadata: {:?}", e);
+                    None
+                },
+            };
+
+            if m.is_some() && m != modified {
+                modified = m;
+                self.reload(path, modified);
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        self.stop()
+    }
+
+    /// Reload a binary following modification, retaining the current
+    /// instance if this fails to load
+    fn reload(&mut self, path: &Path, modified: Option<SystemTime>) {
+        info!("Loading binary: {} (modified: {:?})", path.display(), modified);
+
+        let res = std::fs::read(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|b| self.exec(&b));
+
+        if let Err(e) = res {
+            error!("Failed to load binary: {:?}", e);
+        }
+    }
+
+    /// Serve remote operations on the provided listener until `shutdown`
+    /// is stopped
+    ///
+    /// Clients send the token as a little-endian `u32` length followed by
+    /// the token, then the binary as a little-endian `u32` length followed
+    /// by the module, which replaces any executing instance. The server
+    /// replies with a single line, `ok` or `error: <reason>`, and closes
+    /// the connection, replying as soon as the token or a length is rejected.
+    /// Connections beyond [`ServeConfig::max_connections`] are rejected
+    /// on accept.
+    pub fn serve(&mut self, listener: TcpListener, config: &ServeConfig, shutdown: &StopToken) -> anyhow::Result<()> {
+        if config.token.is_empty() {
+            return Err(anyhow::anyhow!("Serving requires a non-empty token"));
+        }
+
+        // Wake the listener on shutdown so this is observed
+        let addr = listener.local_addr()?;
+        let wake = match addr.ip().is_unspecified() {
+            true => SocketAddr::from((Ipv4Addr::LOCALHOST, addr.port())),
+            false => addr,
+        };
+        shutdown.on_stop(move || {
+            let _ = TcpStream::connect(wake);
+        });
+
+        info!("Listening on {}", addr);
+
+        let server = Mutex::new(self);
+        let active = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for s in listener.incoming() {
+                if shutdown.is_stopped() {
+                    break;
+                }
+
+                let s = match s {
+                    Ok(s) => s,
+                    Err(e) => {
+                        warn!("Failed to accept connection: {:?}", e);
+                        continue;
+                    },
+                };
+
+                // Connections are only counted here, so the limit cannot
+                // be exceeded by concurrent accepts
+                if active.load(Ordering::SeqCst) >= config.max_connections {
+                    warn!("Rejecting connection from {:?}, limit reached", s.peer_addr());
+                    reject(s, config);
+                    continue;
+                }
+                active.fetch_add(1, Ordering::SeqCst);
+
+                let (server, slot) = (&server, Slot(&active));
+                scope.spawn(move || {
+                    let _slot = slot;
+
+                    // Panics are contained so these do not end serving
+                    if std::panic::catch_unwind(AssertUnwindSafe(|| handle(server, s, config))).is_err() {
+                        error!("Connection handler panicked");
+                    }
+                });
+            }
+        });
+
+        server.into_inner().unwrap_or_else(PoisonError::into_inner).stop()
+    }
+}
+
+/// Active connection slot, released on drop
+struct Slot<'a>(&'a AtomicUsize);
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Lock the server, recovering this where a connection handler panicked
+/// while holding the lock
+fn lock<'a, 'b, E: EngineExt>(server: &'a Mutex<&'b mut Server<E>>) -> MutexGuard<'a, &'b mut Server<E>> {
+    server.lock().unwrap_or_else(|e| {
+        warn!("Recovering server following a panicked connection");
+        e.into_inner()
+    })
+}
+
+/// Reject a connection exceeding the connection limit
+fn reject(mut s: TcpStream, config: &ServeConfig) {
+    let res = s.set_write_timeout(Some(config.timeout))
+        .and_then(|_| s.write_all(b"error: Too many connections\n"));
+
+    if let Err(e) = res {
+        warn!("Failed to write reply: {:?}", e);
+    }
+}
+
+/// Handle a serve connection, receiving and executing a binary
+fn handle<E: EngineExt + Send + 'static>(server: &Mutex<&mut Server<E>>, mut s: TcpStream, config: &ServeConfig) {
+    debug!("Connection from {:?}", s.peer_addr());
+
+    // Binaries are received prior to locking the server, so slow clients
+    // do not block others
+    let res = s.set_read_timeout(Some(config.timeout))
+        .and_then(|_| s.set_write_timeout(Some(config.timeout)))
+        .map_err(anyhow::Error::from)
+        .and_then(|_| receive(&mut s, config))
+        .and_then(|b| lock(server).exec(&b));
+
+    let reply = match &res {
+        Ok(_) => String::from("ok\n"),
+        Err(e) => {
+            error!("Failed to load binary: {:?}", e);
+            format!("error: {}\n", e)
+        },
+    };
+
+    if let Err(e) = s.write_all(reply.as_bytes()) {
+        warn!("Failed to write reply: {:?}", e);
+    }
+}
+
+impl <E: EngineExt> Drop for Server<E> {
+    fn drop(&mut self) {
+        // Stop and join any executing instance, releasing its peripherals
+        if let Some(i) = self.instance.take() {
+            i.stop.stop();
+            if i.thread.join().is_err() {
+                error!("Instance thread panicked");
+            }
+        }
+    }
+}
+
+/// Read a length-prefixed field using the serve protocol, rejecting
+/// fields exceeding the provided limit
+fn read_field(s: &mut impl Read, name: &str, limit: usize) -> anyhow::Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    s.read_exact(&mut len)?;
+
+    let len = u32::from_le_bytes(len) as usize;
+    if len > limit {
+        return Err(anyhow::anyhow!("{} too large ({} bytes, limit {})", name, len, limit));
+    }
+
+    // Read incrementally rather than allocating the claimed length
+    let mut buff = Vec::new();
+    Read::by_ref(s).take(len as u64).read_to_end(&mut buff)?;
+    if buff.len() != len {
+        return Err(anyhow::anyhow!("{} truncated ({} of {} bytes)", name, buff.len(), len));
+    }
+
+    Ok(buff)
+}
+
+/// Receive a binary using the serve protocol, checking the client token
+fn receive(s: &mut TcpStream, config: &ServeConfig) -> anyhow::Result<Vec<u8>> {
+    // Bound the time taken to present the token, so clients trickling data
+    // cannot hold connections without authenticating
+    let token = read_field(&mut Deadline{ s, at: Instant::now() + config.timeout }, "Token", MAX_TOKEN_SIZE)?;
+    if !token_eq(&token, config.token.as_bytes()) {
+        return Err(anyhow::anyhow!("Invalid token"));
+    }
+
+    s.set_read_timeout(Some(config.timeout))?;
+
+    let bin = read_field(s, "Binary", config.max_bin_size)?;
+
+    debug!("Received binary ({} bytes)", bin.len());
+
+    Ok(bin)
+}
+
+/// Stream reader failing once a deadline has passed
+struct Deadline<'a> {
+    s: &'a mut TcpStream,
+    at: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buff: &mut [u8]) -> io::Result<usize> {
+        let remaining = match self.at.checked_duration_since(Instant::now()) {
+            Some(d) if !d.is_zero() => d,
+            _ => return Err(io::Error::new(io::ErrorKind::TimedOut, "Deadline exceeded")),
+        };
+
+        self.s.set_read_timeout(Some(remaining))?;
+        self.s.read(buff)
+    }
+}
+
+/// Compare tokens in constant time (for tokens of equal length)
+fn token_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |d, (x, y)| d | (x ^ y)) == 0
+}
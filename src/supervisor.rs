@@ -0,0 +1,512 @@
+//! Supervisor for concurrently executing multiple guest modules
+//!
+//! Modules are described by a TOML manifest, and each executes on a separate
+//! blocking task with its own engine context. Linux modules are restricted to
+//! the peripherals assigned in the manifest, which must not overlap between
+//! modules.
+
+use std::{collections::BTreeMap, format, path::{Path, PathBuf}, string::String, vec, vec::Vec};
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn, error};
+use serde::{Serialize, Deserialize};
+use strum::{Display, EnumString, EnumVariantNames};
+
+use crate::opts::{Engine, Runtime, Limits, Peripherals, StorageConfig, secs};
+use crate::module::Guest;
+use crate::server::StopToken;
+use crate::logging::{GuestLog, LogConfig, LOG_RATE};
+
+/// Supervisor manifest, listing modules to be executed
+#[derive(Clone, PartialEq, Default, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct Manifest {
+    /// Modules to execute
+    #[serde(rename="module", default)]
+    pub modules: Vec<ModuleConfig>,
+}
+
+/// Supervised module configuration
+#[derive(Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct ModuleConfig {
+    /// Module name, used in logs
+    pub name: String,
+    /// WASM binary (relative to the manifest)
+    pub bin: PathBuf,
+    /// WASM runtime
+    #[serde(default)]
+    pub runtime: Runtime,
+    /// Backing engine
+    #[serde(default)]
+    pub engine: Engine,
+    /// Mock configuration file (relative to the manifest), required for mock engines
+    #[serde(default)]
+    pub config: Option<PathBuf>,
+    /// Export to invoke in place of `_start`
+    #[serde(default)]
+    pub invoke: Option<String>,
+    /// Arguments passed to the guest
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables passed to the guest
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Directories preopened for the guest via WASI (relative to the manifest)
+    #[serde(default)]
+    pub dirs: Vec<PathBuf>,
+    /// Guest instruction budget (fuel)
+    #[serde(default)]
+    pub fuel: Option<u64>,
+    /// Guest wall-clock timeout in seconds
+    #[serde(default)]
+    pub timeout: Option<f64>,
+    /// Maximum guest linear memory in 64KiB pages
+    #[serde(default)]
+    pub max_memory_pages: Option<u32>,
+    /// Maximum guest table size in elements
+    #[serde(default)]
+    pub max_table_elements: Option<u32>,
+    /// Maximum concurrently executing guest instances, across all modules
+    #[serde(default)]
+    pub max_instances: Option<usize>,
+    /// Peripherals assigned to the module
+    #[serde(default)]
+    pub peripherals: Peripherals,
+    /// Restart policy
+    #[serde(default)]
+    pub restart: Restart,
+    /// Persistent storage, unavailable to the guest if unset
+    #[serde(default)]
+    pub storage: Option<StorageConfig>,
+    /// Maximum guest log level
+    #[serde(default)]
+    pub log_level: Option<log::LevelFilter>,
+    /// Guest log rate limit in messages per second (unlimited if zero)
+    #[serde(default)]
+    pub log_rate: Option<u32>,
+    /// Peripheral operation trace file (relative to the manifest)
+    #[serde(default)]
+    pub trace: Option<PathBuf>,
+}
+
+/// Module restart policy
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Display, EnumString, EnumVariantNames)]
+#[serde(rename_all="kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Never restart the module
+    Never,
+    /// Restart the module when it fails (traps, errors or exits with a non-zero code)
+    OnFailure,
+    /// Always restart the module when it exits
+    Always,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::Never
+    }
+}
+
+/// Module restart configuration
+///
+/// Restarts are delayed by an exponential backoff, doubling from `backoff`
+/// up to `max_backoff` seconds, which resets once a module has executed
+/// for longer than `max_backoff`.
+#[derive(Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all="snake_case", default)]
+pub struct Restart {
+    /// Restart policy
+    pub policy: RestartPolicy,
+    /// Maximum number of restarts, unlimited if unset
+    pub max_restarts: Option<u32>,
+    /// Initial restart delay in seconds
+    pub backoff: f64,
+    /// Maximum restart delay in seconds
+    pub max_backoff: f64,
+}
+
+impl Default for Restart {
+    fn default() -> Self {
+        Self{
+            policy: RestartPolicy::default(),
+            max_restarts: None,
+            backoff: 1.0,
+            max_backoff: 60.0,
+        }
+    }
+}
+
+impl Restart {
+    /// Check whether a module should be restarted following execution
+    pub fn restart(&self, res: &anyhow::Result<()>) -> bool {
+        match self.policy {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => res.is_err(),
+            RestartPolicy::Always => true,
+        }
+    }
+
+    /// Validate restart delays
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let (backoff, max_backoff) = self.delays()?;
+        if backoff > max_backoff {
+            return Err(anyhow::anyhow!("restart backoff ({:?}) exceeds max_backoff ({:?})", backoff, max_backoff));
+        }
+        Ok(())
+    }
+
+    /// Fetch the initial and maximum restart delays
+    fn delays(&self) -> anyhow::Result<(Duration, Duration)> {
+        let backoff = secs(self.backoff).map_err(|e| anyhow::anyhow!("restart backoff: {}", e))?;
+        let max_backoff = secs(self.max_backoff).map_err(|e| anyhow::anyhow!("restart max_backoff: {}", e))?;
+        Ok((backoff, max_backoff))
+    }
+}
+
+/// Action following execution of a supervised module
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Next {
+    /// Module is complete per the restart policy
+    Exit,
+    /// Module should be restarted but has reached the restart limit
+    Limit,
+    /// Restart the module following the provided delay
+    Restart(Duration),
+}
+
+/// Restart state for a supervised module, applying the module restart
+/// policy, restart limit and backoff
+#[derive(Clone, Debug)]
+pub struct Restarts {
+    restart: Restart,
+    backoff: Duration,
+    max_backoff: Duration,
+    delay: Duration,
+    count: u32,
+}
+
+impl Restarts {
+    /// Create restart state for the provided configuration
+    pub fn new(restart: &Restart) -> anyhow::Result<Self> {
+        restart.validate()?;
+        let (backoff, max_backoff) = restart.delays()?;
+
+        Ok(Self{ restart: restart.clone(), backoff, max_backoff, delay: backoff, count: 0 })
+    }
+
+    /// Fetch the number of restarts so far
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Determine the action following an execution of the module,
+    /// which completed with `res` after executing for `elapsed`
+    pub fn next(&mut self, res: &anyhow::Result<()>, elapsed: Duration) -> Next {
+        if !self.restart.restart(res) {
+            return Next::Exit;
+        }
+
+        if let Some(max) = self.restart.max_restarts {
+            if self.count >= max {
+                return Next::Limit;
+            }
+        }
+
+        // Reset backoff for modules which ran successfully for a while
+        if elapsed > self.max_backoff {
+            self.delay = self.backoff;
+        }
+
+        let delay = self.delay;
+        self.delay = (self.delay * 2).min(self.max_backoff);
+        self.count += 1;
+
+        Next::Restart(delay)
+    }
+}
+
+impl Manifest {
+    /// Load a manifest from a TOML file, resolving module paths relative to this
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        debug!("Loading manifest: {}", path.display());
+
+        let d = std::fs::read_to_string(path)?;
+        let mut m: Manifest = toml::from_str(&d)?;
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for c in &mut m.modules {
+            c.bin = dir.join(&c.bin);
+            c.config = c.config.as_ref().map(|p| dir.join(p));
+            c.trace = c.trace.as_ref().map(|p| dir.join(p));
+            c.dirs = c.dirs.iter().map(|p| dir.join(p)).collect();
+
+            if let Some(StorageConfig::File{ path, .. }) = &mut c.storage {
+                *path = dir.join(&path);
+            }
+        }
+
+        Ok(m)
+    }
+
+    /// Validate the manifest, checking module names are unique and
+    /// peripherals and storage are not shared between modules
+    ///
+    /// Where multiple modules use the Linux engine, each must restrict
+    /// every peripheral kind, as unrestricted modules may access any
+    /// peripheral.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.modules.is_empty() {
+            return Err(anyhow::anyhow!("Manifest contains no modules"));
+        }
+
+        for m in &self.modules {
+            m.validate()?;
+        }
+
+        for (i, a) in self.modules.iter().enumerate() {
+            for b in &self.modules[i+1..] {
+                if a.name == b.name {
+                    return Err(anyhow::anyhow!("Duplicate module name: {}", a.name));
+                }
+
+                if let Some(s) = shared_storage(a.storage.as_ref(), b.storage.as_ref()) {
+                    return Err(anyhow::anyhow!("Modules {} and {} both use {}", a.name, b.name, s));
+                }
+
+                // Peripherals are only shared between hardware-backed modules
+                if a.engine != Engine::Linux || b.engine != Engine::Linux {
+                    continue;
+                }
+
+                let (pa, pb) = (&a.peripherals, &b.peripherals);
+                let shared = overlap("GPIO pin", &pa.gpio, &pb.gpio)
+                    .or_else(|| overlap("I2C device", &pa.i2c, &pb.i2c))
+                    .or_else(|| overlap("SPI device", &pa.spi, &pb.spi))
+                    .or_else(|| overlap("UART device", &pa.uart, &pb.uart))
+                    .or_else(|| overlap("PWM chip", &pa.pwm, &pb.pwm))
+                    .or_else(|| overlap("ADC device", &pa.adc, &pb.adc))
+                    .or_else(|| overlap("CAN port", &pa.can, &pb.can))
+                    .or_else(|| overlap("1-Wire bus", &pa.onewire, &pb.onewire));
+
+                if let Some(s) = shared {
+                    return Err(anyhow::anyhow!("Modules {} and {} both use {}", a.name, b.name, s));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Find the first peripheral available to both modules
+///
+/// Unrestricted modules may access any peripheral of a kind, so these
+/// overlap with every other module using that kind. Modules not using a
+/// kind should restrict this to an empty list.
+fn overlap<T: PartialEq + core::fmt::Display>(kind: &str, a: &Option<Vec<T>>, b: &Option<Vec<T>>) -> Option<String> {
+    match (a, b) {
+        (Some(a), Some(b)) => a.iter().find(|v| b.contains(v)).map(|v| format!("{} {}", kind, v)),
+        (Some(r), None) | (None, Some(r)) if r.is_empty() => None,
+        _ => Some(format!("unrestricted {}s", kind)),
+    }
+}
+
+/// Check whether modules share a storage backend, which must be private to each
+fn shared_storage(a: Option<&StorageConfig>, b: Option<&StorageConfig>) -> Option<String> {
+    match (a?, b?) {
+        (StorageConfig::File{ path: pa, .. }, StorageConfig::File{ path: pb, .. })
+            if pa.starts_with(pb) || pb.starts_with(pa) => Some(format!("storage directory {}", pa.display())),
+        (StorageConfig::Eeprom{ bus: ba, addr: aa, .. }, StorageConfig::Eeprom{ bus: bb, addr: ab, .. })
+            if ba == bb && aa == ab => Some(format!("storage EEPROM {}:{:#04x}", ba, aa)),
+        _ => None,
+    }
+}
+
+impl ModuleConfig {
+    /// Validate module timeouts and restart configuration
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.limits()?;
+        self.restart.validate()
+            .map_err(|e| anyhow::anyhow!("Module {} {}", self.name, e))
+    }
+
+    /// Fetch execution limits for the module
+    pub fn limits(&self) -> anyhow::Result<Limits> {
+        let timeout = match self.timeout {
+            Some(t) => Some(secs(t).map_err(|e| anyhow::anyhow!("Module {} timeout: {}", self.name, e))?),
+            None => None,
+        };
+
+        Ok(Limits{
+            fuel: self.fuel,
+            timeout,
+            max_memory_pages: self.max_memory_pages,
+            max_table_elements: self.max_table_elements,
+            max_instances: self.max_instances,
+            ..Default::default()
+        })
+    }
+
+    /// Fetch guest invocation options for the module
+    pub fn guest(&self) -> Guest {
+        Guest{
+            invoke: self.invoke.clone(),
+            args: vec![self.bin.to_string_lossy().into()].into_iter().chain(self.args.iter().cloned()).collect(),
+            env: self.env.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            dirs: self.dirs.clone(),
+        }
+    }
+
+    /// Build the guest logger for the module, tagged with the module name
+    pub fn logger(&self) -> GuestLog {
+        GuestLog::new(&self.name, LogConfig{
+            level: self.log_level.unwrap_or(log::LevelFilter::Trace),
+            rate: self.log_rate.unwrap_or(LOG_RATE),
+        })
+    }
+
+    /// Execute a WASM binary against the provided engine, tracing
+    /// peripheral operations if enabled
+    ///
+    /// Trace files are replaced on each restart.
+    fn run<E: crate::EngineExt + Send + 'static>(&self, ctx: E, bin: &[u8], guest: &Guest, limits: &Limits, stop: &StopToken) -> anyhow::Result<()> {
+        match &self.trace {
+            Some(p) => crate::run_limited(&self.runtime, crate::trace::Traced::new(ctx, crate::trace::FileSink::create(p)?), bin, guest, limits, stop),
+            None => crate::run_limited(&self.runtime, ctx, bin, guest, limits, stop),
+        }
+    }
+
+    /// Load and execute the module to completion
+    pub fn exec(&self) -> anyhow::Result<()> {
+        let bin = std::fs::read(&self.bin)?;
+
+        let guest = self.guest();
+        let bin = guest.apply(&bin)?;
+        let limits = self.limits()?;
+
+        // Timeouts stop wasm3 guests via the engine, so bind this to the token
+        let stop = StopToken::new();
+
+        #[allow(unreachable_patterns)]
+        match &self.engine {
+            #[cfg(feature="hal-mock")]
+            Engine::Mock => {
+                let cfg = match &self.config {
+                    Some(c) => c.to_string_lossy(),
+                    None => return Err(anyhow::anyhow!("Module {} requires mock config file", self.name)),
+                };
+
+                let ctx = crate::mock::MockCtx::load(&cfg)?.with_logger(self.logger())
+                    .with_stop(stop.clone());
+                let handle = ctx.handle();
+
+                let res = self.run(ctx, &bin, &guest, &limits, &stop);
+
+                handle.verify()?;
+                res
+            },
+            #[cfg(feature="hal-linux")]
+            Engine::Linux => {
+                let mut ctx = crate::linux::LinuxCtx::with_peripherals(self.peripherals.clone())
+                    .with_logger(self.logger())
+                    .with_stop(stop.clone());
+                if let Some(s) = &self.storage {
+                    ctx = ctx.with_storage(crate::linux::StorageDriver::open(s)?);
+                }
+
+                self.run(ctx, &bin, &guest, &limits, &stop)
+            },
+            _ => {
+                Err(anyhow::anyhow!("Runtime was not built with {}:{} enabled", self.runtime, self.engine))
+            },
+        }
+    }
+}
+
+/// Supervisor, executing manifest modules concurrently
+pub struct Supervisor {
+    manifest: Manifest,
+}
+
+impl Supervisor {
+    /// Create a supervisor for the provided manifest
+    pub fn new(manifest: Manifest) -> anyhow::Result<Self> {
+        manifest.validate()?;
+
+        Ok(Self{ manifest })
+    }
+
+    /// Create a supervisor from a manifest file
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::new(Manifest::load(path)?)
+    }
+
+    /// Execute all modules, returning once these have completed
+    ///
+    /// Modules execute and restart independently, a failure in one module
+    /// does not affect the others.
+    pub async fn run(self) -> anyhow::Result<()> {
+        info!("Starting {} modules", self.manifest.modules.len());
+
+        let tasks: Vec<_> = self.manifest.modules.into_iter()
+            .map(|m| (m.name.clone(), tokio::task::spawn_blocking(move || supervise(&m))))
+            .collect();
+
+        let mut failed = Vec::new();
+        for (name, t) in tasks {
+            match t.await {
+                Ok(Ok(())) => info!("[{}] Module complete", name),
+                Ok(Err(e)) => {
+                    error!("[{}] Module failed: {:?}", name, e);
+                    failed.push(name);
+                },
+                Err(e) => {
+                    error!("[{}] Module task failed: {:?}", name, e);
+                    failed.push(name);
+                },
+            }
+        }
+
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow::anyhow!("Modules failed: {}", failed.join(", "))),
+        }
+    }
+}
+
+/// Execute a module, restarting this on exit per the module restart policy
+fn supervise(m: &ModuleConfig) -> anyhow::Result<()> {
+    let mut restarts = Restarts::new(&m.restart)?;
+
+    loop {
+        info!("[{}] Starting module: {}", m.name, m.bin.display());
+
+        // Engine contexts are dropped on completion, releasing peripherals
+        let start = Instant::now();
+        let res = m.exec();
+
+        let delay = match restarts.next(&res, start.elapsed()) {
+            Next::Exit => return res,
+            Next::Limit => {
+                let n = restarts.count();
+                error!("[{}] Restart limit reached ({} restarts)", m.name, n);
+                return res.map_err(|e| e.context(format!("restart limit reached ({} restarts)", n)));
+            },
+            Next::Restart(d) => d,
+        };
+
+        match &res {
+            Ok(_) => info!("[{}] Module exited, restarting in {:?} (restart {})", m.name, delay, restarts.count()),
+            Err(e) => warn!("[{}] Module failed, restarting in {:?} (restart {}): {:?}", m.name, delay, restarts.count(), e),
+        }
+
+        std::thread::sleep(delay);
+    }
+}
@@ -0,0 +1,525 @@
+//! Structured tracing of peripheral operations
+//!
+//! [`Traced`] wraps any engine, timing each GPIO, I2C, SPI and UART call
+//! and reporting this as a [`Span`] (handle, arguments, byte counts,
+//! duration and result) to a [`Sink`]. [`FileSink`] writes spans as JSON
+//! lines for offline analysis, for example:
+//!
+//! ```text
+//! {"seq":0,"start_us":12,"duration_us":85,"peripheral":"i2c","op":"write","handle":0,"args":{"addr":64},"bytes_out":2,"bytes_in":0}
+//! ```
+//!
+//! With the `tracing` feature each call is also entered as a `tracing`
+//! span (named `peripheral`), so operations nest within any application
+//! spans and may be exported by a `tracing` subscriber.
+
+use std::{boxed::Box, collections::BTreeMap, format, string::String};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use embedded_hal::digital::PinState;
+use log::{debug, warn};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use strum::Display;
+
+use wasm_embedded_spec::{Engine, Error, Gpio, I2c, Spi, Uart};
+
+use crate::ext::EngineExt;
+
+/// Traced peripheral
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Display)]
+#[serde(rename_all="snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Peripheral {
+    Gpio,
+    I2c,
+    Spi,
+    Uart,
+}
+
+/// Traced peripheral operation
+#[derive(Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub struct Span {
+    /// Operation sequence number
+    pub seq: u64,
+    /// Start time in microseconds since tracing began
+    pub start_us: u64,
+    /// Operation duration in microseconds
+    pub duration_us: u64,
+    pub peripheral: Peripheral,
+    /// Driver method
+    pub op: String,
+    /// Peripheral handle, as returned for `init` operations
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub handle: Option<i32>,
+    /// Operation arguments (excluding data)
+    #[serde(default, skip_serializing_if="BTreeMap::is_empty")]
+    pub args: BTreeMap<String, Value>,
+    /// Bytes written to the peripheral
+    #[serde(default)]
+    pub bytes_out: usize,
+    /// Bytes read from the peripheral
+    #[serde(default)]
+    pub bytes_in: usize,
+    /// Driver error, `None` where the operation succeeded
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Destination for traced operations
+pub trait Sink: Send {
+    /// Handle a completed operation
+    fn span(&mut self, span: &Span);
+}
+
+/// Shared in-memory sink, allowing spans to be inspected once the traced
+/// engine has been passed to a runtime
+impl Sink for Arc<Mutex<std::vec::Vec<Span>>> {
+    fn span(&mut self, span: &Span) {
+        self.lock().unwrap().push(span.clone());
+    }
+}
+
+/// File sink, writing one JSON span per line
+///
+/// Each span is flushed once written, so traces are complete up to the
+/// last operation even where the guest is killed or the host crashes.
+pub struct FileSink {
+    w: BufWriter<File>,
+    failed: bool,
+}
+
+impl FileSink {
+    /// Create (or truncate) a trace file
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        debug!("Writing peripheral trace: {}", path.display());
+
+        Ok(Self{ w: BufWriter::new(File::create(path)?), failed: false })
+    }
+}
+
+impl Sink for FileSink {
+    fn span(&mut self, span: &Span) {
+        if self.failed {
+            return;
+        }
+
+        // Tracing is diagnostic, so write failures are reported once rather than failing the guest
+        let res = serde_json::to_writer(&mut self.w, span)
+            .map_err(std::io::Error::from)
+            .and_then(|_| self.w.write_all(b"\n"))
+            .and_then(|_| self.w.flush());
+        if let Err(e) = res {
+            warn!("Failed to write trace, tracing disabled: {:?}", e);
+            self.failed = true;
+        }
+    }
+}
+
+/// Span tracer, shared between peripherals of a traced engine
+struct Tracer {
+    sink: Box<dyn Sink>,
+    start: Instant,
+    seq: u64,
+}
+
+/// Operation in progress
+struct Call {
+    span: Span,
+    start: Instant,
+    #[cfg(feature="tracing")]
+    entered: tracing::span::EnteredSpan,
+}
+
+impl Call {
+    fn arg(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.span.args.insert(name.into(), value.into());
+        self
+    }
+
+    fn bytes(mut self, bytes_out: usize, bytes_in: usize) -> Self {
+        self.span.bytes_out = bytes_out;
+        self.span.bytes_in = bytes_in;
+        self
+    }
+}
+
+impl Tracer {
+    /// Start tracing an operation
+    fn begin(&mut self, peripheral: Peripheral, op: &str, handle: Option<i32>) -> Call {
+        let start = Instant::now();
+
+        #[cfg(feature="tracing")]
+        let entered = tracing::debug_span!("peripheral",
+            peripheral = %peripheral,
+            op = op,
+            handle = tracing::field::Empty,
+            bytes_out = tracing::field::Empty,
+            bytes_in = tracing::field::Empty,
+            error = tracing::field::Empty,
+        ).entered();
+
+        #[cfg(feature="tracing")]
+        if let Some(h) = handle {
+            entered.record("handle", h);
+        }
+
+        Call{
+            span: Span{
+                seq: 0,
+                start_us: start.duration_since(self.start).as_micros() as u64,
+                duration_us: 0,
+                peripheral,
+                op: op.into(),
+                handle,
+                args: BTreeMap::new(),
+                bytes_out: 0,
+                bytes_in: 0,
+                error: None,
+            },
+            start,
+            #[cfg(feature="tracing")]
+            entered,
+        }
+    }
+
+    /// Complete an operation, reporting this to the sink
+    fn end<T>(&mut self, call: Call, res: &Result<T, Error>) {
+        let mut span = call.span;
+
+        span.duration_us = call.start.elapsed().as_micros() as u64;
+        span.error = res.as_ref().err().map(|e| format!("{:?}", e));
+        span.seq = self.seq;
+        self.seq += 1;
+
+        #[cfg(feature="tracing")]
+        {
+            // Handles are known only on completion for `init` operations
+            let s = call.entered;
+            if let Some(h) = span.handle {
+                s.record("handle", h);
+            }
+            s.record("bytes_out", span.bytes_out);
+            s.record("bytes_in", span.bytes_in);
+            if let Some(e) = &span.error {
+                s.record("error", e.as_str());
+            }
+        }
+
+        self.sink.span(&span);
+    }
+}
+
+/// Engine wrapper tracing peripheral operations
+///
+/// Extended peripherals are forwarded untraced.
+pub struct Traced<E> {
+    inner: E,
+    tracer: Tracer,
+}
+
+impl<E> Traced<E> {
+    /// Wrap an engine, reporting operations to the provided sink
+    pub fn new(inner: E, sink: impl Sink + 'static) -> Self {
+        Self{
+            inner,
+            tracer: Tracer{ sink: Box::new(sink), start: Instant::now(), seq: 0 },
+        }
+    }
+
+    /// Unwrap the traced engine
+    pub fn into_inner(self) -> E {
+        self.inner
+    }
+}
+
+impl<E: Engine> Engine for Traced<E> {
+    type Gpio = Self;
+
+    type I2c = Self;
+
+    type Spi = Self;
+
+    type Uart = Self;
+
+    // Peripheral availability is checked here so runtimes see the inner
+    // engine's drivers, operations then fetch the inner driver for each call
+
+    fn gpio(&mut self) -> Option<&mut Self::Gpio> {
+        self.inner.gpio()?;
+        Some(self)
+    }
+
+    fn i2c(&mut self) -> Option<&mut Self::I2c> {
+        self.inner.i2c()?;
+        Some(self)
+    }
+
+    fn spi(&mut self) -> Option<&mut Self::Spi> {
+        self.inner.spi()?;
+        Some(self)
+    }
+
+    fn uart(&mut self) -> Option<&mut Self::Uart> {
+        self.inner.uart()?;
+        Some(self)
+    }
+}
+
+impl<E: Engine> Gpio for Traced<E> {
+    fn init(&mut self, port: i32, pin: i32, output: bool) -> Result<i32, Error> {
+        let mut call = self.tracer.begin(Peripheral::Gpio, "init", None)
+            .arg("port", port).arg("pin", pin).arg("output", output);
+
+        let res = self.inner.gpio().ok_or(Error::NoDevice).and_then(|d| d.init(port, pin, output));
+        call.span.handle = res.as_ref().ok().copied();
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn deinit(&mut self, handle: i32) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::Gpio, "deinit", Some(handle));
+
+        let res = self.inner.gpio().ok_or(Error::NoDevice).and_then(|d| d.deinit(handle));
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn set(&mut self, handle: i32, state: PinState) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::Gpio, "set", Some(handle))
+            .arg("state", state == PinState::High);
+
+        let res = self.inner.gpio().ok_or(Error::NoDevice).and_then(|d| d.set(handle, state));
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn get(&mut self, handle: i32) -> Result<PinState, Error> {
+        let mut call = self.tracer.begin(Peripheral::Gpio, "get", Some(handle));
+
+        let res = self.inner.gpio().ok_or(Error::NoDevice).and_then(|d| d.get(handle));
+        if let Ok(s) = &res {
+            call = call.arg("state", *s == PinState::High);
+        }
+
+        self.tracer.end(call, &res);
+        res
+    }
+}
+
+impl<E: Engine> I2c for Traced<E> {
+    fn init(&mut self, port: u32, baud: u32, sda: i32, scl: i32) -> Result<i32, Error> {
+        let mut call = self.tracer.begin(Peripheral::I2c, "init", None)
+            .arg("port", port).arg("baud", baud).arg("sda", sda).arg("scl", scl);
+
+        let res = self.inner.i2c().ok_or(Error::NoDevice).and_then(|d| d.init(port, baud, sda, scl));
+        call.span.handle = res.as_ref().ok().copied();
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn deinit(&mut self, handle: i32) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::I2c, "deinit", Some(handle));
+
+        let res = self.inner.i2c().ok_or(Error::NoDevice).and_then(|d| d.deinit(handle));
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn write(&mut self, handle: i32, addr: u16, data: &[u8]) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::I2c, "write", Some(handle))
+            .arg("addr", addr).bytes(data.len(), 0);
+
+        let res = self.inner.i2c().ok_or(Error::NoDevice).and_then(|d| d.write(handle, addr, data));
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn read(&mut self, handle: i32, addr: u16, buff: &mut [u8]) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::I2c, "read", Some(handle))
+            .arg("addr", addr).bytes(0, buff.len());
+
+        let res = self.inner.i2c().ok_or(Error::NoDevice).and_then(|d| d.read(handle, addr, buff));
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn write_read(&mut self, handle: i32, addr: u16, data: &[u8], buff: &mut [u8]) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::I2c, "write_read", Some(handle))
+            .arg("addr", addr).bytes(data.len(), buff.len());
+
+        let res = self.inner.i2c().ok_or(Error::NoDevice).and_then(|d| d.write_read(handle, addr, data, buff));
+
+        self.tracer.end(call, &res);
+        res
+    }
+}
+
+impl<E: Engine> Spi for Traced<E> {
+    fn init(&mut self, port: u32, baud: u32, mosi: i32, miso: i32, sck: i32, cs: i32) -> Result<i32, Error> {
+        let mut call = self.tracer.begin(Peripheral::Spi, "init", None)
+            .arg("port", port).arg("baud", baud).arg("mosi", mosi).arg("miso", miso).arg("sck", sck).arg("cs", cs);
+
+        let res = self.inner.spi().ok_or(Error::NoDevice).and_then(|d| d.init(port, baud, mosi, miso, sck, cs));
+        call.span.handle = res.as_ref().ok().copied();
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn deinit(&mut self, handle: i32) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::Spi, "deinit", Some(handle));
+
+        let res = self.inner.spi().ok_or(Error::NoDevice).and_then(|d| d.deinit(handle));
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn read<'a>(&mut self, handle: i32, data: &mut [u8]) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::Spi, "read", Some(handle))
+            .bytes(0, data.len());
+
+        let res = self.inner.spi().ok_or(Error::NoDevice).and_then(|d| d.read(handle, data));
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn write<'a>(&mut self, handle: i32, data: &[u8]) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::Spi, "write", Some(handle))
+            .bytes(data.len(), 0);
+
+        let res = self.inner.spi().ok_or(Error::NoDevice).and_then(|d| d.write(handle, data));
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn transfer_inplace<'a>(&mut self, handle: i32, data: &mut [u8]) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::Spi, "transfer_inplace", Some(handle))
+            .bytes(data.len(), data.len());
+
+        let res = self.inner.spi().ok_or(Error::NoDevice).and_then(|d| d.transfer_inplace(handle, data));
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn transfer<'a>(&mut self, handle: i32, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::Spi, "transfer", Some(handle))
+            .bytes(write.len(), read.len());
+
+        let res = self.inner.spi().ok_or(Error::NoDevice).and_then(|d| d.transfer(handle, read, write));
+
+        self.tracer.end(call, &res);
+        res
+    }
+}
+
+impl<E: Engine> Uart for Traced<E> {
+    fn init(&mut self, port: u32, baud: u32, tx: i32, rx: i32) -> Result<i32, Error> {
+        let mut call = self.tracer.begin(Peripheral::Uart, "init", None)
+            .arg("port", port).arg("baud", baud).arg("tx", tx).arg("rx", rx);
+
+        let res = self.inner.uart().ok_or(Error::NoDevice).and_then(|d| d.init(port, baud, tx, rx));
+        call.span.handle = res.as_ref().ok().copied();
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn deinit(&mut self, handle: i32) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::Uart, "deinit", Some(handle));
+
+        let res = self.inner.uart().ok_or(Error::NoDevice).and_then(|d| d.deinit(handle));
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn write(&mut self, handle: i32, flags: u32, data: &[u8]) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::Uart, "write", Some(handle))
+            .arg("flags", flags).bytes(data.len(), 0);
+
+        let res = self.inner.uart().ok_or(Error::NoDevice).and_then(|d| d.write(handle, flags, data));
+
+        self.tracer.end(call, &res);
+        res
+    }
+
+    fn read(&mut self, handle: i32, flags: u32, buff: &mut [u8]) -> Result<(), Error> {
+        let call = self.tracer.begin(Peripheral::Uart, "read", Some(handle))
+            .arg("flags", flags).bytes(0, buff.len());
+
+        let res = self.inner.uart().ok_or(Error::NoDevice).and_then(|d| d.read(handle, flags, buff));
+
+        self.tracer.end(call, &res);
+        res
+    }
+}
+
+impl<E: EngineExt> EngineExt for Traced<E> {
+    type Pwm = E::Pwm;
+
+    type Adc = E::Adc;
+
+    type Can = E::Can;
+
+    type OneWire = E::OneWire;
+
+    type Time = E::Time;
+
+    type Watchdog = E::Watchdog;
+
+    type Storage = E::Storage;
+
+    type Logger = E::Logger;
+
+    fn pwm(&mut self) -> Option<&mut Self::Pwm> {
+        self.inner.pwm()
+    }
+
+    fn adc(&mut self) -> Option<&mut Self::Adc> {
+        self.inner.adc()
+    }
+
+    fn can(&mut self) -> Option<&mut Self::Can> {
+        self.inner.can()
+    }
+
+    fn onewire(&mut self) -> Option<&mut Self::OneWire> {
+        self.inner.onewire()
+    }
+
+    fn time(&mut self) -> Option<&mut Self::Time> {
+        self.inner.time()
+    }
+
+    fn watchdog(&mut self) -> Option<&mut Self::Watchdog> {
+        self.inner.watchdog()
+    }
+
+    fn storage(&mut self) -> Option<&mut Self::Storage> {
+        self.inner.storage()
+    }
+
+    fn logger(&mut self) -> Option<&mut Self::Logger> {
+        self.inner.logger()
+    }
+}
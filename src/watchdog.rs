@@ -0,0 +1,212 @@
+//! Watchdog service, recovering from hung guests
+//!
+//! In [`WatchdogMode::Host`] the runtime pets the watchdog for as long as
+//! the guest executes, stopping once the guest fails or exceeds its limits
+//! (which requires a timeout or fuel budget to detect hangs). In
+//! [`WatchdogMode::Guest`] the watchdog is only petted when the guest calls
+//! `kick`, so a hung guest is recovered without execution limits.
+//!
+//! Watchdogs are disarmed on [`Watchdog::close`] following a clean exit,
+//! and otherwise left to expire.
+
+use std::boxed::Box;
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn, error};
+
+use crate::opts::WatchdogMode;
+
+/// Watchdog backend
+pub trait Backend: Send {
+    /// Pet the watchdog, restarting its timeout
+    fn pet(&mut self) -> anyhow::Result<()>;
+
+    /// Disarm the watchdog on clean shutdown
+    fn disarm(&mut self) -> anyhow::Result<()>;
+}
+
+impl Backend for Box<dyn Backend> {
+    fn pet(&mut self) -> anyhow::Result<()> {
+        self.as_mut().pet()
+    }
+
+    fn disarm(&mut self) -> anyhow::Result<()> {
+        self.as_mut().disarm()
+    }
+}
+
+/// Shared watchdog state
+struct State {
+    backend: Box<dyn Backend>,
+    failed: bool,
+}
+
+impl State {
+    fn pet(&mut self) {
+        if self.failed {
+            return;
+        }
+
+        if let Err(e) = self.backend.pet() {
+            warn!("Failed to pet watchdog: {:?}", e);
+        }
+    }
+}
+
+/// Watchdog service
+pub struct Watchdog {
+    state: Arc<Mutex<State>>,
+    mode: WatchdogMode,
+    done: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Start a watchdog service, petting the backend at the provided
+    /// interval in [`WatchdogMode::Host`]
+    pub fn new(backend: impl Backend + 'static, mode: WatchdogMode, interval: Duration) -> Self {
+        let state = Arc::new(Mutex::new(State{ backend: Box::new(backend), failed: false }));
+        let done = Arc::new(AtomicBool::new(false));
+
+        // Pet once on start, the guest may take some time to reach its first kick
+        state.lock().unwrap().pet();
+
+        let thread = match mode {
+            WatchdogMode::Host => {
+                let (s, d) = (state.clone(), done.clone());
+                Some(std::thread::spawn(move || {
+                    while !d.load(Ordering::SeqCst) {
+                        s.lock().unwrap().pet();
+                        std::thread::park_timeout(interval);
+                    }
+                }))
+            },
+            WatchdogMode::Guest => None,
+        };
+
+        debug!("Started watchdog (mode: {}, interval: {:?})", mode, interval);
+
+        Self{ state, mode, done, thread }
+    }
+
+    /// Fetch a handle for guest kicks
+    pub fn handle(&self) -> WatchdogHandle {
+        WatchdogHandle{ state: self.state.clone() }
+    }
+
+    /// Stop petting the watchdog following a guest failure, leaving this to expire
+    pub fn fail(&mut self) {
+        error!("Guest failed, no longer petting watchdog");
+
+        self.state.lock().unwrap().failed = true;
+        self.stop();
+    }
+
+    /// Disarm the watchdog following a clean exit
+    pub fn close(mut self) -> anyhow::Result<()> {
+        self.stop();
+
+        let mut s = self.state.lock().unwrap();
+        if s.failed {
+            return Ok(());
+        }
+
+        info!("Disarming watchdog");
+        s.backend.disarm()
+    }
+
+    /// Watchdog petting mode
+    pub fn mode(&self) -> WatchdogMode {
+        self.mode
+    }
+
+    fn stop(&mut self) {
+        self.done.store(true, Ordering::SeqCst);
+
+        if let Some(t) = self.thread.take() {
+            t.thread().unpark();
+            let _ = t.join();
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Handle used by engines to pet the watchdog on guest kicks
+#[derive(Clone)]
+pub struct WatchdogHandle {
+    state: Arc<Mutex<State>>,
+}
+
+impl WatchdogHandle {
+    /// Pet the watchdog, unless the guest has been marked as failed
+    pub fn kick(&self) {
+        self.state.lock().unwrap().pet();
+    }
+}
+
+/// Software watchdog backend, logging and flagging expiry rather than
+/// resetting the system
+#[derive(Clone)]
+pub struct SoftwareWatchdog {
+    last: Arc<Mutex<Option<Instant>>>,
+    expired: Arc<AtomicBool>,
+}
+
+impl SoftwareWatchdog {
+    /// Create a software watchdog with the provided timeout, which is
+    /// armed on the first pet
+    pub fn new(timeout: Duration) -> Self {
+        let w = Self{
+            last: Arc::new(Mutex::new(None)),
+            expired: Arc::new(AtomicBool::new(false)),
+        };
+
+        // Weak references stop the monitor once all handles are dropped
+        let last = Arc::downgrade(&w.last);
+        let expired = w.expired.clone();
+        let check = (timeout / 10).max(Duration::from_millis(1));
+
+        std::thread::spawn(move || {
+            while let Some(l) = last.upgrade() {
+                let elapsed = l.lock().unwrap().map(|t| t.elapsed());
+                drop(l);
+
+                if let Some(e) = elapsed {
+                    if e > timeout {
+                        error!("Software watchdog expired ({:?} since last pet)", e);
+                        expired.store(true, Ordering::SeqCst);
+                        return;
+                    }
+                }
+
+                std::thread::sleep(check);
+            }
+        });
+
+        w
+    }
+
+    /// Check whether the watchdog has expired
+    pub fn expired(&self) -> bool {
+        self.expired.load(Ordering::SeqCst)
+    }
+}
+
+impl Backend for SoftwareWatchdog {
+    fn pet(&mut self) -> anyhow::Result<()> {
+        *self.last.lock().unwrap() = Some(Instant::now());
+        Ok(())
+    }
+
+    fn disarm(&mut self) -> anyhow::Result<()> {
+        *self.last.lock().unwrap() = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+//! Checks IIO scan element type parsing and sample decoding
+
+#![cfg(feature="hal-linux")]
+
+use wasm_embedded_rt::linux::ScanType;
+use wasm_embedded_spec::Error;
+
+#[test]
+fn scan_type_parse() {
+    assert_eq!(ScanType::parse("le:s12/16>>4\n").unwrap(), ScanType{
+        big_endian: false, signed: true, bits: 12, storage: 16, shift: 4,
+    });
+
+    assert_eq!(ScanType::parse("be:u24/32").unwrap(), ScanType{
+        big_endian: true, signed: false, bits: 24, storage: 32, shift: 0,
+    });
+}
+
+#[test]
+fn scan_type_parse_invalid() {
+    for t in ["", "le", "xe:s12/16", "le:x12/16", "le:s12", "le:sa/16", "le:s12/16>>b"] {
+        assert!(matches!(ScanType::parse(t), Err(Error::InvalidArg)), "accepted {:?}", t);
+    }
+}
+
+#[test]
+fn scan_type_parse_unsupported() {
+    // Empty and oversized samples, sub-byte and unaligned storage, and
+    // samples shifted beyond their storage
+    for t in ["le:s0/16", "le:s33/64", "le:u64/64", "le:u4/4", "le:u12/12", "le:u8/72", "le:u12/16>>8"] {
+        assert!(matches!(ScanType::parse(t), Err(Error::Unsupported)), "accepted {:?}", t);
+    }
+}
+
+#[test]
+fn scan_type_decode() {
+    let t = ScanType::parse("le:s12/16>>4").unwrap();
+    assert_eq!(t.size(), 2);
+    assert_eq!(t.decode(&[0xf0, 0x7f]), 0x7ff);
+    assert_eq!(t.decode(&[0x00, 0x80]), -0x800);
+    assert_eq!(t.decode(&[0xff, 0xff]), -1);
+
+    let t = ScanType::parse("be:u12/16").unwrap();
+    assert_eq!(t.decode(&[0xff, 0xff]), 0xfff);
+    assert_eq!(t.decode(&[0x01, 0x02]), 0x102);
+
+    let t = ScanType::parse("be:s32/32").unwrap();
+    assert_eq!(t.decode(&[0x80, 0x00, 0x00, 0x00]), i32::MIN);
+    assert_eq!(t.decode(&[0x7f, 0xff, 0xff, 0xff]), i32::MAX);
+
+    let t = ScanType::parse("le:u24/64>>8").unwrap();
+    assert_eq!(t.size(), 8);
+    assert_eq!(t.decode(&[0xaa, 0x03, 0x02, 0x01, 0xff, 0, 0, 0]), 0x010203);
+}
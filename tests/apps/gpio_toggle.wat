@@ -0,0 +1,22 @@
+;; GPIO toggle: configure an output, drive it high then low, and release it
+(module
+  (import "gpio" "init" (func $gpio_init (param i32 i32 i32 i32) (result i32)))
+  (import "gpio" "deinit" (func $gpio_deinit (param i32) (result i32)))
+  (import "gpio" "set" (func $gpio_set (param i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+
+  (func (export "_start")
+    ;; Open port 0 pin 4 as an output, writing the handle to 0x00
+    (if (call $gpio_init (i32.const 0) (i32.const 4) (i32.const 1) (i32.const 0))
+      (then unreachable))
+
+    (if (call $gpio_set (i32.load (i32.const 0)) (i32.const 1))
+      (then unreachable))
+    (if (call $gpio_set (i32.load (i32.const 0)) (i32.const 0))
+      (then unreachable))
+
+    (if (call $gpio_deinit (i32.load (i32.const 0)))
+      (then unreachable))
+  )
+)
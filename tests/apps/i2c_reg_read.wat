@@ -0,0 +1,32 @@
+;; I2C register read: read a device ID register via write_read
+(module
+  (import "i2c" "init" (func $i2c_init (param i32 i32 i32 i32 i32) (result i32)))
+  (import "i2c" "deinit" (func $i2c_deinit (param i32) (result i32)))
+  (import "i2c" "write_read" (func $i2c_write_read (param i32 i32 i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+
+  ;; Register address
+  (data (i32.const 16) "\d0")
+
+  ;; Buffer records for the write (0x10) and read (0x20) data
+  (data (i32.const 48) "\10\00\00\00\01\00\00\00")
+  (data (i32.const 56) "\20\00\00\00\01\00\00\00")
+
+  (func (export "_start")
+    ;; Open I2C port 1 at 100kHz, writing the handle to 0x00
+    (if (call $i2c_init (i32.const 1) (i32.const 100000) (i32.const 2) (i32.const 3) (i32.const 0))
+      (then unreachable))
+
+    ;; Write register address from 0x10, read one byte to 0x20
+    (if (call $i2c_write_read (i32.load (i32.const 0)) (i32.const 0x76) (i32.const 48) (i32.const 56))
+      (then unreachable))
+
+    ;; Check the expected device ID was read
+    (if (i32.ne (i32.load8_u (i32.const 32)) (i32.const 0x60))
+      (then unreachable))
+
+    (if (call $i2c_deinit (i32.load (i32.const 0)))
+      (then unreachable))
+  )
+)
@@ -0,0 +1,33 @@
+;; SPI transfer: exchange two bytes with a device
+(module
+  (import "spi" "init" (func $spi_init (param i32 i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "spi" "deinit" (func $spi_deinit (param i32) (result i32)))
+  (import "spi" "transfer" (func $spi_transfer (param i32 i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+
+  ;; Outgoing data
+  (data (i32.const 16) "\9f\00")
+
+  ;; Buffer records for the read (0x20) and write (0x10) data
+  (data (i32.const 48) "\20\00\00\00\02\00\00\00")
+  (data (i32.const 56) "\10\00\00\00\02\00\00\00")
+
+  (func (export "_start")
+    ;; Open SPI port 0 at 1MHz, writing the handle to 0x00
+    (if (call $spi_init (i32.const 0) (i32.const 1000000)
+          (i32.const 10) (i32.const 9) (i32.const 11) (i32.const 8) (i32.const 0))
+      (then unreachable))
+
+    ;; Read two bytes to 0x20 while writing two bytes from 0x10
+    (if (call $spi_transfer (i32.load (i32.const 0)) (i32.const 48) (i32.const 56))
+      (then unreachable))
+
+    ;; Check the expected response was read
+    (if (i32.ne (i32.load16_u (i32.const 32)) (i32.const 0xef00))
+      (then unreachable))
+
+    (if (call $spi_deinit (i32.load (i32.const 0)))
+      (then unreachable))
+  )
+)
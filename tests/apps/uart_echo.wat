@@ -0,0 +1,27 @@
+;; UART echo: read a message and write it back
+(module
+  (import "uart" "init" (func $uart_init (param i32 i32 i32 i32 i32) (result i32)))
+  (import "uart" "deinit" (func $uart_deinit (param i32) (result i32)))
+  (import "uart" "read" (func $uart_read (param i32 i32 i32) (result i32)))
+  (import "uart" "write" (func $uart_write (param i32 i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+
+  ;; Buffer record at 0x08, four bytes at 0x10
+  (data (i32.const 8) "\10\00\00\00\04\00\00\00")
+
+  (func (export "_start")
+    ;; Open UART port 0 at 115200 baud, writing the handle to 0x00
+    (if (call $uart_init (i32.const 0) (i32.const 115200) (i32.const 14) (i32.const 15) (i32.const 0))
+      (then unreachable))
+
+    ;; Read four bytes to 0x10 then write these back
+    (if (call $uart_read (i32.load (i32.const 0)) (i32.const 0) (i32.const 8))
+      (then unreachable))
+    (if (call $uart_write (i32.load (i32.const 0)) (i32.const 0) (i32.const 8))
+      (then unreachable))
+
+    (if (call $uart_deinit (i32.load (i32.const 0)))
+      (then unreachable))
+  )
+)
@@ -0,0 +1,91 @@
+;; WASI hello: print to stdout and stderr, list arguments, environment and
+;; preopened directories, and check clocks and randomness
+(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_sizes_get" (func $args_sizes_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_get" (func $args_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "environ_sizes_get" (func $environ_sizes_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "environ_get" (func $environ_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_time_get" (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "random_get" (func $random_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_prestat_get" (func $fd_prestat_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_prestat_dir_name" (func $fd_prestat_dir_name (param i32 i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+
+  (data (i32.const 0x100) "hello from wasi")
+  (data (i32.const 0x120) "arg: ")
+  (data (i32.const 0x130) "env: ")
+  (data (i32.const 0x140) "dir: ")
+  (data (i32.const 0x150) "clock ok")
+  (data (i32.const 0x160) "random ok")
+  (data (i32.const 0x170) "hello stderr\n")
+  (data (i32.const 0x1ff) "\n")
+
+  ;; Write a buffer to a file descriptor, using the iovec at 0x00
+  (func $write (param $fd i32) (param $ptr i32) (param $len i32)
+    (i32.store (i32.const 0x00) (local.get $ptr))
+    (i32.store (i32.const 0x04) (local.get $len))
+    (if (call $fd_write (local.get $fd) (i32.const 0x00) (i32.const 1) (i32.const 0x08))
+      (then unreachable)))
+
+  ;; Write a prefixed line to stdout
+  (func $line (param $prefix i32) (param $prefix_len i32) (param $s i32) (param $len i32)
+    (call $write (i32.const 1) (local.get $prefix) (local.get $prefix_len))
+    (call $write (i32.const 1) (local.get $s) (local.get $len))
+    (call $write (i32.const 1) (i32.const 0x1ff) (i32.const 1)))
+
+  (func $strlen (param $s i32) (result i32) (local $n i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (i32.load8_u (i32.add (local.get $s) (local.get $n)))))
+        (local.set $n (i32.add (local.get $n) (i32.const 1)))
+        (br $next)))
+    (local.get $n))
+
+  ;; Write a prefixed line for each string in a WASI string array
+  (func $strings (param $ptrs i32) (param $count i32) (param $first i32) (param $prefix i32) (param $prefix_len i32)
+    (local $i i32) (local $s i32)
+    (local.set $i (local.get $first))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (local.get $count)))
+        (local.set $s (i32.load (i32.add (local.get $ptrs) (i32.mul (local.get $i) (i32.const 4)))))
+        (call $line (local.get $prefix) (local.get $prefix_len) (local.get $s) (call $strlen (local.get $s)))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next))))
+
+  (func (export "_start")
+    (call $line (i32.const 0x100) (i32.const 15) (i32.const 0) (i32.const 0))
+    (call $write (i32.const 2) (i32.const 0x170) (i32.const 13))
+
+    ;; Arguments following the program name, pointers at 0x1000 and strings at 0x2000
+    (if (call $args_sizes_get (i32.const 0x10) (i32.const 0x14)) (then unreachable))
+    (if (call $args_get (i32.const 0x1000) (i32.const 0x2000)) (then unreachable))
+    (call $strings (i32.const 0x1000) (i32.load (i32.const 0x10)) (i32.const 1) (i32.const 0x120) (i32.const 5))
+
+    ;; Environment, pointers at 0x3000 and strings at 0x4000
+    (if (call $environ_sizes_get (i32.const 0x18) (i32.const 0x1c)) (then unreachable))
+    (if (call $environ_get (i32.const 0x3000) (i32.const 0x4000)) (then unreachable))
+    (call $strings (i32.const 0x3000) (i32.load (i32.const 0x18)) (i32.const 0) (i32.const 0x130) (i32.const 5))
+
+    ;; Monotonic time never decreases, realtime is set
+    (if (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 0x20)) (then unreachable))
+    (if (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 0x28)) (then unreachable))
+    (if (i64.lt_u (i64.load (i32.const 0x28)) (i64.load (i32.const 0x20))) (then unreachable))
+    (if (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 0x20)) (then unreachable))
+    (if (i64.eqz (i64.load (i32.const 0x20))) (then unreachable))
+    (call $line (i32.const 0x150) (i32.const 8) (i32.const 0) (i32.const 0))
+
+    (if (call $random_get (i32.const 0x40) (i32.const 16)) (then unreachable))
+    (if (i64.eqz (i64.or (i64.load (i32.const 0x40)) (i64.load (i32.const 0x48)))) (then unreachable))
+    (call $line (i32.const 0x160) (i32.const 9) (i32.const 0) (i32.const 0))
+
+    ;; The first preopened directory, if any, is file descriptor 3
+    (if (i32.eqz (call $fd_prestat_get (i32.const 3) (i32.const 0x30)))
+      (then
+        (if (call $fd_prestat_dir_name (i32.const 3) (i32.const 0x5000) (i32.load (i32.const 0x34)))
+          (then unreachable))
+        (call $line (i32.const 0x140) (i32.const 5) (i32.const 0x5000) (i32.load (i32.const 0x34)))))
+  )
+)
@@ -0,0 +1,372 @@
+//! Checks wasmtime runtime bindings for spec and extended peripherals,
+//! executing WAT guests against mock scripts
+
+#![cfg(all(feature="hal-mock", feature="rt-wasmtime"))]
+
+use wasm_embedded_rt::{opts::Runtime, module, mock::{MockCtx, Kind, CanFrame}, ext::{Polarity, Filter}};
+
+/// Execute a WAT guest under wasmtime, verifying mock operations
+fn exec(wat: &str, ctx: MockCtx) -> anyhow::Result<Vec<Kind>> {
+    let bin = wat::parse_str(wat)?;
+    let handle = ctx.handle();
+
+    let res = wasm_embedded_rt::run(&Runtime::Wasmtime, ctx, &bin);
+
+    let report = handle.verify()?;
+    res?;
+
+    Ok(report.ops)
+}
+
+#[test]
+fn bindings_invalid_pointer() {
+    // Handles written out of bounds fail with InvalidArg (1) rather than trapping
+    let wat = r#"(module
+      (import "gpio" "init" (func $init (param i32 i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "_start")
+        (if (i32.ne (call $init (i32.const 0) (i32.const 4) (i32.const 1) (i32.const 65534)) (i32.const 1))
+          (then unreachable)))
+    )"#;
+
+    let ctx = MockCtx::builder()
+        .expect(Kind::GpioInit{ port: 0, pin: 4, output: true }).returns(2)
+        .build();
+
+    exec(wat, ctx).unwrap();
+}
+
+#[test]
+fn bindings_spi_exec() {
+    // Operations are 12 byte records of kind (transfer, read, write) and buffer
+    let wat = r#"(module
+      (import "spi" "exec" (func $exec (param i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 16) "\9f\00")
+      (data (i32.const 32) "\02\00\00\00\10\00\00\00\01\00\00\00")
+      (data (i32.const 44) "\01\00\00\00\18\00\00\00\02\00\00\00")
+      (data (i32.const 56) "\00\00\00\00\11\00\00\00\01\00\00\00")
+      (data (i32.const 68) "\07\00\00\00\10\00\00\00\01\00\00\00")
+      (func (export "_start")
+        (if (call $exec (i32.const 1) (i32.const 32) (i32.const 3)) (then unreachable))
+        (if (i32.ne (i32.load16_u (i32.const 24)) (i32.const 0xef00)) (then unreachable))
+        (if (i32.ne (i32.load8_u (i32.const 17)) (i32.const 0x5a)) (then unreachable))
+
+        ;; Unknown kinds and operation lists beyond guest memory are rejected
+        (if (i32.ne (call $exec (i32.const 1) (i32.const 68) (i32.const 1)) (i32.const 1)) (then unreachable))
+        (if (i32.ne (call $exec (i32.const 1) (i32.const 65530) (i32.const 1)) (i32.const 1)) (then unreachable)))
+    )"#;
+
+    let ctx = MockCtx::builder()
+        .expect(Kind::SpiWrite{ handle: 1, data_out: vec![0x9f] })
+        .expect(Kind::SpiRead{ handle: 1, data_in: vec![0x00, 0xef] })
+        .expect(Kind::SpiTransferInplace{ handle: 1, data_out: vec![0x00], data_in: vec![0x5a] })
+        .build();
+
+    exec(wat, ctx).unwrap();
+}
+
+#[test]
+fn bindings_pwm() {
+    let wat = r#"(module
+      (import "pwm" "init" (func $init (param i32 i32 i32) (result i32)))
+      (import "pwm" "set_period" (func $period (param i32 i32) (result i32)))
+      (import "pwm" "set_duty_cycle" (func $duty (param i32 i32) (result i32)))
+      (import "pwm" "set_polarity" (func $polarity (param i32 i32) (result i32)))
+      (import "pwm" "enable" (func $enable (param i32 i32) (result i32)))
+      (import "pwm" "deinit" (func $deinit (param i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "_start")
+        (if (call $init (i32.const 0) (i32.const 1) (i32.const 0)) (then unreachable))
+        (if (call $period (i32.load (i32.const 0)) (i32.const 20000000)) (then unreachable))
+        (if (call $duty (i32.load (i32.const 0)) (i32.const 1500000)) (then unreachable))
+        (if (call $polarity (i32.load (i32.const 0)) (i32.const 1)) (then unreachable))
+        ;; Unknown polarities are rejected without reaching the driver
+        (if (i32.ne (call $polarity (i32.load (i32.const 0)) (i32.const 2)) (i32.const 1)) (then unreachable))
+        (if (call $enable (i32.load (i32.const 0)) (i32.const 1)) (then unreachable))
+        (if (call $deinit (i32.load (i32.const 0))) (then unreachable)))
+    )"#;
+
+    let ctx = MockCtx::builder()
+        .expect(Kind::PwmInit{ chip: 0, channel: 1 }).returns(3)
+        .expect(Kind::PwmSetPeriod{ handle: 3, period_ns: 20_000_000 })
+        .expect(Kind::PwmSetDutyCycle{ handle: 3, duty_ns: 1_500_000 })
+        .expect(Kind::PwmSetPolarity{ handle: 3, polarity: Polarity::Inversed })
+        .expect(Kind::PwmEnable{ handle: 3, enabled: true })
+        .expect(Kind::PwmDeinit{ handle: 3 })
+        .build();
+
+    exec(wat, ctx).unwrap();
+}
+
+#[test]
+fn bindings_adc() {
+    let wat = r#"(module
+      (import "adc" "init" (func $init (param i32 i32 i32) (result i32)))
+      (import "adc" "read_raw" (func $read_raw (param i32 i32) (result i32)))
+      (import "adc" "read" (func $read (param i32 i32) (result i32)))
+      (import "adc" "capture" (func $capture (param i32 i32 i32 i32) (result i32)))
+      (import "adc" "deinit" (func $deinit (param i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "_start")
+        (if (call $init (i32.const 0) (i32.const 2) (i32.const 0)) (then unreachable))
+        (if (call $read_raw (i32.load (i32.const 0)) (i32.const 4)) (then unreachable))
+        (if (i32.ne (i32.load (i32.const 4)) (i32.const 2048)) (then unreachable))
+        (if (call $read (i32.load (i32.const 0)) (i32.const 8)) (then unreachable))
+        (if (f32.ne (f32.load (i32.const 8)) (f32.const 1650.5)) (then unreachable))
+        (if (call $capture (i32.load (i32.const 0)) (i32.const 1) (i32.const 16) (i32.const 3)) (then unreachable))
+        (if (i32.ne (i32.load (i32.const 24)) (i32.const -7)) (then unreachable))
+        ;; Buffers beyond guest memory are rejected prior to capture
+        (if (i32.ne (call $capture (i32.load (i32.const 0)) (i32.const 1) (i32.const 65532) (i32.const 2)) (i32.const 1))
+          (then unreachable))
+        (if (call $deinit (i32.load (i32.const 0))) (then unreachable)))
+    )"#;
+
+    let ctx = MockCtx::builder()
+        .expect(Kind::AdcInit{ device: 0, channel: 2 }).returns(1)
+        .expect(Kind::AdcReadRaw{ handle: 1, value: 2048 })
+        .expect(Kind::AdcRead{ handle: 1, value: 1650.5 })
+        .expect(Kind::AdcCapture{ handle: 1, trigger: 1, samples: vec![10, 20, -7] })
+        .expect(Kind::AdcDeinit{ handle: 1 })
+        .build();
+
+    exec(wat, ctx).unwrap();
+}
+
+#[test]
+fn bindings_can() {
+    let wat = r#"(module
+      (import "can" "init" (func $init (param i32 i32 i32) (result i32)))
+      (import "can" "set_filters" (func $filters (param i32 i32 i32) (result i32)))
+      (import "can" "send" (func $send (param i32 i32) (result i32)))
+      (import "can" "receive" (func $receive (param i32 i32 i32 i32) (result i32)))
+      (import "can" "deinit" (func $deinit (param i32) (result i32)))
+      (memory (export "memory") 1)
+
+      ;; Filters: 0x100/0x700 standard, 0x18DA0000/0x1FFF0000 extended
+      (data (i32.const 16) "\00\01\00\00\00\07\00\00\00\00\00\00")
+      (data (i32.const 28) "\00\00\DA\18\00\00\FF\1F\01\00\00\00")
+      ;; Extended CAN-FD frame 0x18DA10F1 with 12 bytes of data
+      (data (i32.const 64) "\F1\10\DA\18\05\0C\00\00\01\02\03\04\05\06\07\08\09\0A\0B\0C")
+
+      (func (export "_start")
+        (if (call $init (i32.const 0) (i32.const 1) (i32.const 0)) (then unreachable))
+        (if (call $filters (i32.load (i32.const 0)) (i32.const 16) (i32.const 2)) (then unreachable))
+        (if (call $send (i32.load (i32.const 0)) (i32.const 64)) (then unreachable))
+
+        ;; Receive a standard frame 0x123 [0xAA, 0xBB] to 0x100
+        (if (call $receive (i32.load (i32.const 0)) (i32.const 100) (i32.const 256) (i32.const 4)) (then unreachable))
+        (if (i32.ne (i32.load (i32.const 4)) (i32.const 1)) (then unreachable))
+        (if (i32.ne (i32.load (i32.const 256)) (i32.const 0x123)) (then unreachable))
+        (if (i32.ne (i32.load16_u (i32.const 260)) (i32.const 0x0200)) (then unreachable))
+        (if (i32.ne (i32.load16_u (i32.const 264)) (i32.const 0xBBAA)) (then unreachable))
+
+        ;; Timeouts report no frame received
+        (if (call $receive (i32.load (i32.const 0)) (i32.const -1) (i32.const 256) (i32.const 4)) (then unreachable))
+        (if (i32.load (i32.const 4)) (then unreachable))
+
+        (if (call $deinit (i32.load (i32.const 0))) (then unreachable)))
+    )"#;
+
+    let ctx = MockCtx::builder()
+        .expect(Kind::CanInit{ port: 0, fd: true }).returns(1)
+        .expect(Kind::CanSetFilters{ handle: 1, filters: vec![
+            Filter{ id: 0x100, mask: 0x700, extended: false },
+            Filter{ id: 0x18DA_0000, mask: 0x1FFF_0000, extended: true },
+        ]})
+        .expect(Kind::CanSend{ handle: 1, frame: CanFrame{
+            id: 0x18DA_10F1, extended: true, fd: true, remote: None, data: (1..=12).collect(),
+        }})
+        .expect(Kind::CanReceive{ handle: 1, timeout_ms: Some(100), frame: Some(CanFrame{
+            id: 0x123, extended: false, fd: false, remote: None, data: vec![0xAA, 0xBB],
+        })})
+        .expect(Kind::CanReceive{ handle: 1, timeout_ms: None, frame: None })
+        .expect(Kind::CanDeinit{ handle: 1 })
+        .build();
+
+    exec(wat, ctx).unwrap();
+}
+
+#[test]
+fn bindings_onewire() {
+    let wat = r#"(module
+      (import "onewire" "init" (func $init (param i32 i32 i32) (result i32)))
+      (import "onewire" "reset" (func $reset (param i32 i32) (result i32)))
+      (import "onewire" "search" (func $search (param i32 i32 i32 i32) (result i32)))
+      (import "onewire" "write_byte" (func $write (param i32 i32) (result i32)))
+      (import "onewire" "read_byte" (func $read (param i32 i32) (result i32)))
+      (import "onewire" "deinit" (func $deinit (param i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "_start")
+        (if (call $init (i32.const 1) (i32.const -1) (i32.const 0)) (then unreachable))
+        (if (call $reset (i32.load (i32.const 0)) (i32.const 4)) (then unreachable))
+        (if (i32.ne (i32.load (i32.const 4)) (i32.const 1)) (then unreachable))
+
+        ;; Search into space for two devices at 0x100, with one present
+        (if (call $search (i32.load (i32.const 0)) (i32.const 0x100) (i32.const 2) (i32.const 8)) (then unreachable))
+        (if (i32.ne (i32.load (i32.const 8)) (i32.const 1)) (then unreachable))
+        (if (i64.ne (i64.load (i32.const 0x100)) (i64.const 0x9003160f1e64ff28)) (then unreachable))
+        ;; ROM buffers beyond guest memory are rejected prior to search
+        (if (i32.ne (call $search (i32.load (i32.const 0)) (i32.const 65528) (i32.const 2) (i32.const 8)) (i32.const 1))
+          (then unreachable))
+
+        (if (call $write (i32.load (i32.const 0)) (i32.const 0xcc)) (then unreachable))
+        (if (call $read (i32.load (i32.const 0)) (i32.const 12)) (then unreachable))
+        (if (i32.ne (i32.load (i32.const 12)) (i32.const 0x50)) (then unreachable))
+        (if (call $deinit (i32.load (i32.const 0))) (then unreachable)))
+    )"#;
+
+    let ctx = MockCtx::builder()
+        .expect(Kind::OneWireInit{ bus: 1, pin: -1 }).returns(2)
+        .expect(Kind::OneWireReset{ handle: 2, presence: true })
+        .expect(Kind::OneWireSearch{ handle: 2, roms: vec![[0x28, 0xff, 0x64, 0x1e, 0x0f, 0x16, 0x03, 0x90]] })
+        .expect(Kind::OneWireWriteByte{ handle: 2, data: 0xcc })
+        .expect(Kind::OneWireReadByte{ handle: 2, data: 0x50 })
+        .expect(Kind::OneWireDeinit{ handle: 2 })
+        .build();
+
+    exec(wat, ctx).unwrap();
+}
+
+#[test]
+fn bindings_time() {
+    let wat = r#"(module
+      (import "time" "now_us" (func $now (param i32) (result i32)))
+      (import "time" "timer_start" (func $start (param i32 i32) (result i32)))
+      (import "time" "dispatch" (func $dispatch (param i32 i32) (result i32)))
+      (import "time" "timer_stop" (func $stop (param i32) (result i32)))
+      (memory (export "memory") 1)
+
+      ;; Count callbacks per handle at 0x100
+      (func (export "timer_handler") (param $h i32)
+        (i32.store (i32.const 0x100) (i32.add (i32.load (i32.const 0x100)) (i32.const 1)))
+        (i32.store (i32.const 0x104) (local.get $h)))
+
+      (func (export "_start")
+        (if (call $now (i32.const 8)) (then unreachable))
+        (if (i64.ne (i64.load (i32.const 8)) (i64.const 10000)) (then unreachable))
+        (if (call $start (i32.const 1000) (i32.const 0)) (then unreachable))
+
+        ;; The first expiry is delivered to the handler
+        (if (call $dispatch (i32.const -1) (i32.const 4)) (then unreachable))
+        (if (i32.ne (i32.load (i32.const 4)) (i32.const 1)) (then unreachable))
+        (if (i32.ne (i32.load (i32.const 0x100)) (i32.const 1)) (then unreachable))
+        (if (i32.ne (i32.load (i32.const 0x104)) (i32.const 5)) (then unreachable))
+
+        ;; Timeouts ahead of the next expiry dispatch nothing
+        (if (call $dispatch (i32.const 200) (i32.const 4)) (then unreachable))
+        (if (i32.load (i32.const 4)) (then unreachable))
+
+        (if (call $stop (i32.load (i32.const 0))) (then unreachable)))
+    )"#;
+
+    let ctx = MockCtx::builder()
+        .expect(Kind::TimeNow{ us: 10_000 })
+        .expect(Kind::TimerStart{ period_us: 1_000 }).returns(5)
+        .expect(Kind::TimerWaitAny{ timeout_us: None, handle: Some(5) })
+        .expect(Kind::TimerPoll{ handle: None })
+        .expect(Kind::TimerWaitAny{ timeout_us: Some(200), handle: None })
+        .expect(Kind::TimerStop{ handle: 5 })
+        .build();
+
+    exec(wat, ctx).unwrap();
+}
+
+#[test]
+fn bindings_time_dispatch_requires_handler() {
+    // Guests without a handler export cannot receive callbacks (Unsupported, 5)
+    let wat = r#"(module
+      (import "time" "dispatch" (func $dispatch (param i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (func (export "_start")
+        (if (i32.ne (call $dispatch (i32.const -1) (i32.const 0)) (i32.const 5)) (then unreachable)))
+    )"#;
+
+    exec(wat, MockCtx::builder().build()).unwrap();
+}
+
+#[test]
+fn bindings_watchdog() {
+    let wat = r#"(module
+      (import "watchdog" "kick" (func $kick (result i32)))
+      (func (export "_start")
+        (if (call $kick) (then unreachable))
+        (if (call $kick) (then unreachable)))
+    )"#;
+
+    let ctx = MockCtx::builder()
+        .expect(Kind::WatchdogKick)
+        .expect(Kind::WatchdogKick)
+        .build();
+
+    exec(wat, ctx).unwrap();
+}
+
+#[test]
+fn bindings_storage() {
+    let wat = r#"(module
+      (import "storage" "get" (func $get (param i32 i32 i32 i32 i32) (result i32)))
+      (import "storage" "set" (func $set (param i32 i32 i32 i32) (result i32)))
+      (import "storage" "remove" (func $remove (param i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 16) "count")
+      (data (i32.const 24) "boot")
+      (data (i32.const 32) "../x")
+
+      (func (export "_start")
+        ;; Values are truncated to the buffer, reporting the full length
+        (if (call $get (i32.const 16) (i32.const 5) (i32.const 64) (i32.const 1) (i32.const 0)) (then unreachable))
+        (if (i32.ne (i32.load (i32.const 0)) (i32.const 2)) (then unreachable))
+        (if (i32.ne (i32.load8_u (i32.const 64)) (i32.const 0x0d)) (then unreachable))
+        (if (i32.ne (i32.load8_u (i32.const 65)) (i32.const 0)) (then unreachable))
+
+        ;; Unset keys report a length of -1
+        (if (call $get (i32.const 24) (i32.const 4) (i32.const 64) (i32.const 8) (i32.const 0)) (then unreachable))
+        (if (i32.ne (i32.load (i32.const 0)) (i32.const -1)) (then unreachable))
+
+        (i32.store8 (i32.const 64) (i32.const 0x0e))
+        (if (call $set (i32.const 16) (i32.const 5) (i32.const 64) (i32.const 1)) (then unreachable))
+        (if (call $remove (i32.const 24) (i32.const 4) (i32.const 4)) (then unreachable))
+        (if (i32.load (i32.const 4)) (then unreachable))
+
+        ;; Invalid keys are rejected (InvalidArg, 1)
+        (if (i32.ne (call $set (i32.const 32) (i32.const 4) (i32.const 64) (i32.const 1)) (i32.const 1)) (then unreachable)))
+    )"#;
+
+    let ctx = MockCtx::builder()
+        .storage("count", &[0x0d, 0x01])
+        .expect(Kind::StorageGet{ key: "count".into(), value: Some(vec![0x0d, 0x01]) })
+        .expect(Kind::StorageGet{ key: "boot".into(), value: None })
+        .expect(Kind::StorageSet{ key: "count".into(), value: vec![0x0e] })
+        .expect(Kind::StorageRemove{ key: "boot".into(), existed: false })
+        .expect(Kind::StorageSet{ key: "../x".into(), value: vec![0x0e] })
+        .expect_storage("count", &[0x0e])
+        .build();
+
+    exec(wat, ctx).unwrap();
+}
+
+#[test]
+fn bindings_log() {
+    let wat = r#"(module
+      (import "log" "write" (func $write (param i32 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 16) "hello \ff")
+      (func (export "_start")
+        ;; Invalid UTF-8 is replaced rather than rejected
+        (if (call $write (i32.const 3) (i32.const 16) (i32.const 7)) (then unreachable))
+        ;; Unknown levels and out of bounds messages are rejected (InvalidArg, 1)
+        (if (i32.ne (call $write (i32.const 6) (i32.const 16) (i32.const 7)) (i32.const 1)) (then unreachable))
+        (if (i32.ne (call $write (i32.const 3) (i32.const 65535) (i32.const 7)) (i32.const 1)) (then unreachable)))
+    )"#;
+
+    exec(wat, MockCtx::builder().build()).unwrap();
+}
+
+#[test]
+fn bindings_ext_rejected_without_runtime_support() {
+    let bin = wat::parse_str(r#"(module (import "pwm" "enable" (func (param i32 i32) (result i32))))"#).unwrap();
+
+    let e = module::check_ext(&bin).unwrap_err().to_string();
+    assert!(e.contains("pwm::enable"), "unexpected error: {}", e);
+}
@@ -0,0 +1,97 @@
+//! SocketCAN driver tests against the `vcan0` virtual interface
+//!
+//! These are skipped where `vcan0` is not available, to set this up:
+//!
+//! ```sh
+//! sudo modprobe vcan
+//! sudo ip link add dev vcan0 type vcan
+//! sudo ip link set vcan0 mtu 72 up
+//! ```
+
+#![cfg(feature="hal-linux")]
+
+use std::path::Path;
+
+use wasm_embedded_rt::linux::CanDriver;
+use wasm_embedded_rt::ext::{Can, Filter, Frame};
+use wasm_embedded_spec::Error;
+
+/// Open a pair of sockets on `vcan0`, returning `None` if this is not available
+fn open(fd: bool) -> Option<(CanDriver, i32, i32)> {
+    if !Path::new("/sys/class/net/vcan0").exists() {
+        eprintln!("vcan0 not available, skipping");
+        return None;
+    }
+
+    let mut can = CanDriver::new().with_prefix("vcan");
+    let tx = match can.init(0, fd) {
+        Err(Error::Unsupported) => {
+            eprintln!("vcan0 does not support CAN-FD, skipping");
+            return None;
+        },
+        r => r.unwrap(),
+    };
+    let rx = can.init(0, fd).unwrap();
+
+    Some((can, tx, rx))
+}
+
+#[test]
+fn vcan_send_receive() {
+    let (mut can, tx, rx) = match open(false) {
+        Some(c) => c,
+        None => return,
+    };
+
+    let frames = [
+        Frame::new(0x123, false, false, &[1, 2, 3]).unwrap(),
+        Frame::new(0x1234_5678, true, false, &[4; 8]).unwrap(),
+        Frame::remote(0x456, false, 2).unwrap(),
+    ];
+
+    for f in &frames {
+        can.send(tx, f).unwrap();
+        assert_eq!(can.receive(rx, Some(1000)).unwrap(), Some(*f));
+    }
+
+    // Nothing further pending
+    assert_eq!(can.receive(rx, Some(10)).unwrap(), None);
+}
+
+#[test]
+fn vcan_filters() {
+    let (mut can, tx, rx) = match open(false) {
+        Some(c) => c,
+        None => return,
+    };
+
+    can.set_filters(rx, &[Filter{ id: 0x100, mask: 0x700, extended: false }]).unwrap();
+
+    let rejected = Frame::new(0x200, false, false, &[1]).unwrap();
+    let extended = Frame::new(0x100, true, false, &[2]).unwrap();
+    let accepted = Frame::new(0x1ab, false, false, &[3]).unwrap();
+
+    for f in &[rejected, extended, accepted] {
+        can.send(tx, f).unwrap();
+    }
+
+    assert_eq!(can.receive(rx, Some(1000)).unwrap(), Some(accepted));
+    assert_eq!(can.receive(rx, Some(10)).unwrap(), None);
+
+    // Clearing filters receives all frames
+    can.set_filters(rx, &[]).unwrap();
+    can.send(tx, &rejected).unwrap();
+    assert_eq!(can.receive(rx, Some(1000)).unwrap(), Some(rejected));
+}
+
+#[test]
+fn vcan_fd() {
+    let (mut can, tx, rx) = match open(true) {
+        Some(c) => c,
+        None => return,
+    };
+
+    let f = Frame::new(0x321, false, true, &[5; 48]).unwrap();
+    can.send(tx, &f).unwrap();
+    assert_eq!(can.receive(rx, Some(1000)).unwrap(), Some(f));
+}
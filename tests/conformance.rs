@@ -0,0 +1,74 @@
+//! Cross-runtime conformance tests, executing test apps against mock scripts
+//!
+//! Each app in `tests/apps` is a WAT source with a matching mock TOML
+//! script, and is executed under every runtime enabled in this build.
+//! Apps must match their scripts and produce identical operation logs
+//! across runtimes.
+
+#![cfg(feature="hal-mock")]
+
+use std::path::PathBuf;
+
+use wasm_embedded_rt::{opts::Runtime, mock::{MockCtx, Kind}};
+
+/// Resolve a test app resource
+fn app_path(app: &str, ext: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/apps")
+        .join(app)
+        .with_extension(ext)
+}
+
+/// Execute a test app under the provided runtime, returning the executed
+/// operations once these have been verified against the app mock script
+fn exec(runtime: &Runtime, app: &str) -> anyhow::Result<Vec<Kind>> {
+    let bin = wat::parse_file(app_path(app, "wat"))?;
+    let ctx = MockCtx::load(app_path(app, "toml").to_str().unwrap())?;
+    let handle = ctx.handle();
+
+    let res = wasm_embedded_rt::run(runtime, ctx, &bin);
+
+    let report = handle.verify()?;
+    res?;
+
+    Ok(report.ops)
+}
+
+/// Execute a test app under each enabled runtime, checking operations
+/// match the mock script and are identical across runtimes
+fn conformance(app: &str) {
+    let mut logs: Vec<(Runtime, Vec<Kind>)> = Vec::new();
+
+    for runtime in Runtime::enabled() {
+        let ops = match exec(&runtime, app) {
+            Ok(ops) => ops,
+            Err(e) => panic!("{} failed under {}: {:?}", app, runtime, e),
+        };
+
+        if let Some((r, o)) = logs.first() {
+            assert_eq!(o, &ops, "{} operations differ between {} and {}", app, r, runtime);
+        }
+
+        logs.push((runtime, ops));
+    }
+}
+
+#[test]
+fn gpio_toggle() {
+    conformance("gpio_toggle");
+}
+
+#[test]
+fn i2c_reg_read() {
+    conformance("i2c_reg_read");
+}
+
+#[test]
+fn spi_transfer() {
+    conformance("spi_transfer");
+}
+
+#[test]
+fn uart_echo() {
+    conformance("uart_echo");
+}
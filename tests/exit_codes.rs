@@ -0,0 +1,102 @@
+//! Process exit status mapping, executing guests through the runtime
+//! binary against an empty mock script
+
+#![cfg(all(feature="rt", feature="hal-mock", feature="rt-wasmtime"))]
+
+use std::{path::PathBuf, process::{Command, Output}};
+
+/// Write a guest and empty mock script to a temporary directory,
+/// returning the directory
+fn guest_dir(name: &str, wat: &str) -> PathBuf {
+    let d = std::env::temp_dir().join(format!("wasm-embedded-exit-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&d);
+    std::fs::create_dir_all(&d).unwrap();
+
+    std::fs::write(d.join("guest.wasm"), wat::parse_str(wat).unwrap()).unwrap();
+    std::fs::write(d.join("mock.toml"), "ops = []\n").unwrap();
+
+    d
+}
+
+/// Execute a guest, returning the process output
+fn exec(name: &str, wat: &str, args: &[&str]) -> Output {
+    let d = guest_dir(name, wat);
+
+    let out = Command::new(env!("CARGO_BIN_EXE_wasm-embedded-rt"))
+        .args(["--engine", "mock", "--runtime", "wasmtime", "--log-level", "off"])
+        .arg("--config").arg(d.join("mock.toml"))
+        .args(args)
+        .arg(d.join("guest.wasm"))
+        .output()
+        .unwrap();
+
+    let _ = std::fs::remove_dir_all(&d);
+
+    out
+}
+
+/// Build a guest exiting via WASI `proc_exit` with the provided code
+fn proc_exit(code: i32) -> String {
+    format!(r#"(module
+      (import "wasi_snapshot_preview1" "proc_exit" (func $exit (param i32)))
+      (memory (export "memory") 1)
+      (func (export "_start") (call $exit (i32.const {})) unreachable)
+    )"#, code)
+}
+
+#[test]
+fn exit_codes_proc_exit() {
+    assert_eq!(exec("exit-0", &proc_exit(0), &[]).status.code(), Some(0));
+    assert_eq!(exec("exit-7", &proc_exit(7), &[]).status.code(), Some(7));
+}
+
+#[test]
+fn exit_codes_out_of_range_are_clamped() {
+    // Codes colliding with host exit codes are reported as the maximum guest code
+    assert_eq!(exec("exit-124", &proc_exit(124), &[]).status.code(), Some(123));
+
+    let wat = r#"(module (func (export "_start") (result i32) (i32.const -1)))"#;
+    assert_eq!(exec("entry-neg", wat, &[]).status.code(), Some(123));
+
+    // WASI rejects statuses outside `0..126`, failing execution
+    assert_eq!(exec("exit-neg", &proc_exit(-1), &[]).status.code(), Some(125));
+}
+
+#[test]
+fn exit_codes_entrypoint_result() {
+    let wat = r#"(module (func (export "_start") (result i32) (i32.const 5)))"#;
+    assert_eq!(exec("entry-5", wat, &[]).status.code(), Some(5));
+
+    let wat = r#"(module (func (export "_start") (result i32) (i32.const 0)))"#;
+    assert_eq!(exec("entry-0", wat, &[]).status.code(), Some(0));
+}
+
+#[test]
+fn exit_codes_trap_backtrace() {
+    let wat = r#"(module
+      (func $check_sensor unreachable)
+      (func (export "_start") (call $check_sensor))
+    )"#;
+
+    let out = exec("trap", wat, &[]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+
+    assert_eq!(out.status.code(), Some(134));
+    assert!(stderr.contains("guest trapped"), "unexpected stderr: {}", stderr);
+    assert!(stderr.contains("check_sensor"), "backtrace not symbolized: {}", stderr);
+}
+
+#[test]
+fn exit_codes_limit_exceeded() {
+    let wat = r#"(module (func (export "_start") (loop br 0)))"#;
+
+    assert_eq!(exec("fuel", wat, &["--fuel", "10000"]).status.code(), Some(124));
+    assert_eq!(exec("timeout", wat, &["--timeout", "0.1"]).status.code(), Some(124));
+}
+
+#[test]
+fn exit_codes_failure() {
+    // Invalid entrypoints fail prior to execution
+    let wat = r#"(module (func (export "_start") (param i32)))"#;
+    assert_eq!(exec("invalid", wat, &[]).status.code(), Some(125));
+}
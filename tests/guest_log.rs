@@ -0,0 +1,95 @@
+//! Checks guest log messages are tagged, filtered and rate limited
+
+#![cfg(feature="hal-mock")]
+
+use std::sync::{Mutex, Once};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use wasm_embedded_rt::{EngineExt, mock::MockCtx};
+use wasm_embedded_rt::ext::{Logger, level};
+use wasm_embedded_rt::logging::{GuestLog, LogConfig, LOG_MAX_LEN};
+
+/// Host logger capturing guest records as (target, level, message)
+struct Capture(Mutex<Vec<(String, Level, String)>>);
+
+impl Log for Capture {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target().starts_with("guest::") {
+            self.0.lock().unwrap().push((record.target().into(), record.level(), record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+static INIT: Once = Once::new();
+
+/// Fetch records captured for a module, tests use distinct modules as
+/// they share the host logger
+fn captured(module: &str) -> Vec<(Level, String)> {
+    INIT.call_once(|| {
+        log::set_logger(&CAPTURE).unwrap();
+        log::set_max_level(LevelFilter::Debug);
+    });
+
+    let target = format!("guest::{}", module);
+    CAPTURE.0.lock().unwrap().iter()
+        .filter(|(t, _, _)| t == &target)
+        .map(|(_, l, m)| (*l, m.clone()))
+        .collect()
+}
+
+#[test]
+fn guest_log_filters() {
+    captured("filters");
+
+    let mut ctx = MockCtx::builder().build()
+        .with_logger(GuestLog::new("filters", LogConfig{ level: LevelFilter::Info, rate: 0 }));
+    let logger = ctx.logger().unwrap();
+
+    logger.write(level(1).unwrap(), "sensor missing").unwrap();
+    logger.write(Level::Info, "line one\nline two").unwrap();
+    // Filtered by the module level
+    logger.write(Level::Debug, "module debug").unwrap();
+    // Filtered by the host level
+    logger.write(Level::Trace, "host trace").unwrap();
+    logger.write(Level::Warn, &"x".repeat(LOG_MAX_LEN + 1)).unwrap();
+
+    assert!(level(0).is_err() && level(6).is_err());
+
+    let records = captured("filters");
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0], (Level::Error, "[filters] sensor missing".into()));
+    assert_eq!(records[1], (Level::Info, "[filters] line one\\nline two".into()));
+    assert_eq!(records[2].1, format!("[filters] {}...", "x".repeat(LOG_MAX_LEN)));
+}
+
+#[test]
+fn guest_log_rate_limit() {
+    captured("chatty");
+
+    let mut log = GuestLog::new("chatty", LogConfig{ level: LevelFilter::Trace, rate: 10 });
+    for i in 0..100 {
+        log.write(Level::Info, &format!("message {}", i)).unwrap();
+    }
+
+    // Bursts are limited to one second of messages
+    assert!(log.dropped() >= 89);
+    assert!(captured("chatty").len() <= 11);
+
+    // Suppressed messages are summarised once logging resumes
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    log.write(Level::Info, "resumed").unwrap();
+
+    let records = captured("chatty");
+    let n = records.len();
+    assert_eq!(records[n-2].0, Level::Warn);
+    assert!(records[n-2].1.ends_with("messages suppressed by rate limit"));
+    assert_eq!(records[n-1].1, "[chatty] resumed");
+}
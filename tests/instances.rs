@@ -0,0 +1,64 @@
+//! Checks concurrent guest instance limits
+
+#![cfg(all(feature="hal-mock", feature="rt-wasmtime"))]
+
+use std::time::{Duration, Instant};
+
+use wasm_embedded_rt::{StopToken, server::Stopped, opts::{Limits, Runtime}, limits::LimitExceeded, module::Guest, mock::MockCtx};
+
+/// Execute a WAT guest under wasmtime with the provided limits
+fn exec(wat: &str, limits: &Limits, stop: &StopToken) -> anyhow::Result<()> {
+    let bin = wat::parse_str(wat)?;
+    let ctx = MockCtx::builder().build();
+
+    wasm_embedded_rt::run_limited(&Runtime::Wasmtime, ctx, &bin, &Guest::default(), limits, stop)
+}
+
+#[test]
+fn instances_limited() {
+    let limits = Limits{ max_instances: Some(1), ..Default::default() };
+    let empty = r#"(module (func (export "_start")))"#;
+
+    // Instance slots are held for the duration of execution
+    let stop = StopToken::new();
+    let t = {
+        let (stop, limits) = (stop.clone(), limits.clone());
+        std::thread::spawn(move || loop {
+            // Retry where the probe below holds the slot
+            match exec(r#"(module (func (export "_start") (loop br 0)))"#, &limits, &stop) {
+                Err(e) if e.is::<LimitExceeded>() => std::thread::sleep(Duration::from_millis(1)),
+                r => break r,
+            }
+        })
+    };
+
+    let start = Instant::now();
+    let e = loop {
+        match exec(empty, &limits, &StopToken::new()) {
+            Err(e) => break e,
+            Ok(_) if start.elapsed() < Duration::from_secs(5) => std::thread::sleep(Duration::from_millis(10)),
+            Ok(_) => panic!("instance limit not applied"),
+        }
+    };
+    assert_eq!(e.downcast_ref::<LimitExceeded>(), Some(&LimitExceeded::Instances(1)));
+
+    // And released once the instance exits
+    stop.stop();
+    assert!(t.join().unwrap().unwrap_err().is::<Stopped>());
+
+    exec(empty, &limits, &StopToken::new()).unwrap();
+}
+
+#[test]
+fn instances_counted_per_limit_set() {
+    let limits = Limits{ max_instances: Some(1), ..Default::default() };
+    let other = Limits{ max_instances: Some(1), ..Default::default() };
+
+    // Instances are counted against the limits these were acquired with,
+    // shared between clones
+    let _guard = limits.acquire().unwrap();
+    assert_eq!(limits.clone().acquire().err(), Some(LimitExceeded::Instances(1)));
+    assert_eq!(limits.instances.count(), 1);
+
+    exec(r#"(module (func (export "_start")))"#, &other, &StopToken::new()).unwrap();
+}
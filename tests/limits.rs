@@ -0,0 +1,160 @@
+//! Checks guest execution limits and preemptive stopping, executing WAT
+//! guests against empty mock scripts
+
+#![cfg(all(feature="hal-mock", feature="rt-wasmtime"))]
+
+use std::time::{Duration, Instant};
+
+use wasm_embedded_rt::{Engine, StopToken, server::Stopped, opts::{Limits, Runtime}, limits::LimitExceeded, module::Guest, mock::MockCtx};
+
+/// Guest spinning indefinitely without calling into the host
+const SPIN: &str = r#"(module (func (export "_start") (loop br 0)))"#;
+
+/// Execute a WAT guest under the provided runtime and limits
+fn exec(runtime: Runtime, wat: &str, limits: &Limits, stop: &StopToken) -> anyhow::Result<()> {
+    let bin = wat::parse_str(wat)?;
+    let ctx = MockCtx::builder().build();
+
+    wasm_embedded_rt::run_limited(&runtime, ctx, &bin, &Guest::default(), limits, stop)
+}
+
+#[test]
+fn limits_fuel() {
+    let limits = Limits{ fuel: Some(10_000), ..Default::default() };
+
+    let e = exec(Runtime::Wasmtime, SPIN, &limits, &StopToken::new()).unwrap_err();
+    assert_eq!(e.downcast_ref::<LimitExceeded>(), Some(&LimitExceeded::Fuel(10_000)));
+
+    // Guests completing within their budget are unaffected
+    exec(Runtime::Wasmtime, r#"(module (func (export "_start")))"#, &limits, &StopToken::new()).unwrap();
+}
+
+#[test]
+fn limits_timeout() {
+    let t = Duration::from_millis(100);
+    let limits = Limits{ timeout: Some(t), ..Default::default() };
+
+    let start = Instant::now();
+    let e = exec(Runtime::Wasmtime, SPIN, &limits, &StopToken::new()).unwrap_err();
+
+    assert_eq!(e.downcast_ref::<LimitExceeded>(), Some(&LimitExceeded::Timeout(t)));
+    assert!(start.elapsed() < Duration::from_secs(5), "guest not interrupted: {:?}", start.elapsed());
+}
+
+#[test]
+fn limits_stop_interrupts_guest() {
+    let stop = StopToken::new();
+
+    let s = stop.clone();
+    let t = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        s.stop();
+    });
+
+    let e = exec(Runtime::Wasmtime, SPIN, &Limits::default(), &stop).unwrap_err();
+    assert!(e.is::<Stopped>(), "unexpected error: {:?}", e);
+
+    t.join().unwrap();
+
+    // Guests started with a stopped token are interrupted immediately
+    let e = exec(Runtime::Wasmtime, SPIN, &Limits::default(), &stop).unwrap_err();
+    assert!(e.is::<Stopped>(), "unexpected error: {:?}", e);
+}
+
+#[test]
+fn limits_memory() {
+    let limits = Limits{ max_memory_pages: Some(2), ..Default::default() };
+
+    // Guests requiring more memory than permitted fail to load
+    let wat = r#"(module (memory 3) (func (export "_start")))"#;
+    let e = exec(Runtime::Wasmtime, wat, &limits, &StopToken::new()).unwrap_err();
+    assert_eq!(e.downcast_ref::<LimitExceeded>(), Some(&LimitExceeded::Memory{ requested: 3, limit: 2 }));
+
+    // Growth within the limit succeeds, beyond it fails in the guest
+    let wat = r#"(module
+      (memory 1)
+      (func (export "_start")
+        (if (i32.ne (memory.grow (i32.const 1)) (i32.const 1)) (then unreachable))
+        (if (i32.ne (memory.grow (i32.const 1)) (i32.const -1)) (then unreachable)))
+    )"#;
+    exec(Runtime::Wasmtime, wat, &limits, &StopToken::new()).unwrap();
+}
+
+#[test]
+fn limits_tables() {
+    let limits = Limits{ max_table_elements: Some(4), ..Default::default() };
+
+    let wat = r#"(module (table 5 funcref) (func (export "_start")))"#;
+    let e = exec(Runtime::Wasmtime, wat, &limits, &StopToken::new()).unwrap_err();
+    assert_eq!(e.downcast_ref::<LimitExceeded>(), Some(&LimitExceeded::Table{ requested: 5, limit: 4 }));
+
+    let wat = r#"(module
+      (table 2 funcref)
+      (func (export "_start")
+        (if (i32.ne (table.grow (ref.null func) (i32.const 2)) (i32.const 2)) (then unreachable))
+        (if (i32.ne (table.grow (ref.null func) (i32.const 1)) (i32.const -1)) (then unreachable)))
+    )"#;
+    exec(Runtime::Wasmtime, wat, &limits, &StopToken::new()).unwrap();
+}
+
+#[test]
+fn limits_instrument_clamps_imports() {
+    use parity_wasm::elements::{Module, External};
+
+    let limits = Limits{ max_memory_pages: Some(4), max_table_elements: Some(8), ..Default::default() };
+
+    let wat = r#"(module
+      (import "env" "memory" (memory 1))
+      (import "env" "table" (table 2 100 funcref))
+      (memory 1 10)
+    )"#;
+    let bin = limits.instrument(&wat::parse_str(wat).unwrap()).unwrap();
+    let m: Module = parity_wasm::deserialize_buffer(&bin).unwrap();
+
+    let imports: Vec<_> = m.import_section().unwrap().entries().iter()
+        .map(|e| match e.external() {
+            External::Memory(t) => t.limits().maximum(),
+            External::Table(t) => t.limits().maximum(),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(imports, vec![Some(4), Some(8)]);
+    assert_eq!(m.memory_section().unwrap().entries()[0].limits().maximum(), Some(4));
+
+    // Imports exceeding the limit are rejected
+    let wat = r#"(module (import "env" "memory" (memory 5)))"#;
+    let e = limits.instrument(&wat::parse_str(wat).unwrap()).unwrap_err();
+    assert_eq!(e.downcast_ref::<LimitExceeded>(), Some(&LimitExceeded::Memory{ requested: 5, limit: 4 }));
+}
+
+#[test]
+fn limits_timeout_stops_engine() {
+    // Runtimes unable to interrupt guests rely on engines refusing driver
+    // access once the stop token is stopped
+    let t = Duration::from_millis(50);
+    let limits = Limits{ timeout: Some(t), ..Default::default() };
+
+    let stop = StopToken::new();
+    let mut ctx = MockCtx::builder().build().with_stop(stop.clone());
+
+    let e = limits.timed::<()>(&stop, || loop {
+        if ctx.gpio().is_none() {
+            break Err(anyhow::anyhow!("no device"));
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }).unwrap_err();
+
+    assert_eq!(e.downcast_ref::<LimitExceeded>(), Some(&LimitExceeded::Timeout(t)));
+}
+
+#[cfg(feature="rt-wasm3")]
+#[test]
+fn limits_under_wasm3() {
+    let limits = Limits{ fuel: Some(10_000), ..Default::default() };
+    let e = exec(Runtime::Wasm3, SPIN, &limits, &StopToken::new()).unwrap_err().to_string();
+    assert!(e.contains("unsupported by the wasm3 runtime"), "unexpected error: {}", e);
+
+    // Timeouts are applied via the stop token
+    let limits = Limits{ timeout: Some(Duration::from_secs(5)), ..Default::default() };
+    exec(Runtime::Wasm3, r#"(module (func (export "_start")))"#, &limits, &StopToken::new()).unwrap();
+}
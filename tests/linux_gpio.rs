@@ -0,0 +1,18 @@
+//! Checks Linux GPIO port handling, without accessing sysfs pins
+
+#![cfg(feature="hal-linux")]
+
+use wasm_embedded_rt::{Engine, linux::LinuxCtx, opts::Peripherals};
+use wasm_embedded_spec::{Error, Gpio};
+
+#[test]
+fn linux_gpio_ports() {
+    // Permitted pins use global numbering, so other ports are rejected
+    let mut ctx = LinuxCtx::with_peripherals(Peripherals{ gpio: Some(vec![17]), ..Default::default() });
+    assert_eq!(ctx.gpio().unwrap().init(1, 17, true), Err(Error::InvalidArg));
+    assert_eq!(ctx.gpio().unwrap().init(0, 18, true), Err(Error::NoDevice));
+
+    // Unrestricted pins ignore the port (an invalid pin failing to export)
+    let mut ctx = LinuxCtx::new();
+    assert_ne!(ctx.gpio().unwrap().init(1, 100_000, true), Err(Error::InvalidArg));
+}
@@ -0,0 +1,12 @@
+//! Checks Linux PWM handle handling, without accessing sysfs channels
+
+#![cfg(feature="hal-linux")]
+
+use wasm_embedded_rt::{ext::{EngineExt, Pwm}, linux::LinuxCtx};
+use wasm_embedded_spec::Error;
+
+#[test]
+fn linux_pwm_unknown_handle() {
+    let mut ctx = LinuxCtx::new();
+    assert_eq!(ctx.pwm().unwrap().deinit(99), Err(Error::NoDevice));
+}
@@ -0,0 +1,247 @@
+//! Checks supervisor manifest parsing and validation
+
+#![cfg(feature="supervisor")]
+
+use std::path::{Path, PathBuf};
+
+use wasm_embedded_rt::opts::{Engine, StorageConfig};
+use wasm_embedded_rt::supervisor::{Manifest, RestartPolicy};
+
+/// Write a manifest for a test, returning its path
+fn manifest(name: &str, toml: &str) -> PathBuf {
+    let d = std::env::temp_dir().join(format!("wasm-embedded-manifest-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&d).unwrap();
+
+    let p = d.join("manifest.toml");
+    std::fs::write(&p, toml).unwrap();
+    p
+}
+
+/// Parse a manifest from TOML
+fn parse(toml: &str) -> Manifest {
+    toml::from_str(toml).unwrap()
+}
+
+/// Check a manifest is rejected with a message containing `reason`
+fn rejects(toml: &str, reason: &str) {
+    let e = parse(toml).validate().unwrap_err().to_string();
+    assert!(e.contains(reason), "unexpected error: {}", e);
+}
+
+#[test]
+fn manifest_load_resolves_paths() {
+    let p = manifest("load", r#"
+        [[module]]
+        name = "sensor"
+        bin = "sensor.wasm"
+        engine = "mock"
+        config = "sensor.toml"
+        trace = "sensor.jsonl"
+        args = ["--verbose"]
+        env = { LEVEL = "2" }
+        storage = { backend = "file", path = "data" }
+        max_memory_pages = 16
+        max_instances = 2
+
+        [module.restart]
+        policy = "on-failure"
+        max_restarts = 3
+    "#);
+    let dir = p.parent().unwrap();
+
+    let m = Manifest::load(&p).unwrap();
+    m.validate().unwrap();
+
+    let c = &m.modules[0];
+    assert_eq!(c.bin, dir.join("sensor.wasm"));
+    assert_eq!(c.config.as_deref(), Some(dir.join("sensor.toml").as_path()));
+    assert_eq!(c.trace.as_deref(), Some(dir.join("sensor.jsonl").as_path()));
+    assert_eq!(c.engine, Engine::Mock);
+    assert!(matches!(&c.storage, Some(StorageConfig::File{ path, .. }) if path == &dir.join("data")));
+
+    let l = c.limits().unwrap();
+    assert_eq!((l.max_memory_pages, l.max_instances), (Some(16), Some(2)));
+
+    // Restart settings not specified take defaults
+    assert_eq!(c.restart.policy, RestartPolicy::OnFailure);
+    assert_eq!(c.restart.max_restarts, Some(3));
+    assert_eq!(c.restart.backoff, 1.0);
+
+    // Guests receive the binary path as the first argument
+    let g = c.guest();
+    assert_eq!(g.args, vec![dir.join("sensor.wasm").to_string_lossy().to_string(), "--verbose".into()]);
+    assert_eq!(g.env, vec![("LEVEL".into(), "2".into())]);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn manifest_absolute_paths_retained() {
+    let p = manifest("absolute", r#"
+        [[module]]
+        name = "a"
+        bin = "/opt/a.wasm"
+    "#);
+
+    let m = Manifest::load(&p).unwrap();
+    assert_eq!(m.modules[0].bin, Path::new("/opt/a.wasm"));
+
+    let _ = std::fs::remove_dir_all(p.parent().unwrap());
+}
+
+#[test]
+fn manifest_rejects_empty_and_duplicates() {
+    rejects("", "no modules");
+
+    rejects(r#"
+        [[module]]
+        name = "a"
+        bin = "a.wasm"
+
+        [[module]]
+        name = "a"
+        bin = "b.wasm"
+    "#, "Duplicate module name: a");
+}
+
+#[test]
+fn manifest_rejects_shared_peripherals() {
+    rejects(r#"
+        [[module]]
+        name = "a"
+        bin = "a.wasm"
+        engine = "linux"
+        peripherals = { gpio = [4, 5], i2c = [], spi = [], uart = [], pwm = [], adc = [], can = [], onewire = [] }
+
+        [[module]]
+        name = "b"
+        bin = "b.wasm"
+        engine = "linux"
+        peripherals = { gpio = [5], i2c = [], spi = [], uart = [], pwm = [], adc = [], can = [], onewire = [] }
+    "#, "both use GPIO pin 5");
+}
+
+#[test]
+fn manifest_rejects_unrestricted_peripherals() {
+    // Unrestricted modules may access peripherals assigned to others
+    rejects(r#"
+        [[module]]
+        name = "a"
+        bin = "a.wasm"
+        engine = "linux"
+        peripherals = { gpio = [4], i2c = [], spi = [], uart = [], pwm = [], adc = [], can = [], onewire = [] }
+
+        [[module]]
+        name = "b"
+        bin = "b.wasm"
+        engine = "linux"
+        peripherals = { i2c = [], spi = [], uart = [], pwm = [], adc = [], can = [], onewire = [] }
+    "#, "both use unrestricted GPIO pins");
+
+    rejects(r#"
+        [[module]]
+        name = "a"
+        bin = "a.wasm"
+        engine = "linux"
+
+        [[module]]
+        name = "b"
+        bin = "b.wasm"
+        engine = "linux"
+    "#, "unrestricted");
+}
+
+#[test]
+fn manifest_accepts_partitioned_peripherals() {
+    // Modules not using a kind may leave this unrestricted where the
+    // other module claims none
+    let m = parse(r#"
+        [[module]]
+        name = "a"
+        bin = "a.wasm"
+        engine = "linux"
+        peripherals = { gpio = [4], i2c = [1], spi = [], uart = [], pwm = [], adc = [], can = [], onewire = [] }
+
+        [[module]]
+        name = "b"
+        bin = "b.wasm"
+        engine = "linux"
+        peripherals = { gpio = [5], i2c = [2], uart = [0], pwm = [], adc = [], can = [], onewire = [] }
+
+        [[module]]
+        name = "c"
+        bin = "c.wasm"
+        engine = "mock"
+    "#);
+
+    m.validate().unwrap();
+}
+
+#[test]
+fn manifest_rejects_shared_storage() {
+    rejects(r#"
+        [[module]]
+        name = "a"
+        bin = "a.wasm"
+        engine = "mock"
+        storage = { backend = "file", path = "/var/lib/wasm" }
+
+        [[module]]
+        name = "b"
+        bin = "b.wasm"
+        engine = "mock"
+        storage = { backend = "file", path = "/var/lib/wasm/b" }
+    "#, "storage directory");
+
+    rejects(r#"
+        [[module]]
+        name = "a"
+        bin = "a.wasm"
+        engine = "mock"
+        storage = { backend = "eeprom", bus = 1, size = 4096 }
+
+        [[module]]
+        name = "b"
+        bin = "b.wasm"
+        engine = "mock"
+        storage = { backend = "eeprom", bus = 1, addr = 0x50, size = 4096 }
+    "#, "storage EEPROM 1:0x50");
+}
+
+#[test]
+fn manifest_rejects_invalid_periods() {
+    rejects(r#"
+        [[module]]
+        name = "a"
+        bin = "a.wasm"
+        timeout = -1.0
+    "#, "Module a timeout");
+
+    rejects(r#"
+        [[module]]
+        name = "a"
+        bin = "a.wasm"
+        timeout = nan
+    "#, "Module a timeout");
+
+    rejects(r#"
+        [[module]]
+        name = "a"
+        bin = "a.wasm"
+        restart = { policy = "always", backoff = -0.5 }
+    "#, "Module a restart backoff");
+
+    rejects(r#"
+        [[module]]
+        name = "a"
+        bin = "a.wasm"
+        restart = { policy = "always", max_backoff = inf }
+    "#, "Module a restart max_backoff");
+
+    rejects(r#"
+        [[module]]
+        name = "a"
+        bin = "a.wasm"
+        restart = { policy = "always", backoff = 10.0, max_backoff = 5.0 }
+    "#, "exceeds max_backoff");
+}
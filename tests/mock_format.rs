@@ -0,0 +1,100 @@
+//! Checks mock recordings and divergence reports round trip through each
+//! supported format
+
+#![cfg(feature="hal-mock")]
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use wasm_embedded_rt::{Engine, EngineExt, mock::{MockCtx, MockConfig, MismatchReport, Format, Op, Kind, CanFrame, PinState, Timing}};
+use wasm_embedded_rt::ext::{Time, Filter};
+use wasm_embedded_spec::{Gpio, I2c};
+
+/// Build a mock context expecting an I2C read then a delayed GPIO write
+fn mock() -> MockCtx {
+    MockCtx::builder()
+        .expect(Kind::I2cRead{ handle: 0, addr: 0x40, data_in: vec![0x12, 0x34] })
+        .expect(Kind::DelayMs{ ms: 5 })
+        .expect(Kind::GpioSet{ handle: 1, state: PinState::High })
+            .timing(Timing{ after: Some(0), min_ms: Some(10.0), max_ms: None })
+        .build()
+}
+
+/// Execute operations against the mock, violating the GPIO timing constraint
+fn exec(ctx: &mut MockCtx) {
+    let mut buff = [0u8; 2];
+    let _ = ctx.i2c().unwrap().read(0, 0x40, &mut buff);
+    let _ = ctx.time().unwrap().delay_ms(5);
+    let _ = ctx.gpio().unwrap().set(1, PinState::High.into());
+}
+
+/// Resolve a temporary output file for a test
+fn path(name: &str, ext: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("wasm-embedded-format-{}-{}.{}", name, std::process::id(), ext))
+}
+
+#[test]
+fn mock_diff_report_formats() {
+    let mut ctx = mock();
+    let handle = ctx.handle();
+    exec(&mut ctx);
+
+    let report = handle.verify().unwrap_err();
+    assert_eq!(report.divergence.index, 2);
+    assert!(report.divergence.timing.is_some());
+
+    for ext in ["toml", "json", "yaml"] {
+        let p = path("diff", ext);
+        report.store(&p, None).unwrap();
+
+        let r: MismatchReport = Format::load(&p, None).unwrap();
+        assert_eq!(r, report, "{} report differs", ext);
+
+        let _ = std::fs::remove_file(&p);
+    }
+}
+
+#[test]
+fn mock_record_format_override() {
+    // Recordings use the requested format regardless of extension
+    let p = path("record", "out");
+
+    let mut ctx = mock();
+    ctx.record(&p, Some(Format::Json));
+    exec(&mut ctx);
+    drop(ctx);
+
+    let d = std::fs::read(&p).unwrap();
+    let c: MockConfig = Format::Json.decode(&d).unwrap();
+    assert_eq!(c.ops.len(), 3);
+    assert_eq!(c.ops[0].kind, Kind::I2cRead{ handle: 0, addr: 0x40, data_in: vec![0x12, 0x34] });
+
+    let _ = std::fs::remove_file(&p);
+}
+
+#[test]
+fn mock_can_ops_round_trip() {
+    // Nested frames and filters are serialised alongside operation results
+    let c = MockConfig{
+        ops: vec![
+            Op{ kind: Kind::CanSetFilters{ handle: 1, filters: vec![
+                Filter{ id: 0x100, mask: 0x700, extended: false },
+                Filter{ id: 0x18DA_0000, mask: 0x1FFF_0000, extended: true },
+            ]}, res: 0, timing: None },
+            Op{ kind: Kind::CanSend{ handle: 1, frame: CanFrame{
+                id: 0x18DA_10F1, extended: true, fd: true, remote: None, data: vec![1, 2, 3],
+            }}, res: 0, timing: Some(Timing{ after: None, min_ms: Some(1.0), max_ms: None }) },
+            Op{ kind: Kind::CanReceive{ handle: 1, timeout_ms: Some(100), frame: Some(CanFrame{
+                id: 0x123, extended: false, fd: false, remote: Some(2), data: vec![],
+            })}, res: 0, timing: None },
+            Op{ kind: Kind::CanReceive{ handle: 1, timeout_ms: None, frame: None }, res: 0, timing: None },
+        ],
+        storage: BTreeMap::new(),
+        expect_storage: None,
+    };
+
+    for f in [Format::Toml, Format::Json, Format::Yaml] {
+        let d = f.encode(&c).unwrap();
+        let r: MockConfig = f.decode(&d).unwrap();
+        assert_eq!(r, c, "{:?} config differs", f);
+    }
+}
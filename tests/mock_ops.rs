@@ -0,0 +1,262 @@
+//! Checks each mock driver method records a distinct and correct operation
+
+#![cfg(feature="hal-mock")]
+
+use std::collections::HashSet;
+
+use strum::IntoEnumIterator;
+
+use wasm_embedded_rt::{Engine, EngineExt, mock::{MockCtx, Kind, Method, PinState, CanFrame}};
+use wasm_embedded_rt::ext::{Adc, Can, OneWire, Pwm, Storage, Time, Watchdog, Polarity, Filter, Frame, crc8};
+use wasm_embedded_spec::{Gpio, I2c, Spi, Uart};
+
+/// Driver method recording each operation kind
+///
+/// This match is exhaustive, so operation kinds added to the mock fail to
+/// compile until mapped here (and called below). The mock engine implements
+/// every driver trait, so required methods added to these traits must in
+/// turn record a new operation kind.
+fn driver_method(m: Method) -> &'static str {
+    match m {
+        Method::I2cInit => "I2c::init",
+        Method::I2cDeinit => "I2c::deinit",
+        Method::I2cWrite => "I2c::write",
+        Method::I2cRead => "I2c::read",
+        Method::I2cWriteRead => "I2c::write_read",
+        Method::SpiInit => "Spi::init",
+        Method::SpiDeinit => "Spi::deinit",
+        Method::SpiRead => "Spi::read",
+        Method::SpiWrite => "Spi::write",
+        Method::SpiTransfer => "Spi::transfer",
+        Method::SpiTransferInplace => "Spi::transfer_inplace",
+        Method::UartInit => "Uart::init",
+        Method::UartDeinit => "Uart::deinit",
+        Method::UartWrite => "Uart::write",
+        Method::UartRead => "Uart::read",
+        Method::GpioInit => "Gpio::init",
+        Method::GpioDeinit => "Gpio::deinit",
+        Method::GpioSet => "Gpio::set",
+        Method::GpioGet => "Gpio::get",
+        Method::PwmInit => "Pwm::init",
+        Method::PwmDeinit => "Pwm::deinit",
+        Method::PwmSetPeriod => "Pwm::set_period",
+        Method::PwmSetDutyCycle => "Pwm::set_duty_cycle",
+        Method::PwmSetPolarity => "Pwm::set_polarity",
+        Method::PwmEnable => "Pwm::enable",
+        Method::AdcInit => "Adc::init",
+        Method::AdcDeinit => "Adc::deinit",
+        Method::AdcReadRaw => "Adc::read_raw",
+        Method::AdcRead => "Adc::read",
+        Method::AdcCapture => "Adc::capture",
+        Method::CanInit => "Can::init",
+        Method::CanDeinit => "Can::deinit",
+        Method::CanSetFilters => "Can::set_filters",
+        Method::CanSend => "Can::send",
+        Method::CanReceive => "Can::receive",
+        Method::OneWireInit => "OneWire::init",
+        Method::OneWireDeinit => "OneWire::deinit",
+        Method::OneWireReset => "OneWire::reset",
+        Method::OneWireSearch => "OneWire::search",
+        Method::OneWireWriteByte => "OneWire::write_byte",
+        Method::OneWireReadByte => "OneWire::read_byte",
+        Method::DelayUs => "Time::delay_us",
+        Method::DelayMs => "Time::delay_ms",
+        Method::TimeNow => "Time::time_now",
+        Method::TimerStart => "Time::timer_start",
+        Method::TimerStop => "Time::timer_stop",
+        Method::TimerWait => "Time::timer_wait",
+        Method::TimerPoll => "Time::timer_poll",
+        Method::TimerWaitAny => "Time::timer_wait_any",
+        Method::WatchdogKick => "Watchdog::kick",
+        Method::StorageGet => "Storage::get",
+        Method::StorageSet => "Storage::set",
+        Method::StorageRemove => "Storage::remove",
+    }
+}
+
+#[test]
+fn mock_ops_match_driver_methods() {
+    // Expected operations, one per driver method in call order
+    let ops = vec![
+        Kind::GpioInit{ port: 0, pin: 4, output: true },
+        Kind::GpioSet{ handle: 1, state: PinState::High },
+        Kind::GpioGet{ handle: 1, state: PinState::Low },
+        Kind::GpioDeinit{ handle: 1 },
+        Kind::I2cInit{ port: 1, baud: 100_000, sda: 2, scl: 3 },
+        Kind::I2cWrite{ handle: 2, addr: 0x40, data_out: vec![0x01] },
+        Kind::I2cRead{ handle: 2, addr: 0x40, data_in: vec![0x02, 0x03] },
+        Kind::I2cWriteRead{ handle: 2, addr: 0x40, data_out: vec![0x04], data_in: vec![0x05] },
+        Kind::I2cDeinit{ handle: 2 },
+        Kind::SpiInit{ port: 0, baud: 1_000_000, mosi: 10, miso: 9, sck: 11, cs: 8 },
+        Kind::SpiWrite{ handle: 3, data_out: vec![0x06] },
+        Kind::SpiRead{ handle: 3, data_in: vec![0x07] },
+        Kind::SpiTransfer{ handle: 3, data_out: vec![0x08], data_in: vec![0x09] },
+        Kind::SpiTransferInplace{ handle: 3, data_out: vec![0x0a], data_in: vec![0x0b] },
+        Kind::SpiDeinit{ handle: 3 },
+        Kind::UartInit{ port: 0, baud: 115_200, tx: 14, rx: 15 },
+        Kind::UartWrite{ handle: 4, flags: 0, data_out: vec![0x0c] },
+        Kind::UartRead{ handle: 4, flags: 0, data_in: vec![0x0d] },
+        Kind::UartDeinit{ handle: 4 },
+        Kind::PwmInit{ chip: 0, channel: 1 },
+        Kind::PwmSetPeriod{ handle: 5, period_ns: 20_000_000 },
+        Kind::PwmSetDutyCycle{ handle: 5, duty_ns: 1_500_000 },
+        Kind::PwmSetPolarity{ handle: 5, polarity: Polarity::Inversed },
+        Kind::PwmEnable{ handle: 5, enabled: true },
+        Kind::PwmDeinit{ handle: 5 },
+        Kind::AdcInit{ device: 0, channel: 2 },
+        Kind::AdcReadRaw{ handle: 6, value: 2048 },
+        Kind::AdcRead{ handle: 6, value: 1650.0 },
+        Kind::AdcCapture{ handle: 6, trigger: 0, samples: vec![1, 2, 3] },
+        Kind::AdcDeinit{ handle: 6 },
+        Kind::CanInit{ port: 0, fd: true },
+        Kind::CanSetFilters{ handle: 7, filters: vec![Filter{ id: 0x100, mask: 0x700, extended: false }] },
+        Kind::CanSend{ handle: 7, frame: CanFrame{ id: 0x1234, extended: true, fd: true, remote: None, data: vec![0x0e; 12] } },
+        Kind::CanReceive{ handle: 7, timeout_ms: Some(100), frame: Some(CanFrame{ id: 0x123, extended: false, fd: false, remote: None, data: vec![0x0f] }) },
+        Kind::CanDeinit{ handle: 7 },
+        Kind::OneWireInit{ bus: 1, pin: -1 },
+        Kind::OneWireReset{ handle: 9, presence: true },
+        Kind::OneWireSearch{ handle: 9, roms: vec![[0x28, 0xff, 0x64, 0x1e, 0x0f, 0x16, 0x03, 0x90]] },
+        Kind::OneWireWriteByte{ handle: 9, data: 0xcc },
+        Kind::OneWireReadByte{ handle: 9, data: 0x50 },
+        Kind::OneWireDeinit{ handle: 9 },
+        Kind::TimeNow{ us: 10_000 },
+        Kind::TimerStart{ period_us: 1_000 },
+        Kind::DelayMs{ ms: 2 },
+        Kind::TimerPoll{ handle: Some(8) },
+        Kind::DelayUs{ us: 500 },
+        Kind::TimerWait{ handle: 8, expirations: 1 },
+        Kind::TimerWaitAny{ timeout_us: Some(5_000), handle: Some(8) },
+        Kind::TimerStop{ handle: 8 },
+        Kind::WatchdogKick,
+        Kind::StorageSet{ key: "count".into(), value: vec![0x0e] },
+        Kind::StorageGet{ key: "count".into(), value: Some(vec![0x0e]) },
+        Kind::StorageRemove{ key: "count".into(), existed: true },
+    ];
+
+    let mut ctx = ops.iter().fold(MockCtx::builder(), |b, k| {
+        let res = match k {
+            Kind::GpioInit{..} => 1,
+            Kind::I2cInit{..} => 2,
+            Kind::SpiInit{..} => 3,
+            Kind::UartInit{..} => 4,
+            Kind::PwmInit{..} => 5,
+            Kind::AdcInit{..} => 6,
+            Kind::CanInit{..} => 7,
+            Kind::TimerStart{..} => 8,
+            Kind::OneWireInit{..} => 9,
+            _ => 0,
+        };
+        b.expect(k.clone()).returns(res)
+    }).build();
+    let handle = ctx.handle();
+
+    // Call each driver method
+    let gpio = ctx.gpio().unwrap();
+    let h = gpio.init(0, 4, true).unwrap();
+    gpio.set(h, PinState::High.into()).unwrap();
+    assert_eq!(gpio.get(h).unwrap(), PinState::Low.into());
+    gpio.deinit(h).unwrap();
+
+    let i2c = ctx.i2c().unwrap();
+    let h = i2c.init(1, 100_000, 2, 3).unwrap();
+    i2c.write(h, 0x40, &[0x01]).unwrap();
+    let mut buff = [0u8; 2];
+    i2c.read(h, 0x40, &mut buff).unwrap();
+    assert_eq!(buff, [0x02, 0x03]);
+    let mut buff = [0u8; 1];
+    i2c.write_read(h, 0x40, &[0x04], &mut buff).unwrap();
+    assert_eq!(buff, [0x05]);
+    i2c.deinit(h).unwrap();
+
+    let spi = ctx.spi().unwrap();
+    let h = spi.init(0, 1_000_000, 10, 9, 11, 8).unwrap();
+    spi.write(h, &[0x06]).unwrap();
+    let mut buff = [0u8; 1];
+    spi.read(h, &mut buff).unwrap();
+    assert_eq!(buff, [0x07]);
+    let mut buff = [0u8; 1];
+    spi.transfer(h, &mut buff, &[0x08]).unwrap();
+    assert_eq!(buff, [0x09]);
+    let mut buff = [0x0a];
+    spi.transfer_inplace(h, &mut buff).unwrap();
+    assert_eq!(buff, [0x0b]);
+    spi.deinit(h).unwrap();
+
+    let uart = ctx.uart().unwrap();
+    let h = uart.init(0, 115_200, 14, 15).unwrap();
+    uart.write(h, 0, &[0x0c]).unwrap();
+    let mut buff = [0u8; 1];
+    uart.read(h, 0, &mut buff).unwrap();
+    assert_eq!(buff, [0x0d]);
+    uart.deinit(h).unwrap();
+
+    let pwm = ctx.pwm().unwrap();
+    let h = pwm.init(0, 1).unwrap();
+    pwm.set_period(h, 20_000_000).unwrap();
+    pwm.set_duty_cycle(h, 1_500_000).unwrap();
+    pwm.set_polarity(h, Polarity::Inversed).unwrap();
+    pwm.enable(h, true).unwrap();
+    pwm.deinit(h).unwrap();
+
+    let adc = ctx.adc().unwrap();
+    let h = adc.init(0, 2).unwrap();
+    assert_eq!(adc.read_raw(h).unwrap(), 2048);
+    assert_eq!(adc.read(h).unwrap(), 1650.0);
+    let mut samples = [0i32; 3];
+    adc.capture(h, 0, &mut samples).unwrap();
+    assert_eq!(samples, [1, 2, 3]);
+    adc.deinit(h).unwrap();
+
+    let can = ctx.can().unwrap();
+    let h = can.init(0, true).unwrap();
+    can.set_filters(h, &[Filter{ id: 0x100, mask: 0x700, extended: false }]).unwrap();
+    can.send(h, &Frame::new(0x1234, true, true, &[0x0e; 12]).unwrap()).unwrap();
+    assert_eq!(can.receive(h, Some(100)).unwrap(), Frame::new(0x123, false, false, &[0x0f]));
+    can.deinit(h).unwrap();
+
+    let onewire = ctx.onewire().unwrap();
+    let h = onewire.init(1, -1).unwrap();
+    assert!(onewire.reset(h).unwrap());
+    let mut roms = [[0u8; 8]; 2];
+    assert_eq!(onewire.search(h, &mut roms).unwrap(), 1);
+    assert_eq!(crc8(&roms[0]), 0);
+    onewire.write_byte(h, 0xcc).unwrap();
+    assert_eq!(onewire.read_byte(h).unwrap(), 0x50);
+    onewire.deinit(h).unwrap();
+
+    // Scripted reads move the virtual clock forward, as do delays
+    let time = ctx.time().unwrap();
+    assert_eq!(time.now_us().unwrap(), 10_000);
+    let h = time.timer_start(1_000).unwrap();
+    time.delay_ms(2).unwrap();
+    assert_eq!(time.timer_poll().unwrap(), Some(h));
+    time.delay_us(500).unwrap();
+    assert_eq!(time.timer_wait(h).unwrap(), 1);
+    assert_eq!(time.timer_wait_any(Some(5_000)).unwrap(), Some(h));
+    time.timer_stop(h).unwrap();
+
+    ctx.watchdog().unwrap().kick().unwrap();
+
+    let storage = ctx.storage().unwrap();
+    storage.set("count", &[0x0e]).unwrap();
+    let mut buff = [0u8; 2];
+    assert_eq!(storage.get("count", &mut buff).unwrap(), Some(1));
+    assert_eq!(buff, [0x0e, 0x00]);
+    assert!(storage.remove("count").unwrap());
+
+    // Check operations were recorded as expected
+    let report = match handle.verify() {
+        Ok(r) => r,
+        Err(e) => panic!("{}", e),
+    };
+    assert_eq!(report.ops, ops);
+
+    // Check every operation kind is covered by exactly one driver method
+    let methods: Vec<Method> = report.ops.iter().map(Method::from).collect();
+    let distinct: HashSet<Method> = methods.iter().cloned().collect();
+    assert_eq!(distinct.len(), methods.len(), "driver methods share mock operations");
+    assert_eq!(distinct, Method::iter().collect(), "mock operations not covered by driver methods");
+
+    let names: HashSet<&str> = Method::iter().map(driver_method).collect();
+    assert_eq!(names.len(), Method::iter().count(), "operation kinds share driver methods");
+}
@@ -0,0 +1,88 @@
+//! Checks the mock storage driver against initial and expected contents
+
+#![cfg(feature="hal-mock")]
+
+use wasm_embedded_rt::{EngineExt, mock::{MockCtx, MockConfig, Kind}};
+use wasm_embedded_rt::ext::Storage;
+
+/// Script incrementing a stored boot counter and clearing a calibration value
+const SCRIPT: &str = r#"
+[storage]
+boots = [ 1 ]
+cal = [ 0x10, 0x20 ]
+
+[expect_storage]
+boots = [ 2 ]
+
+[[ops]]
+kind = "storage_get"
+key = "boots"
+value = [ 1 ]
+res = 0
+
+[[ops]]
+kind = "storage_set"
+key = "boots"
+value = [ 2 ]
+res = 0
+
+[[ops]]
+kind = "storage_remove"
+key = "cal"
+existed = true
+res = 0
+"#;
+
+/// Execute the scripted operations against a context
+fn exec(ctx: &mut MockCtx, remove: &str) {
+    let storage = ctx.storage().unwrap();
+
+    let mut buff = [0u8; 1];
+    let _ = storage.get("boots", &mut buff);
+    let _ = storage.set("boots", &[buff[0] + 1]);
+    let _ = storage.remove(remove);
+}
+
+#[test]
+fn mock_storage_matches() {
+    let config: MockConfig = toml::from_str(SCRIPT).unwrap();
+    assert_eq!(config.storage.len(), 2);
+
+    let mut ctx = MockCtx::from_config(config);
+    let handle = ctx.handle();
+
+    exec(&mut ctx, "cal");
+
+    if let Err(e) = handle.verify() {
+        panic!("{}", e);
+    }
+
+    // Recordings replay from the initial contents
+    let recording = handle.recording();
+    assert_eq!(recording.storage.get("boots"), Some(&vec![1]));
+    assert_eq!(recording.expect_storage, None);
+}
+
+#[test]
+fn mock_storage_mismatch() {
+    let mut ctx = MockCtx::builder()
+        .storage("boots", &[1])
+        .storage("cal", &[0x10, 0x20])
+        .expect_storage("boots", &[2])
+        .expect(Kind::StorageGet{ key: "boots".into(), value: Some(vec![1]) })
+        .expect(Kind::StorageSet{ key: "boots".into(), value: vec![2] })
+        .expect(Kind::StorageRemove{ key: "other".into(), existed: false })
+        .build();
+    let handle = ctx.handle();
+
+    // Operations match, but the calibration value is left in place
+    exec(&mut ctx, "other");
+
+    let e = handle.verify().unwrap_err();
+    assert_eq!(e.matched, 3);
+
+    let s = e.divergence.storage.unwrap();
+    assert_eq!(s.key, "cal");
+    assert_eq!(s.expected, None);
+    assert_eq!(s.actual, Some(vec![0x10, 0x20]));
+}
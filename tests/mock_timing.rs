@@ -0,0 +1,76 @@
+//! Checks mock timing constraints against the virtual clock
+
+#![cfg(feature="hal-mock")]
+
+use wasm_embedded_rt::{Engine, EngineExt, mock::{MockCtx, MockConfig, Kind, PinState, Timing}};
+use wasm_embedded_rt::ext::Time;
+use wasm_embedded_spec::{Gpio, I2c};
+
+/// Script writing to an I2C device then setting a GPIO, which is expected
+/// at least `min_ms` after the write
+const SCRIPT: &str = r#"
+[[ops]]
+kind = "i2c_write"
+handle = 0
+addr = 0x40
+data_out = [ 1 ]
+res = 0
+
+[[ops]]
+kind = "delay_ms"
+ms = 10
+res = 0
+
+[[ops]]
+kind = "gpio_set"
+handle = 1
+state = "high"
+res = 0
+timing = { after = 0, min_ms = 10.0 }
+"#;
+
+/// Execute the scripted operations against a context
+fn exec(ctx: &mut MockCtx, delay_ms: u32) {
+    let _ = ctx.i2c().unwrap().write(0, 0x40, &[1]);
+    let _ = ctx.time().unwrap().delay_ms(delay_ms);
+    let _ = ctx.gpio().unwrap().set(1, PinState::High.into());
+}
+
+#[test]
+fn mock_timing_met() {
+    let config: MockConfig = toml::from_str(SCRIPT).unwrap();
+    assert_eq!(config.ops[2].timing, Some(Timing{ after: Some(0), min_ms: Some(10.0), max_ms: None }));
+
+    let mut ctx = MockCtx::from_config(config);
+    let handle = ctx.handle();
+
+    let start = std::time::Instant::now();
+    exec(&mut ctx, 10);
+
+    // Delays advance the virtual clock without blocking
+    assert!(start.elapsed().as_millis() < 10);
+    assert_eq!(handle.times(), vec![0, 10_000, 10_000]);
+
+    if let Err(e) = handle.verify() {
+        panic!("{}", e);
+    }
+}
+
+#[test]
+fn mock_timing_violated() {
+    let mut ctx = MockCtx::builder()
+        .expect(Kind::I2cWrite{ handle: 0, addr: 0x40, data_out: vec![1] })
+        .expect(Kind::DelayMs{ ms: 5 })
+        .expect(Kind::GpioSet{ handle: 1, state: PinState::High })
+        .timing(Timing{ after: Some(0), min_ms: Some(10.0), max_ms: None })
+        .build();
+    let handle = ctx.handle();
+
+    exec(&mut ctx, 5);
+
+    let e = handle.verify().unwrap_err();
+    assert_eq!(e.divergence.index, 2);
+
+    let t = e.divergence.timing.unwrap();
+    assert_eq!(t.elapsed_ms, Some(5.0));
+}
@@ -0,0 +1,172 @@
+//! Checks guest module rewriting, executing rewritten WAT guests under
+//! wasmtime
+
+#![cfg(all(feature="hal-mock", feature="rt-wasmtime"))]
+
+use parity_wasm::elements::Module;
+
+use wasm_embedded_rt::{opts::Runtime, module::{self, Guest, Exit}, mock::MockCtx};
+
+/// Execute a guest binary under wasmtime against an empty mock script
+fn exec(bin: &[u8]) -> anyhow::Result<()> {
+    wasm_embedded_rt::run(&Runtime::Wasmtime, MockCtx::builder().build(), bin)
+}
+
+/// Apply `--invoke` to a WAT guest
+fn invoke(wat: &str, name: &str) -> Vec<u8> {
+    let guest = Guest{ invoke: Some(name.into()), ..Default::default() };
+    guest.apply(&wat::parse_str(wat).unwrap()).unwrap()
+}
+
+/// Parse a module including its name section
+fn names(bin: &[u8]) -> Module {
+    parity_wasm::deserialize_buffer::<Module>(bin).unwrap()
+        .parse_names().unwrap_or_else(|(_, m)| m)
+}
+
+#[test]
+fn module_apply_unmodified() {
+    // Modules are not rewritten without options requiring this
+    let bin = wat::parse_str(r#"(module (func (export "_start")))"#).unwrap();
+    assert_eq!(Guest::default().apply(&bin).unwrap(), bin);
+
+    let guest = Guest{ args: vec!["prog".into()], ..Default::default() };
+    assert_eq!(guest.shim_args(&bin).unwrap(), bin);
+}
+
+#[test]
+fn module_parse_features() {
+    // Sign extension and bulk memory instructions are supported when rewriting
+    let wat = r#"(module
+      (memory 1)
+      (func (export "run")
+        (drop (i32.extend8_s (i32.const 255)))
+        (memory.fill (i32.const 0) (i32.const 0) (i32.const 16)))
+    )"#;
+
+    exec(&invoke(wat, "run")).unwrap();
+
+    // SIMD modules are executed where not rewritten
+    let wat = r#"(module
+      (memory 1)
+      (func (export "_start")
+        (v128.store (i32.const 0) (v128.const i32x4 1 2 3 4)))
+    )"#;
+    let bin = wat::parse_str(wat).unwrap();
+
+    exec(&Guest::default().apply(&bin).unwrap()).unwrap();
+}
+
+#[test]
+fn module_invoke_calls_initialize() {
+    let wat = r#"(module
+      (global $init (mut i32) (i32.const 0))
+      (func (export "_initialize") (global.set $init (i32.add (global.get $init) (i32.const 1))))
+      (func (export "run") (if (i32.ne (global.get $init) (i32.const 1)) (then unreachable)))
+    )"#;
+
+    exec(&invoke(wat, "run")).unwrap();
+}
+
+#[test]
+fn module_invoke_calls_ctors() {
+    // Constructors are located by name where not exported
+    let wat = r#"(module
+      (global $init (mut i32) (i32.const 0))
+      (func $__wasm_call_ctors (global.set $init (i32.add (global.get $init) (i32.const 1))))
+      (func (export "run") (result i32)
+        (if (i32.ne (global.get $init) (i32.const 1)) (then unreachable))
+        (i32.const 5))
+    )"#;
+
+    let e = exec(&invoke(wat, "run")).unwrap_err();
+    assert_eq!(e.downcast_ref::<Exit>(), Some(&Exit(5)));
+
+    // Targets calling constructors themselves are not wrapped
+    let wat = r#"(module
+      (global $init (mut i32) (i32.const 0))
+      (func $__wasm_call_ctors (global.set $init (i32.add (global.get $init) (i32.const 1))))
+      (func (export "run")
+        (call $__wasm_call_ctors)
+        (if (i32.ne (global.get $init) (i32.const 1)) (then unreachable)))
+    )"#;
+
+    exec(&invoke(wat, "run")).unwrap();
+}
+
+#[test]
+fn module_invoke_rejects_invalid_targets() {
+    let wat = r#"(module (func (export "run") (param i32)))"#;
+    let guest = Guest{ invoke: Some("run".into()), ..Default::default() };
+
+    assert!(guest.apply(&wat::parse_str(wat).unwrap()).is_err());
+
+    let guest = Guest{ invoke: Some("missing".into()), ..Default::default() };
+    assert!(guest.apply(&wat::parse_str(wat).unwrap()).is_err());
+}
+
+#[test]
+fn module_shim_args() {
+    // Replaced imports precede others, so remaining function references are remapped
+    let wat = r#"(module
+      (type $ret_i32 (func (result i32)))
+      (import "wasi_snapshot_preview1" "args_sizes_get" (func $args_sizes_get (param i32 i32) (result i32)))
+      (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+      (import "wasi_snapshot_preview1" "args_get" (func $args_get (param i32 i32) (result i32)))
+      (import "wasi_snapshot_preview1" "environ_sizes_get" (func $environ_sizes_get (param i32 i32) (result i32)))
+      (import "wasi_snapshot_preview1" "environ_get" (func $environ_get (param i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (table 1 funcref)
+      (elem (i32.const 0) $seven)
+      (func $seven (result i32) (local $value i32)
+        (local.set $value (i32.const 7))
+        (local.get $value))
+      (func $expect (param $got i32) (param $want i32)
+        (if (i32.ne (local.get $got) (local.get $want)) (then unreachable)))
+      (func (export "_start")
+        ;; Arguments: count and buffer size, then pointers to nul-terminated strings
+        (call $expect (call $args_sizes_get (i32.const 0) (i32.const 4)) (i32.const 0))
+        (call $expect (i32.load (i32.const 0)) (i32.const 2))
+        (call $expect (i32.load (i32.const 4)) (i32.const 8))
+        (call $expect (call $args_get (i32.const 16) (i32.const 64)) (i32.const 0))
+        (call $expect (i32.load (i32.const 16)) (i32.const 64))
+        (call $expect (i32.load (i32.const 20)) (i32.const 69))
+        (call $expect (i32.load (i32.const 69)) (i32.const 0x3161))
+
+        ;; Environment, as `KEY=VALUE` strings
+        (call $expect (call $environ_sizes_get (i32.const 0) (i32.const 4)) (i32.const 0))
+        (call $expect (i32.load (i32.const 0)) (i32.const 1))
+        (call $expect (i32.load (i32.const 4)) (i32.const 4))
+        (call $expect (call $environ_get (i32.const 32) (i32.const 96)) (i32.const 0))
+        (call $expect (i32.load (i32.const 32)) (i32.const 96))
+        (call $expect (i32.load (i32.const 96)) (i32.const 0x563d4b))
+
+        ;; Direct and indirect calls resolve to the original functions
+        (call $expect (call $seven) (i32.const 7))
+        (call $expect (call_indirect (type $ret_i32) (i32.const 0)) (i32.const 7))
+
+        (call $proc_exit (i32.const 0)))
+    )"#;
+
+    let guest = Guest{
+        args: vec!["prog".into(), "a1".into()],
+        env: vec![("K".into(), "V".into())],
+        ..Default::default()
+    };
+    let bin = guest.shim_args(&wat::parse_str(wat).unwrap()).unwrap();
+
+    let imports = module::imports(&bin).unwrap();
+    assert_eq!(imports.iter().map(|i| i.name.as_str()).collect::<Vec<_>>(), vec!["proc_exit"]);
+
+    exec(&bin).unwrap();
+
+    // Function and local names follow remapped indices
+    let m = names(&bin);
+    let n = m.names_section().unwrap();
+
+    let (idx, _) = n.functions().unwrap().names().iter().find(|(_, n)| n.as_str() == "seven").unwrap();
+    assert_eq!(idx, 1);
+
+    let locals = n.locals().unwrap().local_names().get(idx).unwrap();
+    assert_eq!(locals.get(0).map(|s| s.as_str()), Some("value"));
+}
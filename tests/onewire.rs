@@ -0,0 +1,139 @@
+//! Checks 1-Wire CRC and ROM search against a simulated bus
+
+use wasm_embedded_rt::ext::{OneWireBits, Rom, SEARCH_ROM, crc8, search_rom};
+use wasm_embedded_spec::Error;
+
+/// Maxim application note 27 example ROM (family 0x02, CRC 0xA2)
+const AN27_ROM: Rom = [0x02, 0x1C, 0xB8, 0x01, 0x00, 0x00, 0x00, 0xA2];
+
+/// DS18B20 temperature sensor ROMs (family 0x28)
+const DS18B20_A: Rom = [0x28, 0xFF, 0x64, 0x1E, 0x0F, 0x16, 0x04, 0x13];
+const DS18B20_B: Rom = [0x28, 0xFF, 0x64, 0x1E, 0x0F, 0x16, 0x05, 0x4D];
+
+#[test]
+fn onewire_crc8() {
+    assert_eq!(crc8(&AN27_ROM[..7]), 0xA2);
+    assert_eq!(crc8(&DS18B20_A[..7]), 0x13);
+
+    // Valid ROM codes have a CRC of zero over all bytes
+    assert_eq!(crc8(&AN27_ROM), 0);
+    assert_eq!(crc8(&DS18B20_A), 0);
+    assert_eq!(crc8(&DS18B20_B), 0);
+    assert_ne!(crc8(&[0x28, 0xFF, 0x64, 0x1E, 0x0F, 0x16, 0x04, 0x14]), 0);
+}
+
+/// Simulated open-drain bus with devices responding to ROM search
+struct SimBus {
+    roms: Vec<Rom>,
+    /// Devices participating in the current search pass
+    active: Vec<bool>,
+    /// Command bits written since reset
+    cmd: Vec<bool>,
+    /// Current ROM bit index and read phase (bit, complement, direction)
+    bit: usize,
+    phase: usize,
+    resets: usize,
+}
+
+impl SimBus {
+    fn new(roms: &[Rom]) -> Self {
+        Self{ roms: roms.to_vec(), active: vec![], cmd: vec![], bit: 0, phase: 0, resets: 0 }
+    }
+
+    fn rom_bit(rom: &Rom, i: usize) -> bool {
+        rom[i / 8] & (1 << (i % 8)) != 0
+    }
+
+    fn searching(&self) -> bool {
+        self.cmd.len() >= 8
+    }
+}
+
+impl OneWireBits for SimBus {
+    fn reset(&mut self) -> Result<bool, Error> {
+        self.resets += 1;
+        self.active = vec![true; self.roms.len()];
+        self.cmd.clear();
+        self.bit = 0;
+        self.phase = 0;
+        Ok(!self.roms.is_empty())
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), Error> {
+        if !self.searching() {
+            self.cmd.push(bit);
+            if self.cmd.len() == 8 {
+                let c = self.cmd.iter().enumerate().fold(0u8, |c, (i, b)| c | ((*b as u8) << i));
+                assert_eq!(c, SEARCH_ROM, "unexpected command");
+            }
+            return Ok(());
+        }
+
+        // Devices not matching the selected direction leave the search
+        assert_eq!(self.phase, 2, "direction written out of sequence");
+        for (a, r) in self.active.iter_mut().zip(&self.roms) {
+            *a &= Self::rom_bit(r, self.bit) == bit;
+        }
+        self.bit += 1;
+        self.phase = 0;
+
+        Ok(())
+    }
+
+    fn read_bit(&mut self) -> Result<bool, Error> {
+        assert!(self.searching() && self.phase < 2, "read out of sequence");
+
+        // Active devices write their bit then its complement, the line
+        // is low where any device pulls this low (wired-AND)
+        let complement = self.phase == 1;
+        let v = self.roms.iter().zip(&self.active)
+            .filter(|(_, a)| **a)
+            .all(|(r, _)| Self::rom_bit(r, self.bit) != complement);
+        self.phase += 1;
+
+        Ok(v)
+    }
+}
+
+#[test]
+fn onewire_search_two_devices() {
+    let mut bus = SimBus::new(&[DS18B20_B, DS18B20_A]);
+
+    let mut roms = [[0u8; 8]; 4];
+    let n = search_rom(&mut bus, &mut roms).unwrap();
+
+    // Devices are found in ascending ROM bit order (zero branch first)
+    assert_eq!(n, 2);
+    assert_eq!(&roms[..n], &[DS18B20_A, DS18B20_B]);
+    assert_eq!(bus.resets, 2);
+}
+
+#[test]
+fn onewire_search_limited_and_empty() {
+    let mut bus = SimBus::new(&[DS18B20_A, DS18B20_B, AN27_ROM]);
+    let mut roms = [[0u8; 8]; 2];
+    assert_eq!(search_rom(&mut bus, &mut roms).unwrap(), 2);
+    assert_eq!(roms, [DS18B20_A, DS18B20_B]);
+
+    let mut bus = SimBus::new(&[]);
+    assert_eq!(search_rom(&mut bus, &mut roms).unwrap(), 0);
+}
+
+#[test]
+fn onewire_search_crc_mismatch() {
+    let mut bad = DS18B20_A;
+    bad[7] ^= 0x01;
+
+    let mut bus = SimBus::new(&[bad]);
+    let mut roms = [[0u8; 8]; 1];
+    assert!(matches!(search_rom(&mut bus, &mut roms), Err(Error::Failed)));
+}
+
+#[cfg(feature="hal-linux")]
+#[test]
+fn onewire_bitbang_requires_opt_in() {
+    use wasm_embedded_rt::{EngineExt, ext::OneWire, linux::LinuxCtx};
+
+    let mut ctx = LinuxCtx::new();
+    assert!(matches!(ctx.onewire().unwrap().init(0, 4), Err(Error::Unsupported)));
+}
@@ -0,0 +1,117 @@
+//! Checks mock run summaries render as the expected JUnit documents
+
+#![cfg(feature="hal-mock")]
+
+use std::time::Duration;
+
+use wasm_embedded_rt::mock::{Summary, Divergence, Kind, Report, PinState};
+
+/// Summary with names and messages requiring XML escaping
+fn summary() -> Summary {
+    Summary{
+        name: "apps/<blink>&\"co\".wasm".into(),
+        class: "wasmtime.mock".into(),
+        passed: true,
+        matched: 2,
+        expected: 2,
+        executed: 2,
+        divergence: None,
+        error: None,
+        time: 0.0125,
+    }
+}
+
+#[test]
+fn junit_passed() {
+    let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites tests="1" failures="0" errors="0" time="0.013">
+  <testsuite name="wasmtime.mock" tests="1" failures="0" errors="0" time="0.013">
+    <testcase name="apps/&lt;blink&gt;&amp;&quot;co&quot;.wasm" classname="wasmtime.mock" time="0.013">
+      <system-out>matched 2 of 2 operations (2 executed)</system-out>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#;
+
+    assert_eq!(summary().junit(), expected);
+}
+
+#[test]
+fn junit_mismatch() {
+    let s = Summary{
+        passed: false,
+        matched: 1,
+        expected: 3,
+        divergence: Some(Divergence{
+            index: 1,
+            expected: Some(Kind::GpioSet{ handle: 0, state: PinState::High }),
+            actual: Some(Kind::GpioSet{ handle: 0, state: PinState::Low }),
+            timing: None,
+            storage: None,
+        }),
+        ..summary()
+    };
+
+    let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites tests="1" failures="1" errors="0" time="0.013">
+  <testsuite name="wasmtime.mock" tests="1" failures="1" errors="0" time="0.013">
+    <testcase name="apps/&lt;blink&gt;&amp;&quot;co&quot;.wasm" classname="wasmtime.mock" time="0.013">
+      <failure message="mismatch at operation 1 (1 of 3 matched)" type="mismatch">expected: Some(GpioSet { handle: 0, state: High })
+actual: Some(GpioSet { handle: 0, state: Low })</failure>
+      <system-out>matched 1 of 3 operations (2 executed)</system-out>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#;
+
+    assert_eq!(s.junit(), expected);
+}
+
+#[test]
+fn junit_error() {
+    let e = anyhow::anyhow!("guest trapped: 'unreachable' <at> \"main\" & exited");
+    let s = Summary::failed("app.wasm", "wasm3.mock", &e, Duration::from_millis(2));
+    assert!(!s.passed);
+
+    let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites tests="1" failures="0" errors="1" time="0.002">
+  <testsuite name="wasm3.mock" tests="1" failures="0" errors="1" time="0.002">
+    <testcase name="app.wasm" classname="wasm3.mock" time="0.002">
+      <error message="guest trapped: &apos;unreachable&apos; &lt;at&gt; &quot;main&quot; &amp; exited" type="runtime"/>
+      <system-out>matched 0 of 0 operations (0 executed)</system-out>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#;
+
+    assert_eq!(s.junit(), expected);
+}
+
+#[test]
+fn summary_from_report() {
+    let report = Ok(Report{ matched: 2, ops: vec![Kind::DelayMs{ ms: 1 }, Kind::DelayMs{ ms: 2 }] });
+    let s = Summary::new("app.wasm", "wasmtime.mock", &report, None, Duration::from_millis(5));
+
+    assert!(s.passed);
+    assert_eq!((s.matched, s.expected, s.executed), (2, 2, 2));
+    assert_eq!(s.time, 0.005);
+}
+
+#[test]
+fn junit_control_chars() {
+    let e = anyhow::anyhow!("bad\0byte\x1b[0m\tand\r\nline\x0c");
+    let s = Summary::failed("app\x08.wasm", "wasm3.mock", &e, Duration::from_millis(2));
+
+    let expected = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<testsuites tests=\"1\" failures=\"0\" errors=\"1\" time=\"0.002\">
+  <testsuite name=\"wasm3.mock\" tests=\"1\" failures=\"0\" errors=\"1\" time=\"0.002\">
+    <testcase name=\"app\u{fffd}.wasm\" classname=\"wasm3.mock\" time=\"0.002\">
+      <error message=\"bad\u{fffd}byte\u{fffd}[0m\tand\r\nline\u{fffd}\" type=\"runtime\"/>
+      <system-out>matched 0 of 0 operations (0 executed)</system-out>
+    </testcase>
+  </testsuite>
+</testsuites>
+";
+
+    assert_eq!(s.junit(), expected);
+}
@@ -0,0 +1,90 @@
+//! Checks supervisor restart policies, limits and backoff
+
+#![cfg(feature="supervisor")]
+
+use std::time::Duration;
+
+use wasm_embedded_rt::supervisor::{Restart, RestartPolicy, Restarts, Next};
+
+const OK: anyhow::Result<()> = Ok(());
+
+fn failed() -> anyhow::Result<()> {
+    Err(anyhow::anyhow!("trap"))
+}
+
+fn restart(policy: RestartPolicy) -> Restart {
+    Restart{ policy, max_restarts: None, backoff: 1.0, max_backoff: 8.0 }
+}
+
+fn secs(s: u64) -> Duration {
+    Duration::from_secs(s)
+}
+
+#[test]
+fn restart_policies() {
+    let never = restart(RestartPolicy::Never);
+    assert!(!never.restart(&OK));
+    assert!(!never.restart(&failed()));
+
+    let on_failure = restart(RestartPolicy::OnFailure);
+    assert!(!on_failure.restart(&OK));
+    assert!(on_failure.restart(&failed()));
+
+    let always = restart(RestartPolicy::Always);
+    assert!(always.restart(&OK));
+    assert!(always.restart(&failed()));
+
+    let mut r = Restarts::new(&on_failure).unwrap();
+    assert_eq!(r.next(&OK, secs(0)), Next::Exit);
+    assert_eq!(r.count(), 0);
+}
+
+#[test]
+fn restart_backoff_doubles_to_max() {
+    let mut r = Restarts::new(&restart(RestartPolicy::Always)).unwrap();
+
+    let delays: Vec<_> = (0..6).map(|_| r.next(&failed(), secs(0))).collect();
+    assert_eq!(delays, [1, 2, 4, 8, 8, 8].map(|s| Next::Restart(secs(s))));
+    assert_eq!(r.count(), 6);
+}
+
+#[test]
+fn restart_backoff_resets_after_healthy_run() {
+    let mut r = Restarts::new(&restart(RestartPolicy::Always)).unwrap();
+
+    assert_eq!(r.next(&failed(), secs(0)), Next::Restart(secs(1)));
+    assert_eq!(r.next(&failed(), secs(0)), Next::Restart(secs(2)));
+    assert_eq!(r.next(&failed(), secs(0)), Next::Restart(secs(4)));
+
+    // Modules executing for longer than the maximum backoff restart promptly
+    assert_eq!(r.next(&failed(), secs(9)), Next::Restart(secs(1)));
+    assert_eq!(r.next(&failed(), secs(0)), Next::Restart(secs(2)));
+}
+
+#[test]
+fn restart_limit() {
+    let mut c = restart(RestartPolicy::OnFailure);
+    c.max_restarts = Some(2);
+
+    let mut r = Restarts::new(&c).unwrap();
+    assert!(matches!(r.next(&failed(), secs(0)), Next::Restart(_)));
+    assert!(matches!(r.next(&failed(), secs(0)), Next::Restart(_)));
+    assert_eq!(r.next(&failed(), secs(0)), Next::Limit);
+    assert_eq!(r.count(), 2);
+
+    // Successful exits complete regardless of the limit
+    assert_eq!(r.next(&OK, secs(0)), Next::Exit);
+
+    c.max_restarts = Some(0);
+    let mut r = Restarts::new(&c).unwrap();
+    assert_eq!(r.next(&failed(), secs(0)), Next::Limit);
+}
+
+#[test]
+fn restart_rejects_invalid_delays() {
+    for (backoff, max_backoff) in [(-1.0, 8.0), (f64::NAN, 8.0), (1.0, f64::INFINITY), (1.0, 1e30), (4.0, 2.0)] {
+        let c = Restart{ backoff, max_backoff, ..restart(RestartPolicy::Always) };
+        assert!(c.validate().is_err(), "accepted backoff {} max {}", backoff, max_backoff);
+        assert!(Restarts::new(&c).is_err());
+    }
+}
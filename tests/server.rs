@@ -0,0 +1,258 @@
+//! Checks server instance replacement, stopping, watching and serving
+
+#![cfg(all(feature="hal-mock", feature="rt-wasmtime"))]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use wasm_embedded_rt::{Server, StopToken, server::ServeConfig, opts::{Config, Runtime}, mock::MockCtx};
+
+/// Token used by serve tests
+const TOKEN: &str = "secret";
+
+/// Guest spinning indefinitely without calling into the host
+const SPIN: &str = r#"(module (func (export "_start") (loop br 0)))"#;
+
+/// Create a server executing guests under wasmtime against empty mock scripts
+fn server() -> Server<MockCtx> {
+    let config = Config{ runtime: Runtime::Wasmtime, ..Default::default() };
+
+    Server::new(config, |stop| Ok(MockCtx::builder().build().with_stop(stop)))
+}
+
+#[test]
+fn server_stop_interrupts_guest() {
+    let mut s = server();
+    let bin = wat::parse_str(SPIN).unwrap();
+
+    s.exec(&bin).unwrap();
+    assert!(s.running());
+
+    // Guests not calling into the host are interrupted rather than awaited
+    let start = Instant::now();
+    s.stop().unwrap();
+
+    assert!(!s.running());
+    assert!(start.elapsed() < Duration::from_secs(5), "instance not interrupted: {:?}", start.elapsed());
+}
+
+#[test]
+fn server_exec_replaces_instance() {
+    let mut s = server();
+    let bin = wat::parse_str(SPIN).unwrap();
+
+    s.exec(&bin).unwrap();
+    s.exec(&bin).unwrap();
+    assert!(s.running());
+
+    // Invalid binaries are rejected without stopping the executing instance
+    assert!(s.exec(b"not wasm").is_err());
+    assert!(s.running());
+
+    s.stop().unwrap();
+    assert!(!s.running());
+}
+
+/// Serve on an ephemeral loopback port, returning the address and server thread
+fn serve(config: ServeConfig, shutdown: &StopToken) -> (std::net::SocketAddr, JoinHandle<anyhow::Result<()>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let shutdown = shutdown.clone();
+    let t = std::thread::spawn(move || server().serve(listener, &config, &shutdown));
+
+    (addr, t)
+}
+
+/// Send a length-prefixed field using the serve protocol
+fn field(s: &mut TcpStream, data: &[u8]) {
+    s.write_all(&(data.len() as u32).to_le_bytes()).unwrap();
+    s.write_all(data).unwrap();
+}
+
+/// Send a binary using the serve protocol, returning the server reply
+fn push(addr: std::net::SocketAddr, token: &str, bin: &[u8]) -> String {
+    let mut s = TcpStream::connect(addr).unwrap();
+    field(&mut s, token.as_bytes());
+    field(&mut s, bin);
+
+    let mut reply = String::new();
+    s.read_to_string(&mut reply).unwrap();
+    reply
+}
+
+#[test]
+fn server_serve() {
+    let shutdown = StopToken::new();
+    let (addr, t) = serve(ServeConfig::new(TOKEN), &shutdown);
+    let bin = wat::parse_str(SPIN).unwrap();
+
+    assert_eq!(push(addr, TOKEN, &bin), "ok\n");
+    assert_eq!(push(addr, TOKEN, &bin), "ok\n");
+
+    // Clients must present the configured token, rejected prior to
+    // receiving the binary
+    let mut s = TcpStream::connect(addr).unwrap();
+    field(&mut s, b"guess");
+
+    let mut reply = String::new();
+    s.read_to_string(&mut reply).unwrap();
+    assert_eq!(reply, "error: Invalid token\n");
+
+    // Shutdown stops the executing instance and returns
+    shutdown.stop();
+    t.join().unwrap().unwrap();
+}
+
+#[test]
+fn server_serve_limits() {
+    let shutdown = StopToken::new();
+    let config = ServeConfig{ max_bin_size: 1024, timeout: Duration::from_millis(200), ..ServeConfig::new(TOKEN) };
+    let (addr, t) = serve(config, &shutdown);
+
+    // Oversized binaries are rejected prior to being read
+    let mut s = TcpStream::connect(addr).unwrap();
+    field(&mut s, TOKEN.as_bytes());
+    s.write_all(&(1u32 << 30).to_le_bytes()).unwrap();
+
+    let mut reply = String::new();
+    s.read_to_string(&mut reply).unwrap();
+    assert!(reply.starts_with("error: Binary too large"), "unexpected reply: {}", reply);
+
+    // Stalled clients time out without blocking others
+    let mut stalled = TcpStream::connect(addr).unwrap();
+    field(&mut stalled, TOKEN.as_bytes());
+
+    let bin = wat::parse_str(SPIN).unwrap();
+    assert_eq!(push(addr, TOKEN, &bin), "ok\n");
+
+    let mut reply = String::new();
+    stalled.read_to_string(&mut reply).unwrap();
+    assert!(reply.starts_with("error:"), "unexpected reply: {}", reply);
+
+    shutdown.stop();
+    t.join().unwrap().unwrap();
+}
+
+#[test]
+fn server_serve_connection_limits() {
+    let shutdown = StopToken::new();
+    let config = ServeConfig{ timeout: Duration::from_millis(300), max_connections: 1, ..ServeConfig::new(TOKEN) };
+    let (addr, t) = serve(config, &shutdown);
+
+    // Clients trickling the token are dropped once the timeout elapses,
+    // rather than on each read
+    let mut slow = TcpStream::connect(addr).unwrap();
+    slow.write_all(&(TOKEN.len() as u32).to_le_bytes()).unwrap();
+
+    // Connections beyond the limit are rejected
+    let mut reply = String::new();
+    TcpStream::connect(addr).unwrap().read_to_string(&mut reply).unwrap();
+    assert_eq!(reply, "error: Too many connections\n");
+
+    let start = Instant::now();
+    for b in TOKEN.bytes().take(4) {
+        std::thread::sleep(Duration::from_millis(100));
+        if slow.write_all(&[b]).is_err() {
+            break;
+        }
+    }
+
+    let mut reply = String::new();
+    let _ = slow.read_to_string(&mut reply);
+    assert!(reply.starts_with("error:"), "unexpected reply: {}", reply);
+    assert!(start.elapsed() < Duration::from_millis(600));
+
+    // Connection slots are released once handled
+    let bin = wat::parse_str(SPIN).unwrap();
+    assert_eq!(push(addr, TOKEN, &bin), "ok\n");
+
+    shutdown.stop();
+    t.join().unwrap().unwrap();
+}
+
+#[test]
+fn server_serve_recovers_from_panics() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = StopToken::new();
+
+    // Fail the first instance by panicking while the server is locked
+    let mut count = 0;
+    let config = Config{ runtime: Runtime::Wasmtime, ..Default::default() };
+    let mut server = Server::new(config, move |stop| {
+        count += 1;
+        if count == 1 {
+            panic!("engine creation failed");
+        }
+        Ok(MockCtx::builder().build().with_stop(stop))
+    });
+
+    let s = shutdown.clone();
+    let t = std::thread::spawn(move || server.serve(listener, &ServeConfig::new(TOKEN), &s));
+
+    let bin = wat::parse_str(SPIN).unwrap();
+    assert_eq!(push(addr, TOKEN, &bin), "");
+    assert_eq!(push(addr, TOKEN, &bin), "ok\n");
+
+    shutdown.stop();
+    t.join().unwrap().unwrap();
+}
+
+#[test]
+fn server_serve_requires_token() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let e = server().serve(listener, &ServeConfig::new(""), &StopToken::new()).unwrap_err();
+    assert!(e.to_string().contains("token"), "unexpected error: {}", e);
+}
+
+#[test]
+fn server_watch() {
+    let d = std::env::temp_dir().join(format!("wasm-embedded-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&d).unwrap();
+    let p: PathBuf = d.join("guest.wasm");
+
+    // Guests record executions by writing to a file via WASI
+    let wat = |n: u8| format!(r#"(module
+      (import "wasi_snapshot_preview1" "path_open" (func $open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 16) "run-{}")
+      (func (export "_start")
+        (drop (call $open (i32.const 3) (i32.const 0) (i32.const 16) (i32.const 5) (i32.const 1) (i64.const 0) (i64.const 0) (i32.const 0) (i32.const 8)))
+        (loop br 0))
+    )"#, n);
+
+    std::fs::write(&p, wat::parse_str(wat(1)).unwrap()).unwrap();
+
+    let shutdown = StopToken::new();
+    let t = {
+        let (p, d, shutdown) = (p.clone(), d.clone(), shutdown.clone());
+        std::thread::spawn(move || {
+            let guest = wasm_embedded_rt::module::Guest{ dirs: vec![d], ..Default::default() };
+            server().with_guest(guest).watch(p, &shutdown)
+        })
+    };
+
+    // Modified binaries replace the executing instance
+    wait_for(&d.join("run-1"));
+    std::fs::write(&p, wat::parse_str(wat(2)).unwrap()).unwrap();
+    wait_for(&d.join("run-2"));
+
+    shutdown.stop();
+    t.join().unwrap().unwrap();
+
+    let _ = std::fs::remove_dir_all(&d);
+}
+
+/// Wait for a file to be created
+fn wait_for(p: &std::path::Path) {
+    let start = Instant::now();
+    while !p.exists() {
+        assert!(start.elapsed() < Duration::from_secs(10), "timeout waiting for {}", p.display());
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
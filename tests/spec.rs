@@ -0,0 +1,81 @@
+//! Checks wasmtime runtime bindings against the spec WITX
+
+#![cfg(all(feature="hal-mock", feature="rt-wasmtime"))]
+
+use std::path::PathBuf;
+
+use witx::{Document, Id, Type};
+
+use wasm_embedded_spec::Error;
+use wasm_embedded_rt::{opts::Runtime, mock::MockCtx, rt_wasmtime::{status, OK}};
+
+/// Load a spec WITX document
+fn load(name: &str) -> Document {
+    let path = PathBuf::from(env!("WASM_EMBEDDED_SPEC_ROOT")).join("witx").join(name);
+    witx::load(&[path]).unwrap()
+}
+
+/// Fetch the status of a spec `$errno` case
+fn errno(doc: &Document, name: &str) -> i32 {
+    let t = doc.typename(&Id::new("errno")).unwrap();
+    let v = match &**t.type_() {
+        Type::Variant(v) => v.clone(),
+        _ => panic!("errno is not an enum"),
+    };
+
+    v.cases.iter().position(|c| c.name.as_str() == name).unwrap() as i32
+}
+
+#[test]
+fn spec_status_matches_errno() {
+    let doc = load("common.witx");
+
+    assert_eq!(status(Ok(())), OK);
+    assert_eq!(OK, errno(&doc, "ok"));
+
+    // Listed exhaustively so new spec errors must be mapped here
+    for e in [Error::InvalidArg, Error::Failed, Error::NoDevice, Error::Unexpected, Error::Unsupported].iter().cloned() {
+        let name = match e {
+            Error::InvalidArg => "invalid_arg",
+            Error::Failed => "failed",
+            Error::NoDevice => "no_device",
+            Error::Unexpected => "unexpected",
+            Error::Unsupported => "unsupported",
+        };
+
+        assert_eq!(status(Err(e)), errno(&doc, name), "{}", name);
+    }
+}
+
+/// Build the WAT import of a spec function, following the WITX ABI
+fn import(module: &str, f: &witx::InterfaceFunc) -> String {
+    let ty = |t: &[witx::WasmType]| t.iter().map(|t| match t {
+        witx::WasmType::I32 => " i32",
+        witx::WasmType::I64 => " i64",
+        witx::WasmType::F32 => " f32",
+        witx::WasmType::F64 => " f64",
+    }).collect::<String>();
+
+    let (params, results) = f.wasm_signature();
+
+    format!(r#"(import "{}" "{}" (func (param{}) (result{})))"#, module, f.name.as_str(), ty(&params), ty(&results))
+}
+
+#[test]
+fn spec_imports_link() {
+    let mut imports = vec![];
+
+    for name in &["gpio.witx", "i2c.witx", "spi.witx", "uart.witx", "device.witx"] {
+        for m in load(name).modules() {
+            for f in m.funcs() {
+                imports.push(import(m.name.as_str(), &f));
+            }
+        }
+    }
+
+    // Guests importing each spec function link against the runtime
+    let wat = format!(r#"(module {} (memory (export "memory") 1) (func (export "_start")))"#, imports.join(" "));
+    let bin = wat::parse_str(&wat).unwrap();
+
+    wasm_embedded_rt::run(&Runtime::Wasmtime, MockCtx::builder().build(), &bin).unwrap();
+}
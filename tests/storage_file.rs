@@ -0,0 +1,63 @@
+//! Checks the file-backed storage backend
+
+#![cfg(feature="hal-linux")]
+
+use std::path::PathBuf;
+
+use wasm_embedded_rt::linux::FileStorage;
+use wasm_embedded_rt::ext::Storage;
+use wasm_embedded_spec::Error;
+
+/// Create an empty storage directory for a test
+fn dir(name: &str) -> PathBuf {
+    let d = std::env::temp_dir().join(format!("wasm-embedded-storage-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&d);
+    d
+}
+
+#[test]
+fn file_storage_persists() {
+    let d = dir("persists");
+
+    let mut s = FileStorage::open(&d, 64).unwrap();
+    s.set("cal.offset", &[1, 2, 3, 4]).unwrap();
+    s.set("cal.offset", &[5, 6, 7]).unwrap();
+    drop(s);
+
+    // Interrupted writes are discarded on open
+    std::fs::write(d.join(".cal.offset.tmp"), [0xff]).unwrap();
+
+    let mut s = FileStorage::open(&d, 64).unwrap();
+    let mut buff = [0u8; 2];
+    assert_eq!(s.get("cal.offset", &mut buff).unwrap(), Some(3));
+    assert_eq!(buff, [5, 6]);
+    assert!(!d.join(".cal.offset.tmp").exists());
+
+    assert!(s.remove("cal.offset").unwrap());
+    assert!(!s.remove("cal.offset").unwrap());
+    assert_eq!(s.get("cal.offset", &mut buff).unwrap(), None);
+
+    let _ = std::fs::remove_dir_all(&d);
+}
+
+#[test]
+fn file_storage_limits() {
+    let d = dir("limits");
+
+    let mut s = FileStorage::open(&d, 8).unwrap();
+
+    // Keys may not escape the storage directory
+    assert!(matches!(s.set("../escape", &[0]), Err(Error::InvalidArg)));
+    assert!(matches!(s.set(".hidden", &[0]), Err(Error::InvalidArg)));
+
+    // Replacing a value only counts the difference against the quota
+    s.set("a", &[0; 6]).unwrap();
+    assert!(matches!(s.set("b", &[0; 3]), Err(Error::Failed)));
+    s.set("a", &[0; 4]).unwrap();
+    s.set("b", &[0; 4]).unwrap();
+
+    let mut buff = [];
+    assert_eq!(s.get("b", &mut buff).unwrap(), Some(4));
+
+    let _ = std::fs::remove_dir_all(&d);
+}
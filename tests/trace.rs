@@ -0,0 +1,137 @@
+//! Checks peripheral operations are traced through to the wrapped engine
+
+#![cfg(all(feature="hal-mock", feature="trace"))]
+
+use std::sync::{Arc, Mutex};
+
+use wasm_embedded_rt::{EngineExt, mock::{MockCtx, Kind, PinState}};
+use wasm_embedded_rt::ext::Time;
+use wasm_embedded_rt::trace::{Traced, Span, Peripheral, FileSink};
+use wasm_embedded_spec::{Gpio, I2c};
+
+/// Build a mock context expecting an I2C transaction and GPIO write
+fn mock() -> MockCtx {
+    MockCtx::builder()
+        .expect(Kind::I2cInit{ port: 1, baud: 100_000, sda: 2, scl: 3 }).returns(4)
+        .expect(Kind::I2cWriteRead{ handle: 4, addr: 0x40, data_out: vec![0x01], data_in: vec![0x02, 0x03] })
+        .expect(Kind::DelayMs{ ms: 1 })
+        .expect(Kind::GpioSet{ handle: 0, state: PinState::High })
+        .build()
+}
+
+/// Execute the expected operations, failing the final GPIO write
+fn exec(ctx: &mut impl EngineExt) {
+    let i2c = ctx.i2c().unwrap();
+    let h = i2c.init(1, 100_000, 2, 3).unwrap();
+    let mut buff = [0u8; 2];
+    i2c.write_read(h, 0x40, &[0x01], &mut buff).unwrap();
+    assert_eq!(buff, [0x02, 0x03]);
+
+    // Extended peripherals are forwarded untraced
+    ctx.time().unwrap().delay_ms(1).unwrap();
+
+    assert!(ctx.gpio().unwrap().set(0, PinState::Low.into()).is_err());
+}
+
+#[test]
+fn trace_records_spans() {
+    let ctx = mock();
+    let handle = ctx.handle();
+
+    let spans = Arc::new(Mutex::new(Vec::new()));
+    let mut traced = Traced::new(ctx, spans.clone());
+
+    exec(&mut traced);
+
+    let spans = spans.lock().unwrap().clone();
+    assert_eq!(spans.len(), 3);
+
+    assert_eq!((spans[0].peripheral, spans[0].op.as_str(), spans[0].handle), (Peripheral::I2c, "init", Some(4)));
+    assert_eq!(spans[0].args["baud"], 100_000);
+
+    assert_eq!((spans[1].op.as_str(), spans[1].handle), ("write_read", Some(4)));
+    assert_eq!((spans[1].bytes_out, spans[1].bytes_in), (1, 2));
+    assert_eq!(spans[1].args["addr"], 0x40);
+    assert_eq!(spans[1].error, None);
+
+    assert_eq!((spans[2].peripheral, spans[2].op.as_str()), (Peripheral::Gpio, "set"));
+    assert_eq!(spans[2].args["state"], false);
+    assert!(spans[2].error.is_some());
+
+    assert!(spans.windows(2).all(|w| w[1].seq == w[0].seq + 1 && w[1].start_us >= w[0].start_us));
+
+    // Operations reach the wrapped engine
+    let e = handle.verify().unwrap_err();
+    assert_eq!(e.divergence.index, 3);
+}
+
+#[test]
+fn trace_file_sink() {
+    let path = std::env::temp_dir().join(format!("wasm-embedded-trace-{}.jsonl", std::process::id()));
+
+    let mut traced = Traced::new(mock(), FileSink::create(&path).unwrap());
+    exec(&mut traced);
+
+    // Spans are flushed as written, so traces are readable while executing
+    let d = std::fs::read_to_string(&path).unwrap();
+    let spans: Vec<Span> = d.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(spans.iter().map(|s| s.op.as_str()).collect::<Vec<_>>(), vec!["init", "write_read", "set"]);
+
+    drop(traced);
+    let _ = std::fs::remove_file(&path);
+}
+
+/// Subscriber collecting recorded span handles
+#[cfg(feature="tracing")]
+struct Handles(Arc<Mutex<Vec<i64>>>);
+
+#[cfg(feature="tracing")]
+impl tracing::field::Visit for &Handles {
+    fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
+        if field.name() == "handle" {
+            self.0.lock().unwrap().push(value);
+        }
+    }
+
+    fn record_debug(&mut self, _field: &tracing::field::Field, _value: &dyn std::fmt::Debug) {}
+}
+
+#[cfg(feature="tracing")]
+impl tracing::Subscriber for Handles {
+    fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        span.record(&mut &*self);
+        tracing::span::Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+        values.record(&mut &*self);
+    }
+
+    fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+    fn event(&self, _event: &tracing::Event<'_>) {}
+
+    fn enter(&self, _span: &tracing::span::Id) {}
+
+    fn exit(&self, _span: &tracing::span::Id) {}
+}
+
+#[cfg(feature="tracing")]
+#[test]
+fn trace_spans_record_init_handle() {
+    let handles = Arc::new(Mutex::new(Vec::new()));
+
+    tracing::subscriber::with_default(Handles(handles.clone()), || {
+        let mut traced = Traced::new(mock(), Arc::new(Mutex::new(Vec::new())));
+        exec(&mut traced);
+    });
+
+    // Handles returned by `init` are recorded on completion
+    let handles = handles.lock().unwrap().clone();
+    assert_eq!(handles.first(), Some(&4));
+    assert!(handles.contains(&0));
+}
@@ -0,0 +1,68 @@
+//! WASI conformance, executing the `wasi_hello` app through the runtime
+//! binary and checking its output
+
+#![cfg(all(feature="rt", feature="hal-mock"))]
+
+use std::{path::PathBuf, process::Command};
+
+/// Resolve a test app resource
+fn app_path(ext: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/apps/wasi_hello")
+        .with_extension(ext)
+}
+
+/// Write the app binary to a temporary directory, which is also used as
+/// a preopened directory
+fn app_dir(name: &str) -> PathBuf {
+    let d = std::env::temp_dir().join(format!("wasm-embedded-wasi-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&d);
+    std::fs::create_dir_all(&d).unwrap();
+
+    std::fs::write(d.join("app.wasm"), wat::parse_file(app_path("wat")).unwrap()).unwrap();
+
+    d
+}
+
+#[cfg(feature="rt-wasmtime")]
+#[test]
+fn wasi_hello() {
+    let d = app_dir("hello");
+
+    let out = Command::new(env!("CARGO_BIN_EXE_wasm-embedded-rt"))
+        .args(["--engine", "mock", "--runtime", "wasmtime", "--log-level", "off"])
+        .arg("--config").arg(app_path("toml"))
+        .args(["--env", "BOARD=rpi", "--env", "EMPTY="])
+        .arg("--dir").arg(&d)
+        .arg(d.join("app.wasm"))
+        .args(["--", "one", "two three"])
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(out.status.success(), "app failed: {:?}\n{}", out.status, stderr);
+
+    let expected = format!(
+        "hello from wasi\narg: one\narg: two three\nenv: BOARD=rpi\nenv: EMPTY=\nclock ok\nrandom ok\ndir: {}\n",
+        d.display(),
+    );
+    assert_eq!(stdout, expected);
+    assert!(stderr.contains("hello stderr"), "unexpected stderr: {}", stderr);
+
+    let _ = std::fs::remove_dir_all(&d);
+}
+
+#[cfg(feature="rt-wasm3")]
+#[test]
+fn wasi_rejected_under_wasm3() {
+    use wasm_embedded_rt::{opts::Runtime, mock::MockCtx};
+
+    // Arguments and environment are shimmed, other WASI imports are rejected prior to loading
+    let bin = wat::parse_file(app_path("wat")).unwrap();
+    let ctx = MockCtx::load(app_path("toml").to_str().unwrap()).unwrap();
+
+    let e = wasm_embedded_rt::run(&Runtime::Wasm3, ctx, &bin).unwrap_err().to_string();
+    assert!(e.contains("fd_write") && e.contains("clock_time_get"), "unexpected error: {}", e);
+    assert!(!e.contains("args_get"), "unexpected error: {}", e);
+}
@@ -0,0 +1,51 @@
+//! Watchdog service tests using the software backend
+
+#![cfg(feature="std")]
+
+use std::time::Duration;
+
+use wasm_embedded_rt::{opts::WatchdogMode, watchdog::{Watchdog, SoftwareWatchdog}};
+
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+#[test]
+fn watchdog_host_petted_until_failure() {
+    let sw = SoftwareWatchdog::new(TIMEOUT);
+    let mut w = Watchdog::new(sw.clone(), WatchdogMode::Host, TIMEOUT / 4);
+
+    std::thread::sleep(TIMEOUT * 3);
+    assert!(!sw.expired(), "watchdog expired while guest healthy");
+
+    w.fail();
+    std::thread::sleep(TIMEOUT * 3);
+    assert!(sw.expired(), "watchdog not expired following guest failure");
+}
+
+#[test]
+fn watchdog_guest_kicks() {
+    let sw = SoftwareWatchdog::new(TIMEOUT);
+    let w = Watchdog::new(sw.clone(), WatchdogMode::Guest, TIMEOUT / 4);
+    let h = w.handle();
+
+    for _ in 0..10 {
+        h.kick();
+        std::thread::sleep(TIMEOUT / 4);
+    }
+    assert!(!sw.expired(), "watchdog expired while guest kicking");
+
+    // Hung guest stops kicking
+    std::thread::sleep(TIMEOUT * 3);
+    assert!(sw.expired(), "watchdog not expired without kicks");
+}
+
+#[test]
+fn watchdog_close_disarms() {
+    let sw = SoftwareWatchdog::new(TIMEOUT);
+    let w = Watchdog::new(sw.clone(), WatchdogMode::Host, TIMEOUT / 4);
+
+    std::thread::sleep(TIMEOUT);
+    w.close().unwrap();
+
+    std::thread::sleep(TIMEOUT * 3);
+    assert!(!sw.expired(), "watchdog expired after clean close");
+}